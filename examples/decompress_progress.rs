@@ -1,33 +1,32 @@
-use std::{fs::File, io::Write, path::PathBuf};
+use sevenz_rust::{ExtractOptions, Progress, ProgressObserver, SevenZArchiveEntry};
 
-fn main() {
-    let mut sz = sevenz_rust::SevenZReader::open("examples/data/sample.7z", "pass".into()).unwrap();
-    let total_size: u64 = sz
-        .archive()
-        .files
-        .iter()
-        .filter(|e| e.has_stream())
-        .map(|e| e.size())
-        .sum();
-    let mut uncompressed_size = 0;
-    let dest = PathBuf::from("examples/data/sample");
-    sz.for_each_entries(|entry, reader| {
-        let mut buf = [0u8; 1024];
-        let path = dest.join(entry.name());
-        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
-        let mut file = File::create(path).unwrap();
-        loop {
-            let read_size = reader.read(&mut buf)?;
-            if read_size == 0 {
-                break Ok(true);
-            }
-            file.write_all(&buf[..read_size])?;
-            uncompressed_size += read_size;
+struct Printer;
+
+impl ProgressObserver for Printer {
+    fn on_entry_start(&mut self, entry: &SevenZArchiveEntry, _progress: &Progress) {
+        println!("extracting {}", entry.name());
+    }
+
+    fn on_progress(&mut self, progress: &Progress) {
+        if let Some(total) = progress.total_bytes.filter(|&total| total > 0) {
             println!(
                 "progress:{:.2}%",
-                (uncompressed_size as f64 / total_size as f64) * 100f64
+                (progress.processed_bytes as f64 / total as f64) * 100f64
             );
         }
-    })
+    }
+}
+
+fn main() {
+    let options = ExtractOptions {
+        password: "pass".into(),
+        ..Default::default()
+    };
+    sevenz_rust::decompress_file_with_progress(
+        "examples/data/sample.7z",
+        "examples/data/sample",
+        options,
+        Printer,
+    )
     .unwrap();
 }
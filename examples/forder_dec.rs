@@ -28,7 +28,7 @@ fn main() {
             .for_each_entries(&mut |entry, reader| {
                 if entry.name() == my_file_name {
                     //only extract the file we want
-                    let dest = dest.join(entry.name());
+                    let dest = sevenz_rust::safe_entry_path(&dest, entry.name())?;
                     sevenz_rust::default_entry_extract_fn(entry, reader, &dest)?;
                 } else {
                     //skip other files
@@ -28,7 +28,7 @@ fn main() {
             let dest = PathBuf::from("examples/data/sample_mt/");
             forder_dec
                 .for_each_entries(&mut |entry, reader| {
-                    let dest = dest.join(entry.name());
+                    let dest = sevenz_rust::safe_entry_path(&dest, entry.name())?;
                     sevenz_rust::default_entry_extract_fn(entry, reader, &dest)?;
                     Ok(true)
                 })
@@ -0,0 +1,16 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use sevenz_rust::{Archive, ReadLimits};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = ReadLimits {
+        max_header_size: 16 * 1024 * 1024,
+        max_files: 1024 * 1024,
+        max_folders: 1024 * 1024,
+        ..Default::default()
+    };
+    let _ = Archive::read_with_limits(&mut Cursor::new(data), data.len() as u64, &[], limits);
+});
@@ -0,0 +1,50 @@
+#![no_main]
+
+//! Feeds the input as the next header of an otherwise valid archive, so the fuzzer
+//! doesn't have to get the start header and its checksums right.
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use sevenz_rust::{Archive, ReadLimits};
+
+const SIGNATURE: [u8; 6] = [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C];
+
+fuzz_target!(|data: &[u8]| {
+    let crc = crc32(data);
+    let mut start_header = Vec::with_capacity(20);
+    start_header.extend_from_slice(&0u64.to_le_bytes());
+    start_header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    start_header.extend_from_slice(&crc.to_le_bytes());
+
+    let mut archive = Vec::with_capacity(32 + data.len());
+    archive.extend_from_slice(&SIGNATURE);
+    archive.extend_from_slice(&[0, 4]);
+    archive.extend_from_slice(&crc32(&start_header).to_le_bytes());
+    archive.extend_from_slice(&start_header);
+    archive.extend_from_slice(data);
+
+    let limits = ReadLimits {
+        max_header_size: 16 * 1024 * 1024,
+        max_files: 1024 * 1024,
+        max_folders: 1024 * 1024,
+        ..Default::default()
+    };
+    let len = archive.len() as u64;
+    let _ = Archive::read_with_limits(&mut Cursor::new(archive), len, &[], limits);
+});
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -116,7 +116,12 @@ impl<R: Read + Seek> Seek for Aes256Sha256Decoder<R> {
 
 fn get_aes_key(properties: &[u8], password: &[u8]) -> Result<([u8; 32], [u8; 16]), crate::Error> {
     if properties.len() < 2 {
-        return Err(crate::Error::other("AES256 properties too shart"));
+        return Err(crate::Error::header(
+            crate::HeaderError::InvalidCoderProperties {
+                method: "AES256SHA256",
+                reason: "too short",
+            },
+        ));
     }
     let b0 = properties[0];
     let num_cycles_power = b0 & 63;
@@ -124,7 +129,12 @@ fn get_aes_key(properties: &[u8], password: &[u8]) -> Result<([u8; 32], [u8; 16]
     let iv_size = ((b0 >> 6 & 1) + (b1 & 15)) as usize;
     let salt_size = ((b0 >> 7 & 1) + (b1 >> 4)) as usize;
     if 2 + salt_size + iv_size > properties.len() {
-        return Err(crate::Error::other("Salt size + IV size too long"));
+        return Err(crate::Error::header(
+            crate::HeaderError::InvalidCoderProperties {
+                method: "AES256SHA256",
+                reason: "salt and IV too long",
+            },
+        ));
     }
     let mut salt = vec![0u8; salt_size];
     salt.copy_from_slice(&properties[2..(2 + salt_size)]);
@@ -153,7 +163,9 @@ fn get_aes_key(properties: &[u8], password: &[u8]) -> Result<([u8; 32], [u8; 16]
                 }
             }
         }
-        sha.finish().as_ref().try_into()
+        sha.finish()
+            .as_ref()
+            .try_into()
             .map_err(|_| crate::Error::other("AES256 sha256 hashing failed"))?
     };
     Ok((aes_key, iv))
@@ -2,11 +2,25 @@
 use crate::{folder::*, method_options::MethodOptions};
 use bit_set::BitSet;
 use nt_time::FileTime;
-use std::{any::Any, collections::LinkedList, sync::Arc, time::SystemTime};
+use std::{
+    any::Any, borrow::Cow, collections::LinkedList, ffi::OsString, sync::Arc, time::SystemTime,
+};
 
 pub(crate) const SIGNATURE_HEADER_SIZE: u64 = 32;
 pub(crate) const SEVEN_Z_SIGNATURE: &[u8] = &[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C];
 
+pub(crate) const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+pub(crate) const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+pub(crate) const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+/// Set by p7zip and 7-Zip when the high 16 bits of the attributes hold a Unix mode.
+pub(crate) const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+pub(crate) const S_IFMT: u32 = 0o170000;
+pub(crate) const S_IFLNK: u32 = 0o120000;
+pub(crate) const S_IFDIR: u32 = 0o040000;
+/// File type of hard link entries. It isn't used by Unix, other tools extract such entries
+/// as regular files containing the link target.
+pub(crate) const S_IFHARDLINK: u32 = 0o110000;
+
 pub(crate) const K_END: u8 = 0x00;
 pub(crate) const K_HEADER: u8 = 0x01;
 pub(crate) const K_ARCHIVE_PROPERTIES: u8 = 0x02;
@@ -56,6 +70,8 @@ pub struct SubStreamsInfo {
 
 #[derive(Debug, Default, Clone)]
 pub struct SevenZArchiveEntry {
+    /// Name of the entry. Unpaired surrogates of a name read from an archive are replaced
+    /// with U+FFFD, see [`SevenZArchiveEntry::raw_name`].
     pub name: String,
     pub has_stream: bool,
     pub is_directory: bool,
@@ -75,6 +91,8 @@ pub struct SevenZArchiveEntry {
     pub compressed_size: u64,
     // pub(crate) content_methods: LinkedList<SevenZMethodConfiguration>,
     pub(crate) content_methods: Arc<Vec<SevenZMethodConfiguration>>,
+    /// UTF-16 name read from the archive, only kept if it isn't valid UTF-16.
+    pub(crate) raw_name: Option<Vec<u16>>,
 }
 
 impl SevenZArchiveEntry {
@@ -86,6 +104,56 @@ impl SevenZArchiveEntry {
         self.name.as_ref()
     }
 
+    /// Sets the name from the UTF-16 units stored in archives, keeping names which aren't
+    /// valid UTF-16, e.g. Windows filenames with unpaired surrogates.
+    pub fn set_raw_name(&mut self, raw_name: Vec<u16>) {
+        match String::from_utf16(&raw_name) {
+            Ok(name) => {
+                self.name = name;
+                self.raw_name = None;
+            }
+            Err(_) => {
+                self.name = String::from_utf16_lossy(&raw_name);
+                self.raw_name = Some(raw_name);
+            }
+        }
+    }
+
+    /// The name as UTF-16 units, as it's stored in the archive.
+    ///
+    /// Differs from the UTF-16 encoding of [`SevenZArchiveEntry::name`] only if the name read
+    /// from the archive isn't valid UTF-16 and [`SevenZArchiveEntry::name`] wasn't changed since.
+    pub fn raw_name(&self) -> Cow<'_, [u16]> {
+        match self.invalid_raw_name() {
+            Some(raw_name) => Cow::Borrowed(raw_name),
+            None => Cow::Owned(self.name.encode_utf16().collect()),
+        }
+    }
+
+    /// Whether [`SevenZArchiveEntry::name`] is a lossy conversion of the name in the archive.
+    pub fn has_lossy_name(&self) -> bool {
+        self.invalid_raw_name().is_some()
+    }
+
+    /// The name as an [`OsString`]. It's lossless on Windows, where filenames may contain
+    /// unpaired surrogates. Other platforms get the same lossy name as
+    /// [`SevenZArchiveEntry::name`].
+    pub fn os_name(&self) -> OsString {
+        #[cfg(windows)]
+        if let Some(raw_name) = self.invalid_raw_name() {
+            use std::os::windows::ffi::OsStringExt;
+            return OsString::from_wide(raw_name);
+        }
+        OsString::from(&self.name)
+    }
+
+    /// The raw name, unless [`SevenZArchiveEntry::name`] was changed after reading it.
+    fn invalid_raw_name(&self) -> Option<&[u16]> {
+        self.raw_name
+            .as_deref()
+            .filter(|raw_name| String::from_utf16_lossy(raw_name) == self.name)
+    }
+
     pub fn is_directory(&self) -> bool {
         self.is_directory
     }
@@ -118,8 +186,55 @@ impl SevenZArchiveEntry {
         self.is_anti_item
     }
 
+    /// Unix file type and permission bits stored in the high 16 bits of
+    /// [`SevenZArchiveEntry::windows_attributes`], as p7zip and 7-Zip on Unix do.
+    pub fn unix_mode(&self) -> Option<u32> {
+        (self.has_windows_attributes
+            && self.windows_attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0)
+            .then_some(self.windows_attributes >> 16)
+    }
+
+    /// Stores `mode` in the high 16 bits of the attributes, keeping the Windows attributes.
+    pub fn set_unix_mode(&mut self, mode: u32) {
+        let attributes = if self.has_windows_attributes {
+            self.windows_attributes & 0x7fff
+        } else {
+            0
+        };
+        self.windows_attributes = (mode << 16) | FILE_ATTRIBUTE_UNIX_EXTENSION | attributes;
+        self.has_windows_attributes = true;
+    }
+
+    /// Whether the entry is a hard link written by [`crate::SevenZWriter::set_store_hard_links`],
+    /// whose content is the name of an earlier entry it links to.
+    pub fn is_hard_link(&self) -> bool {
+        self.unix_mode()
+            .is_some_and(|mode| mode & S_IFMT == S_IFHARDLINK)
+    }
+
+    /// Keeps the permission bits and marks the entry as a hard link.
+    pub(crate) fn set_hard_link_mode(&mut self) {
+        let permissions = self.unix_mode().map_or(0o644, |mode| mode & 0o7777);
+        self.set_unix_mode(S_IFHARDLINK | permissions);
+    }
+
+    /// Whether the entry is a symbolic link, whose content is the link target.
+    pub fn is_symlink(&self) -> bool {
+        self.unix_mode()
+            .is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+    }
+
     pub fn from_path(path: impl AsRef<std::path::Path>, entry_name: String) -> SevenZArchiveEntry {
-        let path = path.as_ref();
+        Self::from_path_following(path.as_ref(), entry_name, false)
+    }
+
+    /// Same as [`SevenZArchiveEntry::from_path`], but describes the file a symbolic link
+    /// points to if `follow_symlinks` is set.
+    pub(crate) fn from_path_following(
+        path: &std::path::Path,
+        entry_name: String,
+        follow_symlinks: bool,
+    ) -> SevenZArchiveEntry {
         #[cfg(target_os = "windows")]
         let entry_name = {
             let mut name_bytes = entry_name.into_bytes();
@@ -130,14 +245,21 @@ impl SevenZArchiveEntry {
             }
             String::from_utf8(name_bytes).unwrap()
         };
+        // a symbolic link is stored itself, not the file it points to
+        let meta = match path.metadata() {
+            Ok(meta) if follow_symlinks => Ok(meta),
+            _ => path.symlink_metadata(),
+        };
+        let is_symlink = meta.as_ref().is_ok_and(|meta| meta.is_symlink());
         let mut entry = SevenZArchiveEntry {
             name: entry_name,
-            has_stream: path.is_file(),
-            is_directory: path.is_dir(),
+            has_stream: path.is_file() || is_symlink,
+            is_directory: path.is_dir() && !is_symlink,
             ..Default::default()
         };
 
-        if let Ok(meta) = path.metadata() {
+        if let Ok(meta) = meta {
+            entry.set_attributes(&meta);
             if let Ok(modified) = meta.modified() {
                 if let Ok(date) = modified.try_into() {
                     entry.last_modified_date = date;
@@ -159,6 +281,36 @@ impl SevenZArchiveEntry {
         }
         entry
     }
+
+    #[cfg(unix)]
+    fn set_attributes(&mut self, meta: &std::fs::Metadata) {
+        use std::os::unix::fs::MetadataExt;
+        let mode = meta.mode() & 0xffff;
+        let mut attributes = if mode & S_IFMT == S_IFDIR {
+            FILE_ATTRIBUTE_DIRECTORY
+        } else {
+            FILE_ATTRIBUTE_ARCHIVE
+        };
+        if mode & 0o200 == 0 {
+            attributes |= FILE_ATTRIBUTE_READONLY;
+        }
+        self.windows_attributes = attributes;
+        self.has_windows_attributes = true;
+        self.set_unix_mode(mode);
+    }
+
+    #[cfg(windows)]
+    fn set_attributes(&mut self, meta: &std::fs::Metadata) {
+        use std::os::windows::fs::MetadataExt;
+        self.windows_attributes = meta.file_attributes() & 0x7fff;
+        self.has_windows_attributes = true;
+        if meta.is_symlink() {
+            self.set_unix_mode(S_IFLNK | 0o777);
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn set_attributes(&mut self, _meta: &std::fs::Metadata) {}
 }
 
 #[derive(Debug, Default)]
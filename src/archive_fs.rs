@@ -0,0 +1,243 @@
+//! Read-only filesystem view over the entries of an archive.
+
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Seek},
+};
+
+use crate::{glob::Glob, Archive, Error, SevenZReader};
+
+/// Directory tree built from the slash-separated entry names of an archive.
+///
+/// Parent directories without an entry of their own are implied. Paths passed to the
+/// methods are relative to the archive root, `/` and `\` both separate components and
+/// leading or trailing separators are ignored.
+///
+/// # Examples
+/// ```no_run
+/// use std::io::Read;
+/// use sevenz_rust::*;
+/// let reader = SevenZReader::open("example.7z", Password::empty()).unwrap();
+/// let mut fs = ArchiveFs::new(reader);
+/// for entry in fs.read_dir("docs").unwrap() {
+///     println!("{} {}", entry.path(), entry.size());
+/// }
+/// let mut content = String::new();
+/// fs.open("docs/readme.txt").unwrap().read_to_string(&mut content).unwrap();
+/// ```
+pub struct ArchiveFs<R: Read + Seek> {
+    reader: SevenZReader<R>,
+    /// The root directory is the first node.
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    path: String,
+    is_dir: bool,
+    file_index: Option<usize>,
+    children: BTreeMap<String, usize>,
+}
+
+/// Metadata of a file or directory of an [`ArchiveFs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveFsEntry {
+    path: String,
+    is_dir: bool,
+    size: u64,
+    file_index: Option<usize>,
+}
+
+impl ArchiveFsEntry {
+    /// Normalized path from the archive root, empty for the root itself.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Last component of the path.
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    /// Uncompressed size in bytes, 0 for directories.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Index of the entry in [`Archive::files`], `None` for implied directories.
+    pub fn file_index(&self) -> Option<usize> {
+        self.file_index
+    }
+}
+
+impl<R: Read + Seek> ArchiveFs<R> {
+    pub fn new(reader: SevenZReader<R>) -> Self {
+        let mut nodes = vec![Node {
+            path: String::new(),
+            is_dir: true,
+            file_index: None,
+            children: BTreeMap::new(),
+        }];
+        for (file_index, file) in reader.archive().files.iter().enumerate() {
+            let components = normalize(file.name());
+            if components.is_empty() {
+                continue;
+            }
+            let mut current = 0;
+            for (depth, component) in components.iter().enumerate() {
+                let next = match nodes[current].children.get(*component) {
+                    Some(&next) => next,
+                    None => {
+                        let path = components[..=depth].join("/");
+                        nodes.push(Node {
+                            path,
+                            is_dir: true,
+                            file_index: None,
+                            children: BTreeMap::new(),
+                        });
+                        let next = nodes.len() - 1;
+                        nodes[current].children.insert(component.to_string(), next);
+                        next
+                    }
+                };
+                // an entry with children is a directory, whatever its attributes say
+                nodes[current].is_dir = true;
+                current = next;
+            }
+            let node = &mut nodes[current];
+            // later duplicates replace earlier ones, as they do on extraction
+            node.file_index = Some(file_index);
+            node.is_dir = file.is_directory() || !node.children.is_empty();
+        }
+        Self { reader, nodes }
+    }
+
+    pub fn archive(&self) -> &Archive {
+        self.reader.archive()
+    }
+
+    pub fn reader(&self) -> &SevenZReader<R> {
+        &self.reader
+    }
+
+    /// Gives access to the reader, e.g. to enable its block cache.
+    pub fn reader_mut(&mut self) -> &mut SevenZReader<R> {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> SevenZReader<R> {
+        self.reader
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    pub fn metadata(&self, path: &str) -> Result<ArchiveFsEntry, Error> {
+        self.find(path)
+            .map(|node| self.entry(node))
+            .ok_or_else(|| not_found(path))
+    }
+
+    /// Lists the children of the directory at `path`, sorted by name.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<ArchiveFsEntry>, Error> {
+        let node = self.find(path).ok_or_else(|| not_found(path))?;
+        if !self.nodes[node].is_dir {
+            return Err(Error::io_msg(
+                std::io::Error::new(ErrorKind::Other, "Not a directory"),
+                path.to_string(),
+            ));
+        }
+        Ok(self.nodes[node]
+            .children
+            .values()
+            .map(|&child| self.entry(child))
+            .collect())
+    }
+
+    /// Returns all files and directories whose path matches the glob `pattern`, in
+    /// depth-first order.
+    ///
+    /// `*` and `?` match within a path component, `[...]` matches a character class and
+    /// `**` matches any number of components.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<ArchiveFsEntry>, Error> {
+        let glob = Glob::new(pattern)?;
+        let mut matches = Vec::new();
+        let mut stack: Vec<usize> = self.nodes[0].children.values().rev().copied().collect();
+        while let Some(node) = stack.pop() {
+            if glob.matches(&self.nodes[node].path) {
+                matches.push(self.entry(node));
+            }
+            stack.extend(self.nodes[node].children.values().rev().copied());
+        }
+        Ok(matches)
+    }
+
+    /// Opens the file at `path` for reading, see [`SevenZReader::open_entry_seekable`].
+    pub fn open(&mut self, path: &str) -> Result<impl Read + Seek + '_, Error> {
+        let node = self.find(path).ok_or_else(|| not_found(path))?;
+        let node = &self.nodes[node];
+        match node.file_index {
+            Some(file_index) if !node.is_dir => self.reader.open_entry_seekable(file_index),
+            _ => Err(Error::io_msg(
+                std::io::Error::new(ErrorKind::Other, "Is a directory"),
+                path.to_string(),
+            )),
+        }
+    }
+
+    fn find(&self, path: &str) -> Option<usize> {
+        normalize(path).into_iter().try_fold(0, |node, component| {
+            self.nodes[node].children.get(component).copied()
+        })
+    }
+
+    fn entry(&self, node: usize) -> ArchiveFsEntry {
+        let node = &self.nodes[node];
+        let size = match node.file_index {
+            Some(file_index) if !node.is_dir => self.archive().files[file_index].size(),
+            _ => 0,
+        };
+        ArchiveFsEntry {
+            path: node.path.clone(),
+            is_dir: node.is_dir,
+            size,
+            file_index: node.file_index,
+        }
+    }
+}
+
+impl<R: Read + Seek> From<SevenZReader<R>> for ArchiveFs<R> {
+    fn from(reader: SevenZReader<R>) -> Self {
+        Self::new(reader)
+    }
+}
+
+/// Splits a path into components, resolving `.` and `..` without leaving the root.
+fn normalize(path: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components
+}
+
+fn not_found(path: &str) -> Error {
+    Error::io_msg(
+        std::io::Error::new(ErrorKind::NotFound, "No such file or directory"),
+        path.to_string(),
+    )
+}
@@ -3,6 +3,8 @@ use std::io::Read;
 
 use bcj2_decode::*;
 const BUF_SIZE: usize = 1 << 18;
+/// Memory used by the stream buffers of a decoder.
+pub(crate) const MEMORY_USAGE: usize = BUF_SIZE * BCJ2_NUM_STREAMS;
 
 pub struct Bcj2Coder {
     bufs: Vec<u8>,
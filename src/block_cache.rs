@@ -0,0 +1,117 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+use crate::reader::seek_position;
+
+/// Decoded blocks kept in memory, evicted in least-recently-used order once
+/// their total size exceeds the byte budget.
+#[derive(Debug, Default)]
+pub(crate) struct BlockCache {
+    budget: usize,
+    used: usize,
+    blocks: HashMap<usize, Arc<Vec<u8>>>,
+    /// Folder indexes, least recently used first.
+    lru: VecDeque<usize>,
+}
+
+impl BlockCache {
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a block of `size` bytes fits in the budget at all.
+    pub(crate) fn accepts(&self, size: u64) -> bool {
+        size <= self.budget as u64
+    }
+
+    pub(crate) fn get(&mut self, folder_index: usize) -> Option<Arc<Vec<u8>>> {
+        let block = self.blocks.get(&folder_index)?.clone();
+        self.touch(folder_index);
+        Some(block)
+    }
+
+    pub(crate) fn insert(&mut self, folder_index: usize, block: Arc<Vec<u8>>) {
+        if !self.accepts(block.len() as u64) {
+            return;
+        }
+        if let Some(old) = self.blocks.remove(&folder_index) {
+            self.used -= old.len();
+            self.lru.retain(|&i| i != folder_index);
+        }
+        while self.used + block.len() > self.budget {
+            match self.lru.pop_front() {
+                Some(evicted) => {
+                    if let Some(old) = self.blocks.remove(&evicted) {
+                        self.used -= old.len();
+                    }
+                }
+                None => break,
+            }
+        }
+        self.used += block.len();
+        self.blocks.insert(folder_index, block);
+        self.lru.push_back(folder_index);
+    }
+
+    fn touch(&mut self, folder_index: usize) {
+        if let Some(pos) = self.lru.iter().position(|&i| i == folder_index) {
+            self.lru.remove(pos);
+        }
+        self.lru.push_back(folder_index);
+    }
+}
+
+/// Reads a range of a cached block.
+pub(crate) struct CachedEntryReader {
+    block: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+    /// Position relative to `start`, may lie past the end after a seek.
+    pos: u64,
+}
+
+impl CachedEntryReader {
+    /// Reads the `size` bytes at `offset`, failing if they extend past the end of `block`.
+    pub(crate) fn new(block: Arc<Vec<u8>>, offset: u64, size: u64) -> std::io::Result<Self> {
+        match offset.checked_add(size) {
+            Some(end) if end <= block.len() as u64 => Ok(Self {
+                block,
+                start: offset as usize,
+                end: end as usize,
+                pos: 0,
+            }),
+            _ => Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "block ended before the entry",
+            )),
+        }
+    }
+}
+
+impl Read for CachedEntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = (self.end - self.start) as u64;
+        if self.pos >= size {
+            return Ok(0);
+        }
+        let from = self.start + self.pos as usize;
+        let n = buf.len().min(self.end - from);
+        buf[..n].copy_from_slice(&self.block[from..from + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for CachedEntryReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let size = (self.end - self.start) as u64;
+        self.pos = seek_position(self.pos, size, pos)?;
+        Ok(self.pos)
+    }
+}
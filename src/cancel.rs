@@ -0,0 +1,90 @@
+//! Cooperative cancellation of long-running operations.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::Error;
+
+/// A flag that stops compression or extraction with [`Error::Cancelled`] once it's set.
+///
+/// Clones share the flag, so one clone can be handed to the operation and another kept to
+/// cancel it, e.g. from another thread. The flag is checked between entries and between the
+/// chunks of data of an entry.
+///
+/// # Examples
+/// ```no_run
+/// use sevenz_rust::*;
+/// let token = CancellationToken::new();
+/// let mut sz = SevenZReader::open("example.7z", Password::empty()).unwrap();
+/// sz.set_cancellation_token(token.clone());
+/// let canceller = std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(10));
+///     token.cancel();
+/// });
+/// match sz.for_each_entries(|_, reader| {
+///     std::io::copy(reader, &mut std::io::sink())?;
+///     Ok(true)
+/// }) {
+///     Err(Error::Cancelled) => println!("cancelled"),
+///     result => result.unwrap(),
+/// }
+/// canceller.join().unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations checking this token or one of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Error::Cancelled`] if the token was cancelled.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Tokens are equal if they are clones of each other.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// Fails reads with [`Error::Cancelled`] once the token is cancelled.
+pub(crate) struct CancellableReader<R> {
+    inner: R,
+    token: CancellationToken,
+}
+
+impl<R> CancellableReader<R> {
+    pub(crate) fn new(inner: R, token: CancellationToken) -> Self {
+        Self { inner, token }
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.token
+            .check()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        self.inner.read(buf)
+    }
+}
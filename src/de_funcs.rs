@@ -1,6 +1,8 @@
-use crate::{password::Password, Error, *};
+use crate::{dest_dir::DestDir, password::Password, Error, *};
+use std::collections::HashMap;
 use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 ///
 /// decompress a 7z file
 /// # Example
@@ -30,7 +32,7 @@ pub fn decompress_file_with_extract_fn(
 /// decompress a source reader to [dest] path
 #[inline]
 pub fn decompress<R: Read + Seek>(src_reader: R, dest: impl AsRef<Path>) -> Result<(), Error> {
-    decompress_with_extract_fn(src_reader, dest, default_entry_extract_fn)
+    decompress_with_options(src_reader, dest, ExtractOptions::default())
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -40,7 +42,51 @@ pub fn decompress_with_extract_fn<R: Read + Seek>(
     dest: impl AsRef<Path>,
     extract_fn: impl FnMut(&SevenZArchiveEntry, &mut dyn Read, &PathBuf) -> Result<bool, Error>,
 ) -> Result<(), Error> {
-    decompress_impl(src_reader, dest, Password::empty(), extract_fn)
+    decompress_impl(
+        src_reader,
+        dest,
+        extract_fn_options(Password::empty()),
+        None,
+        None,
+        Some(extract_fn),
+    )
+}
+
+/// decompress a 7z file, failing with [`Error::ExtractLimitExceeded`] before anything is
+/// extracted if the archive exceeds `limits`
+/// # Example
+/// ```no_run
+/// use sevenz_rust::ExtractLimits;
+/// let limits = ExtractLimits {
+///     max_total_size: Some(1024 * 1024 * 1024),
+///     max_ratio: Some(1000),
+///     ..Default::default()
+/// };
+/// sevenz_rust::decompress_file_with_limits("sample.7z", "sample", limits).expect("complete");
+/// ```
+#[inline]
+pub fn decompress_file_with_limits(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    limits: ExtractLimits,
+) -> Result<(), Error> {
+    let file = std::fs::File::open(src_path.as_ref())
+        .map_err(|e| Error::file_open(e, src_path.as_ref().to_string_lossy().to_string()))?;
+    decompress_with_limits(file, dest, limits)
+}
+
+/// decompress a source reader to [dest] path within `limits`
+#[inline]
+pub fn decompress_with_limits<R: Read + Seek>(
+    src_reader: R,
+    dest: impl AsRef<Path>,
+    limits: ExtractLimits,
+) -> Result<(), Error> {
+    let options = ExtractOptions {
+        limits,
+        ..Default::default()
+    };
+    decompress_impl(src_reader, dest, options, None, None, BUILT_IN)
 }
 
 #[cfg(all(feature = "aes256", not(target_arch = "wasm32")))]
@@ -68,7 +114,11 @@ pub fn decompress_with_password<R: Read + Seek>(
     dest: impl AsRef<Path>,
     password: Password,
 ) -> Result<(), Error> {
-    decompress_impl(src_reader, dest, password, default_entry_extract_fn)
+    let options = ExtractOptions {
+        password,
+        ..Default::default()
+    };
+    decompress_impl(src_reader, dest, options, None, None, BUILT_IN)
 }
 
 #[cfg(all(feature = "aes256", not(target_arch = "wasm32")))]
@@ -78,34 +128,817 @@ pub fn decompress_with_extract_fn_and_password<R: Read + Seek>(
     password: Password,
     extract_fn: impl FnMut(&SevenZArchiveEntry, &mut dyn Read, &PathBuf) -> Result<bool, Error>,
 ) -> Result<(), Error> {
-    decompress_impl(src_reader, dest, password, extract_fn)
+    decompress_impl(
+        src_reader,
+        dest,
+        extract_fn_options(password),
+        None,
+        None,
+        Some(extract_fn),
+    )
+}
+
+/// What to do when the file an entry is extracted to already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing file, even if it is read-only.
+    #[default]
+    Always,
+    /// Keep the existing file and skip the entry.
+    Skip,
+    /// Keep the existing file and extract the entry next to it as `name_1.ext`, `name_2.ext`...
+    Rename,
+    /// Replace the existing file only if the entry was modified after it. Entries without a
+    /// modification date are skipped.
+    IfNewer,
+}
+
+/// How entry paths map to paths below the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathMode {
+    /// Recreate the directories of the entry paths, like `7z x`.
+    #[default]
+    Full,
+    /// Extract all files directly into the destination, like `7z e`.
+    /// Directory entries are skipped.
+    Flat,
+}
+
+/// How symbolic links, entries with [`SevenZArchiveEntry::is_symlink`], are extracted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Create links whose target stays inside the destination and fail with
+    /// [`Error::UnsafeEntryPath`] for others. Targets may only have `..` components before
+    /// the others, so they can't step out through other links.
+    #[default]
+    Contained,
+    /// Create links to any target. Only use this for trusted archives.
+    Any,
+    /// Skip symbolic links.
+    Skip,
+    /// Write the link target into a regular file.
+    AsFile,
+}
+
+/// Which bits of [`SevenZArchiveEntry::unix_mode`] are restored on Unix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PermissionPolicy {
+    /// The read, write and execute bits, without setuid, setgid and sticky.
+    #[default]
+    Basic,
+    /// All permission bits. Only use this for trusted archives.
+    Full,
+    /// None, files keep the default permissions.
+    Ignore,
+}
+
+/// How hard links, entries with [`SevenZArchiveEntry::is_hard_link`], are extracted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HardLinkPolicy {
+    /// Link to the file extracted for the entry the link names.
+    #[default]
+    Create,
+    /// Copy the file extracted for the entry the link names.
+    Copy,
+    /// Write the name of the linked entry into a regular file.
+    AsFile,
+}
+
+/// Options of [`decompress_with_options`] and [`decompress_file_with_options`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractOptions {
+    pub overwrite: OverwritePolicy,
+    pub path_mode: PathMode,
+    pub symlinks: SymlinkPolicy,
+    pub hard_links: HardLinkPolicy,
+    pub permissions: PermissionPolicy,
+    pub limits: ExtractLimits,
+    /// Selects the entries to extract, see [`SevenZReader::set_entry_filter`].
+    pub filter: EntryFilter,
+    pub password: Password,
+    /// Stops the extraction with [`Error::Cancelled`] once the token is cancelled, removing
+    /// the partially extracted file, see [`SevenZReader::set_cancellation_token`].
+    pub cancellation: Option<CancellationToken>,
+    /// Keeps the partially extracted files of entries failing to decode with
+    /// [`decompress_recovering`], like `7z -kb`. They are removed by default.
+    pub keep_broken: bool,
+    /// Receives the failures to restore times and permissions, e.g. on filesystems without
+    /// Unix permissions. They don't stop the extraction. Without a collector the first one is
+    /// returned once all entries are extracted.
+    pub metadata_errors: Option<MetadataErrors>,
+}
+
+/// Collects the failures to restore the metadata of extracted entries, see
+/// [`ExtractOptions::metadata_errors`].
+///
+/// Clones share the collected errors, so one clone can be handed to the extraction and
+/// another kept to read them afterwards. [`Error::entry`] names the entry of each error.
+///
+/// # Examples
+/// ```no_run
+/// use sevenz_rust::*;
+/// let errors = MetadataErrors::new();
+/// let options = ExtractOptions {
+///     metadata_errors: Some(errors.clone()),
+///     ..Default::default()
+/// };
+/// decompress_file_with_options("sample.7z", "sample", options).expect("complete");
+/// for error in errors.take() {
+///     eprintln!("warning: {error}");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MetadataErrors(Arc<Mutex<Vec<Error>>>);
+
+impl MetadataErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the errors collected so far.
+    pub fn take(&self) -> Vec<Error> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn push(&self, error: Error) {
+        self.lock().push(error);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Error>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PartialEq for MetadataErrors {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// decompress a 7z file with `options`
+/// # Example
+/// ```no_run
+/// use sevenz_rust::{ExtractOptions, OverwritePolicy, PathMode};
+/// let options = ExtractOptions {
+///     overwrite: OverwritePolicy::Skip,
+///     path_mode: PathMode::Flat,
+///     ..Default::default()
+/// };
+/// sevenz_rust::decompress_file_with_options("sample.7z", "sample", options).expect("complete");
+/// ```
+#[inline]
+pub fn decompress_file_with_options(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+) -> Result<(), Error> {
+    let file = std::fs::File::open(src_path.as_ref())
+        .map_err(|e| Error::file_open(e, src_path.as_ref().to_string_lossy().to_string()))?;
+    decompress_with_options(file, dest, options)
+}
+
+/// decompress a source reader to [dest] path with `options`
+#[inline]
+pub fn decompress_with_options<R: Read + Seek>(
+    src_reader: R,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+) -> Result<(), Error> {
+    decompress_impl(src_reader, dest, options, None, None, BUILT_IN)
+}
+
+/// decompress a 7z file with `options`, reporting the progress to `observer`
+/// # Example
+/// ```no_run
+/// use sevenz_rust::{ExtractOptions, Progress, ProgressObserver};
+/// struct Printer;
+/// impl ProgressObserver for Printer {
+///     fn on_progress(&mut self, progress: &Progress) {
+///         println!("{}/{:?}", progress.processed_bytes, progress.total_bytes);
+///     }
+/// }
+/// sevenz_rust::decompress_file_with_progress("sample.7z", "sample", ExtractOptions::default(), Printer)
+///     .expect("complete");
+/// ```
+#[inline]
+pub fn decompress_file_with_progress(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+    observer: impl ProgressObserver + Send + 'static,
+) -> Result<(), Error> {
+    let file = std::fs::File::open(src_path.as_ref())
+        .map_err(|e| Error::file_open(e, src_path.as_ref().to_string_lossy().to_string()))?;
+    decompress_with_progress(file, dest, options, observer)
+}
+
+/// decompress a source reader to [dest] path with `options`, reporting the progress to `observer`
+#[inline]
+pub fn decompress_with_progress<R: Read + Seek>(
+    src_reader: R,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+    observer: impl ProgressObserver + Send + 'static,
+) -> Result<(), Error> {
+    decompress_impl(
+        src_reader,
+        dest,
+        options,
+        Some(Box::new(observer)),
+        None,
+        BUILT_IN,
+    )
+}
+
+/// decompress a 7z file with `options`, extracting every intact entry even if others fail to
+/// decode, see [`SevenZReader::for_each_entries_recovering`]. Returns which entries failed.
+/// # Example
+/// ```no_run
+/// let report = sevenz_rust::decompress_file_recovering("damaged.7z", "damaged", Default::default())
+///     .expect("complete");
+/// for (index, status) in report.failures() {
+///     println!("entry {index}: {status:?}");
+/// }
+/// ```
+#[inline]
+pub fn decompress_file_recovering(
+    src_path: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+) -> Result<TestReport, Error> {
+    let file = std::fs::File::open(src_path.as_ref())
+        .map_err(|e| Error::file_open(e, src_path.as_ref().to_string_lossy().to_string()))?;
+    decompress_recovering(file, dest, options)
 }
 
+/// decompress a source reader to [dest] path with `options`, extracting every intact entry
+/// even if others fail to decode. Returns which entries failed.
+#[inline]
+pub fn decompress_recovering<R: Read + Seek>(
+    src_reader: R,
+    dest: impl AsRef<Path>,
+    options: ExtractOptions,
+) -> Result<TestReport, Error> {
+    let mut report = TestReport::default();
+    decompress_impl(src_reader, dest, options, None, Some(&mut report), BUILT_IN)?;
+    Ok(report)
+}
+
+/// Custom extract functions get all entries and handle links and permissions themselves.
+fn extract_fn_options(password: Password) -> ExtractOptions {
+    ExtractOptions {
+        symlinks: SymlinkPolicy::AsFile,
+        hard_links: HardLinkPolicy::AsFile,
+        permissions: PermissionPolicy::Ignore,
+        password,
+        ..Default::default()
+    }
+}
+
+type ExtractFn = fn(&SevenZArchiveEntry, &mut dyn Read, &PathBuf) -> Result<bool, Error>;
+
+/// Extracts the entries with [`DestDir`] instead of an extract function.
+const BUILT_IN: Option<ExtractFn> = None;
+
+/// Extracts the entries of `src_reader` below `dest`.
+///
+/// Without an `extract_fn` the entries are created relative to opened directories of the
+/// destination, which symbolic links can't redirect. An `extract_fn` writes to paths, those
+/// are only checked for symbolic links before the entry is extracted.
 #[cfg(not(target_arch = "wasm32"))]
 fn decompress_impl<R: Read + Seek>(
     mut src_reader: R,
     dest: impl AsRef<Path>,
-    password: Password,
-    mut extract_fn: impl FnMut(&SevenZArchiveEntry, &mut dyn Read, &PathBuf) -> Result<bool, Error>,
+    options: ExtractOptions,
+    progress: Option<Box<dyn ProgressObserver + Send>>,
+    report: Option<&mut TestReport>,
+    mut extract_fn: Option<
+        impl FnMut(&SevenZArchiveEntry, &mut dyn Read, &PathBuf) -> Result<bool, Error>,
+    >,
 ) -> Result<(), Error> {
     use std::io::SeekFrom;
 
     let pos = src_reader.stream_position().map_err(Error::io)?;
     let len = src_reader.seek(SeekFrom::End(0)).map_err(Error::io)?;
     src_reader.seek(SeekFrom::Start(pos)).map_err(Error::io)?;
-    let mut seven = SevenZReader::new(src_reader, len, password)?;
-    let dest = PathBuf::from(dest.as_ref());
-    if !dest.exists() {
-        std::fs::create_dir_all(&dest).map_err(Error::io)?;
+    let mut seven = SevenZReader::new(src_reader, len, options.password)?;
+    seven
+        .set_extract_limits(options.limits)
+        .set_entry_filter(options.filter);
+    if let Some(progress) = progress {
+        seven.set_progress_observer(progress);
+    }
+    if let Some(token) = options.cancellation {
+        seven.set_cancellation_token(token);
+    }
+    let dest = DestDir::open(dest.as_ref()).map_err(Error::io)?;
+    let metadata_errors = options.metadata_errors.clone().unwrap_or_default();
+    let report_metadata = |result: std::io::Result<()>, entry_name: &str, path: &Path| {
+        if let Err(e) = result {
+            metadata_errors.push(metadata_error(e, path).with_entry(entry_name));
+        }
+    };
+    let mut directories = Vec::new();
+    // where the files were extracted to below `dest`, for hard links to them
+    let mut extracted_files = HashMap::new();
+    let recovering = report.is_some();
+    let extract = |entry: &SevenZArchiveEntry, reader: &mut dyn Read| {
+        let mut path = safe_entry_path(Path::new(""), entry.name())?;
+        if options.path_mode == PathMode::Flat {
+            match path.file_name() {
+                Some(name) if !entry.is_directory() => path = PathBuf::from(name),
+                _ => return skip_entry(reader),
+            }
+        }
+        if entry.is_directory() && path.as_os_str().is_empty() {
+            return skip_entry(reader);
+        }
+        dest.check(&path)
+            .map_err(|e| dest_error(e, entry, &dest.root().join(&path)))?;
+        if !entry.is_directory() {
+            match apply_overwrite_policy(&dest, path, entry, options.overwrite)? {
+                Some(unused) => path = unused,
+                None => return skip_entry(reader),
+            }
+        }
+        let full_path = dest.root().join(&path);
+        let Some(extract_fn) = extract_fn.as_mut() else {
+            if entry.is_directory() {
+                dest.create_dir_all(&path)
+                    .map_err(|e| dest_error(e, entry, &full_path))?;
+                directories.push((
+                    path,
+                    entry.name().to_string(),
+                    EntryTimes::of(entry),
+                    entry.unix_mode(),
+                ));
+                return Ok(true);
+            }
+            if entry.is_symlink() {
+                match options.symlinks {
+                    SymlinkPolicy::AsFile => {}
+                    SymlinkPolicy::Skip => return skip_entry(reader),
+                    policy => {
+                        extract_symlink(&dest, entry, reader, &path, policy)?;
+                        return Ok(true);
+                    }
+                }
+            }
+            if entry.is_hard_link() && options.hard_links != HardLinkPolicy::AsFile {
+                let target_name = read_link_target(entry, reader)?;
+                let target = match extracted_files.get(&target_name) {
+                    Some(target) => PathBuf::clone(target),
+                    None => safe_entry_path(Path::new(""), &target_name)?,
+                };
+                extract_hard_link(&dest, entry, &target, &path, options.hard_links)?;
+                return Ok(true);
+            }
+            let file = match extract_file(&dest, entry, reader, &path) {
+                Err(e) if (recovering && !options.keep_broken) || matches!(e, Error::Cancelled) => {
+                    let _ = dest.remove_file(&path);
+                    return Err(e);
+                }
+                result => result?,
+            };
+            report_metadata(
+                restore_file_metadata(&file, entry, options.permissions),
+                entry.name(),
+                &full_path,
+            );
+            extracted_files.insert(entry.name().to_string(), path);
+            return Ok(true);
+        };
+        // the extract function may replace a read-only file, which stays read-only if it doesn't
+        // write it
+        let read_only = match full_path.symlink_metadata() {
+            Ok(meta) if meta.is_file() && meta.permissions().readonly() => {
+                make_writable(&full_path, meta.permissions())
+                    .and_then(|_| full_path.symlink_metadata())
+                    .map(|writable| (meta.permissions(), writable))
+                    .map(Some)
+                    .map_err(|e| Error::file_open(e, full_path.to_string_lossy().to_string()))?
+            }
+            _ => None,
+        };
+        let result = extract_fn(entry, reader, &full_path);
+        if let Some((permissions, writable)) = read_only {
+            if is_unchanged(&full_path, &writable) {
+                let _ = std::fs::set_permissions(&full_path, permissions);
+            }
+        }
+        let result = match result {
+            Err(Error::Cancelled) if !entry.is_directory() => {
+                let _ = dest.remove_file(&path);
+                return Err(Error::Cancelled);
+            }
+            Err(e) if recovering && !options.keep_broken && !entry.is_directory() => {
+                let _ = dest.remove_file(&path);
+                return Err(e);
+            }
+            result => result?,
+        };
+        if entry.is_directory() {
+            directories.push((
+                path,
+                entry.name().to_string(),
+                EntryTimes::of(entry),
+                entry.unix_mode(),
+            ));
+        } else if full_path
+            .symlink_metadata()
+            .is_ok_and(|meta| meta.is_file())
+        {
+            extracted_files.insert(entry.name().to_string(), path);
+        }
+        Ok(result)
+    };
+    match report {
+        Some(report) => *report = seven.for_each_entries_recovering(extract)?,
+        None => seven.for_each_entries(extract)?,
+    }
+
+    // writing the contents updates the times of a directory, and permissions may forbid it,
+    // so both are restored last, children before their parents
+    directories.sort_by(|a, b| b.0.cmp(&a.0));
+    for (path, entry_name, times, mode) in directories {
+        let result = match dest.open_dir(&path) {
+            // both are attempted, the first failure is reported
+            Ok(dir) => {
+                times
+                    .restore(&dir)
+                    .and(restore_permissions(&dir, mode, options.permissions))
+            }
+            // an extract function may not have created it
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => Err(e),
+        };
+        report_metadata(result, &entry_name, &dest.root().join(&path));
+    }
+
+    if options.metadata_errors.is_none() {
+        if let Some(e) = metadata_errors.take().into_iter().next() {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Maps an error creating `path` for `entry` below the destination.
+fn dest_error(e: std::io::Error, entry: &SevenZArchiveEntry, path: &Path) -> Error {
+    if dest_dir::is_symlink_in_path(&e) {
+        return Error::UnsafeEntryPath {
+            name: entry.name().to_string(),
+            reason: "traverses a symbolic link",
+        };
+    }
+    Error::file_open(e, path.to_string_lossy().to_string())
+}
+
+/// Writes the file `entry` to `path` below `dest`.
+fn extract_file(
+    dest: &DestDir,
+    entry: &SevenZArchiveEntry,
+    reader: &mut dyn Read,
+    path: &Path,
+) -> Result<std::fs::File, Error> {
+    let full_path = dest.root().join(path);
+    let file = dest
+        .create_file(path)
+        .map_err(|e| dest_error(e, entry, &full_path))?;
+    if entry.size() == 0 {
+        return Ok(file);
+    }
+    let mut writer = std::io::BufWriter::new(file);
+    std::io::copy(reader, &mut writer).map_err(Error::io)?;
+    writer
+        .into_inner()
+        .map_err(|e| Error::io_msg(e.into_error(), full_path.to_string_lossy().to_string()))
+}
+
+/// Restores the times, the read-only attribute and the Unix mode of `entry` on `file`.
+/// All of them are attempted, the first failure is returned.
+fn restore_file_metadata(
+    file: &std::fs::File,
+    entry: &SevenZArchiveEntry,
+    policy: PermissionPolicy,
+) -> std::io::Result<()> {
+    let times = EntryTimes::of(entry).restore(file);
+    let read_only = if entry.has_windows_attributes
+        && entry.windows_attributes & FILE_ATTRIBUTE_READONLY != 0
+    {
+        file.metadata().and_then(|meta| {
+            let mut permissions = meta.permissions();
+            permissions.set_readonly(true);
+            file.set_permissions(permissions)
+        })
+    } else {
+        Ok(())
+    };
+    times
+        .and(read_only)
+        .and(restore_permissions(file, entry.unix_mode(), policy))
+}
+
+/// Longest link target that is extracted.
+const MAX_LINK_TARGET: u64 = 64 * 1024;
+
+/// Creates the symbolic link `entry` at `path` below `dest`.
+fn extract_symlink(
+    dest: &DestDir,
+    entry: &SevenZArchiveEntry,
+    reader: &mut dyn Read,
+    path: &Path,
+    policy: SymlinkPolicy,
+) -> Result<(), Error> {
+    let target = read_link_target(entry, reader)?;
+    if policy == SymlinkPolicy::Contained && !is_contained_link(path, &target) {
+        return Err(Error::UnsafeEntryPath {
+            name: entry.name().to_string(),
+            reason: "is a symbolic link pointing outside the destination",
+        });
+    }
+    dest.symlink(&target, path)
+        .map_err(|e| dest_error(e, entry, &dest.root().join(path)))
+}
+
+/// Reads the content of a link entry, the target of the link.
+fn read_link_target(entry: &SevenZArchiveEntry, reader: &mut dyn Read) -> Result<String, Error> {
+    if entry.size() > MAX_LINK_TARGET {
+        return Err(Error::UnsafeEntryPath {
+            name: entry.name().to_string(),
+            reason: "is a link with a too long target",
+        });
+    }
+    let mut target = String::new();
+    reader
+        .read_to_string(&mut target)
+        .map_err(|e| Error::io_msg(e, format!("Read link target of {}", entry.name())))?;
+    Ok(target)
+}
+
+/// Creates the hard link `entry` at `path` to the extracted file `target` below `dest`.
+fn extract_hard_link(
+    dest: &DestDir,
+    entry: &SevenZArchiveEntry,
+    target: &Path,
+    path: &Path,
+    policy: HardLinkPolicy,
+) -> Result<(), Error> {
+    let unsafe_target = || Error::UnsafeEntryPath {
+        name: entry.name().to_string(),
+        reason: "is a hard link to an unsafe target",
+    };
+    if target == path {
+        return Err(unsafe_target());
+    }
+    match dest.is_file(target) {
+        Ok(true) => {}
+        Err(e) if dest_dir::is_symlink_in_path(&e) => return Err(unsafe_target()),
+        _ => {
+            return Err(Error::io_msg(
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Hard link target not extracted",
+                ),
+                format!("{} links to {}", entry.name(), target.display()),
+            ))
+        }
+    }
+    match policy {
+        HardLinkPolicy::Copy => dest.copy(target, path),
+        _ => dest.hard_link(target, path),
+    }
+    .map_err(|e| dest_error(e, entry, &dest.root().join(path)))
+}
+
+/// Whether `target` of a link at `path` below the destination resolves to a path below it.
+///
+/// `..` components are only accepted before the others, where they step out of the real
+/// directories the link is created in. After another component they could step out of a
+/// symbolic link extracted before or after this one, e.g. `a -> d/s/..` with `d/s -> ..`.
+fn is_contained_link(path: &Path, target: &str) -> bool {
+    if target.starts_with(['/', '\\']) {
+        return false;
+    }
+    let mut depth = path
+        .parent()
+        .map_or(0, |parent| parent.components().count());
+    let mut descended = false;
+    for component in target.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." if depth == 0 || descended => return false,
+            ".." => depth -= 1,
+            _ if has_drive_prefix(component) => return false,
+            _ => descended = true,
+        }
     }
-    seven.for_each_entries(|entry, reader| {
-        let dest_path = dest.join(entry.name());
-        extract_fn(entry, reader, &dest_path)
-    })?;
+    true
+}
+
+/// Applies the Unix `mode` of an entry to `file` as far as `policy` allows.
+#[cfg(unix)]
+fn restore_permissions(
+    file: &std::fs::File,
+    mode: Option<u32>,
+    policy: PermissionPolicy,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mask = match policy {
+        PermissionPolicy::Basic => 0o777,
+        PermissionPolicy::Full => 0o7777,
+        PermissionPolicy::Ignore => return Ok(()),
+    };
+    let Some(mode) = mode else {
+        return Ok(());
+    };
+    file.set_permissions(std::fs::Permissions::from_mode(mode & mask))
+}
 
+#[cfg(not(unix))]
+fn restore_permissions(
+    _file: &std::fs::File,
+    _mode: Option<u32>,
+    _policy: PermissionPolicy,
+) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Consumes the data of a skipped entry, the entries of a solid block are read in order.
+fn skip_entry(reader: &mut dyn Read) -> Result<bool, Error> {
+    std::io::copy(reader, &mut std::io::sink()).map_err(Error::io)?;
+    Ok(true)
+}
+
+/// Returns the path below `dest` to extract `entry` to if `path` exists, or `None` to skip it.
+fn apply_overwrite_policy(
+    dest: &DestDir,
+    path: PathBuf,
+    entry: &SevenZArchiveEntry,
+    policy: OverwritePolicy,
+) -> Result<Option<PathBuf>, Error> {
+    let Ok(meta) = std::fs::symlink_metadata(dest.root().join(&path)) else {
+        return Ok(Some(path));
+    };
+    match policy {
+        OverwritePolicy::Always => {}
+        OverwritePolicy::Skip => return Ok(None),
+        OverwritePolicy::Rename => return Ok(Some(unused_path(dest, &path))),
+        OverwritePolicy::IfNewer => {
+            let newer = entry.has_last_modified_date
+                && meta.modified().map_or(true, |modified| {
+                    std::time::SystemTime::from(entry.last_modified_date) > modified
+                });
+            if !newer {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(path))
+}
+
+/// Appends `_1`, `_2`... to the file stem of `path` until nothing exists there below `dest`.
+fn unused_path(dest: &DestDir, path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|e| e.to_string_lossy());
+    (1u64..)
+        .map(|n| {
+            let name = match &extension {
+                Some(extension) => format!("{stem}_{n}.{extension}"),
+                None => format!("{stem}_{n}"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| std::fs::symlink_metadata(dest.root().join(candidate)).is_err())
+        .expect("some suffix is unused")
+}
+
+#[cfg(unix)]
+fn make_writable(path: &Path, permissions: std::fs::Permissions) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // only the owner gains write access
+    let mode = permissions.mode() | 0o200;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn make_writable(path: &Path, mut permissions: std::fs::Permissions) -> std::io::Result<()> {
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// Whether the file `path` is still the one `before` describes, neither replaced nor written.
+#[cfg(unix)]
+fn is_unchanged(path: &Path, before: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    // any write or metadata change updates the change time, also one restoring the times
+    path.symlink_metadata().is_ok_and(|after| {
+        after.ino() == before.ino()
+            && after.ctime() == before.ctime()
+            && after.ctime_nsec() == before.ctime_nsec()
+    })
+}
+
+#[cfg(not(unix))]
+fn is_unchanged(path: &Path, before: &std::fs::Metadata) -> bool {
+    path.symlink_metadata().is_ok_and(|after| {
+        after.is_file()
+            && after.len() == before.len()
+            && after.modified().ok() == before.modified().ok()
+    })
+}
+
+/// Resolves the path an entry named `entry_name` is extracted to under `dest`.
+///
+/// Entry names come from the archive and can't be trusted: absolute paths, drive prefixes,
+/// `..` components and NUL bytes are rejected with [`Error::UnsafeEntryPath`].
+/// Both `/` and `\` separate components.
+///
+/// # Example
+/// ```
+/// use std::path::Path;
+/// let path = sevenz_rust::safe_entry_path(Path::new("out"), "docs/./a.txt").unwrap();
+/// assert_eq!(path, Path::new("out").join("docs").join("a.txt"));
+/// assert!(sevenz_rust::safe_entry_path(Path::new("out"), "../a.txt").is_err());
+/// ```
+pub fn safe_entry_path(dest: &Path, entry_name: &str) -> Result<PathBuf, Error> {
+    let unsafe_path = |reason| Error::UnsafeEntryPath {
+        name: entry_name.to_string(),
+        reason,
+    };
+    if entry_name.contains('\0') {
+        return Err(unsafe_path("contains a NUL byte"));
+    }
+    if entry_name.starts_with(['/', '\\']) {
+        return Err(unsafe_path("is an absolute path"));
+    }
+    let mut path = dest.to_path_buf();
+    for component in entry_name.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => return Err(unsafe_path("contains a parent directory component")),
+            _ if has_drive_prefix(component) => return Err(unsafe_path("has a drive prefix")),
+            _ => path.push(component),
+        }
+    }
+    Ok(path)
+}
+
+fn has_drive_prefix(component: &str) -> bool {
+    let bytes = component.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// `FILE_ATTRIBUTE_READONLY` of [`SevenZArchiveEntry::windows_attributes`].
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+
+/// The timestamps an entry has.
+struct EntryTimes {
+    access: Option<ft::FileTime>,
+    modified: Option<ft::FileTime>,
+    creation: Option<ft::FileTime>,
+}
+
+impl EntryTimes {
+    fn of(entry: &SevenZArchiveEntry) -> Self {
+        let time = |defined: bool, time: nt_time::FileTime| {
+            defined.then(|| ft::FileTime::from_system_time(time.into()))
+        };
+        Self {
+            access: time(entry.has_access_date, entry.access_date),
+            modified: time(entry.has_last_modified_date, entry.last_modified_date),
+            creation: time(entry.has_creation_date, entry.creation_date),
+        }
+    }
+
+    /// Sets the defined times on `file`, the creation time only on Windows.
+    fn restore(&self, file: &std::fs::File) -> std::io::Result<()> {
+        if self.access.is_none() && self.modified.is_none() && self.creation.is_none() {
+            return Ok(());
+        }
+        ft::set_file_handle_times(file, self.access, self.modified, self.creation)
+    }
+}
+
+fn metadata_error(e: std::io::Error, path: &Path) -> Error {
+    Error::io_msg(e, format!("Restore metadata of {}", path.display()))
+}
+
+/// Extracts `entry` to `dest`.
+///
+/// Files get the times the entry defines and become read-only if the entry has the read-only
+/// attribute. Directories are only created, the `decompress*` functions restore their times
+/// once all entries are extracted. If the metadata of a file can't be restored, the error is
+/// returned after the file was written, the file is kept.
+///
+/// `dest` is written by path, so symbolic links created in the destination while the entry is
+/// extracted can redirect it. The `decompress*` functions without an extract function create
+/// the entries relative to opened directories instead.
 #[cfg(not(target_arch = "wasm32"))]
 pub fn default_entry_extract_fn(
     entry: &SevenZArchiveEntry,
@@ -130,19 +963,17 @@ pub fn default_entry_extract_fn(
         });
         let file = File::create(path)
             .map_err(|e| Error::file_open(e, path.to_string_lossy().to_string()))?;
-        if entry.size() > 0 {
+        let file = if entry.size() > 0 {
             let mut writer = BufWriter::new(file);
             std::io::copy(reader, &mut writer).map_err(Error::io)?;
-            ft::set_file_handle_times(
-                writer.get_ref(),
-                Some(ft::FileTime::from_system_time(entry.access_date().into())),
-                Some(ft::FileTime::from_system_time(
-                    entry.last_modified_date().into(),
-                )),
-                Some(ft::FileTime::from_system_time(entry.creation_date().into())),
-            )
-            .unwrap_or_default();
-        }
+            writer
+                .into_inner()
+                .map_err(|e| Error::io_msg(e.into_error(), path.to_string_lossy().to_string()))?
+        } else {
+            file
+        };
+        restore_file_metadata(&file, entry, PermissionPolicy::Ignore)
+            .map_err(|e| metadata_error(e, path))?;
     }
     Ok(true)
 }
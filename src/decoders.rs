@@ -1,4 +1,7 @@
-use std::io::Read;
+use std::{
+    io::Read,
+    sync::{atomic::AtomicU64, Arc},
+};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 #[cfg(feature = "bzip2")]
@@ -6,19 +9,49 @@ use bzip2::read::BzDecoder;
 
 #[cfg(feature = "aes256")]
 use crate::aes256sha256::Aes256Sha256Decoder;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::lzma2_parallel::LZMA2ParallelReader;
 use crate::{
     archive::SevenZMethod,
     bcj::SimpleReader,
     delta::DeltaReader,
-    error::Error,
-    folder::Coder,
-    lzma::{lzma2_get_memery_usage, LZMA2Reader, LZMAReader},
+    error::{Error, HeaderError, UnsupportedFeature},
+    folder::{Coder, Folder},
+    lzma::{lzma2_get_memery_usage, lzma_get_memery_usage_by_props, LZMA2Reader, LZMAReader},
 };
 
+/// Upper bound for zstd: the decoder accepts windows up to 2^27 bytes by default.
+const ZSTD_MEMORY_USAGE_KB: usize = (1 << 27) / 1024 + 128;
+/// bzip2 needs four bytes per byte of the largest, 900 KiB, block.
+const BZIP2_MEMORY_USAGE_KB: usize = 100 + 4 * 900;
+
+/// Settings applied while building a decoder chain.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeOptions {
+    /// Memory allowed for decoding a whole block, see [`folder_memory_usage_kb`].
+    pub(crate) max_mem_limit_kb: usize,
+    /// Worker threads used to decode LZMA2 streams, `1` decodes on the calling thread.
+    pub(crate) lzma2_threads: usize,
+    /// Counts the packed bytes read, for progress reporting.
+    pub(crate) packed_counter: Option<Arc<AtomicU64>>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_mem_limit_kb: usize::MAX / 1024,
+            lzma2_threads: 1,
+            packed_counter: None,
+        }
+    }
+}
+
 pub enum Decoder<R: Read> {
     COPY(R),
     LZMA(LZMAReader<R>),
     LZMA2(LZMA2Reader<R>),
+    #[cfg(not(target_arch = "wasm32"))]
+    LZMA2Parallel(LZMA2ParallelReader<R>),
     BCJ(SimpleReader<R>),
     Delta(DeltaReader<R>),
     #[cfg(feature = "zstd")]
@@ -46,6 +79,8 @@ impl<R: Read> Read for Decoder<R> {
             Decoder::COPY(r) => r.read(buf),
             Decoder::LZMA(r) => r.read(buf),
             Decoder::LZMA2(r) => r.read(buf),
+            #[cfg(not(target_arch = "wasm32"))]
+            Decoder::LZMA2Parallel(r) => r.read(buf),
             Decoder::BCJ(r) => r.read(buf),
             Decoder::Delta(r) => r.read(buf),
             #[cfg(feature = "bzip2")]
@@ -61,16 +96,16 @@ pub fn add_decoder<I: Read>(
     uncompressed_len: usize,
     coder: &Coder,
     #[allow(unused)] password: &[u8],
-    max_mem_limit_kb: usize,
+    options: &DecodeOptions,
 ) -> Result<Decoder<I>, Error> {
     let method = SevenZMethod::by_id(coder.decompression_method_id());
     let method = if let Some(m) = method {
         m
     } else {
-        return Err(Error::UnsupportedCompressionMethod(format!(
+        return Err(Error::unsupported(UnsupportedFeature::Method(format!(
             "{:?}",
             coder.decompression_method_id()
-        )));
+        ))));
     };
     match method.id() {
         SevenZMethod::ID_COPY => Ok(Decoder::COPY(input)),
@@ -82,7 +117,10 @@ pub fn add_decoder<I: Read>(
         SevenZMethod::ID_LZMA => {
             let dict_size = get_lzma_dic_size(coder)?;
             if coder.properties.is_empty() {
-                return Err(Error::Other("LZMA properties too short".into()));
+                return Err(Error::header(HeaderError::InvalidCoderProperties {
+                    method: "LZMA",
+                    reason: "too short",
+                }));
             }
             let props = coder.properties[0];
             let lz =
@@ -92,12 +130,16 @@ pub fn add_decoder<I: Read>(
         }
         SevenZMethod::ID_LZMA2 => {
             let dic_size = get_lzma2_dic_size(coder)?;
-            let mem_size = lzma2_get_memery_usage(dic_size) as usize;
-            if mem_size > max_mem_limit_kb {
-                return Err(Error::MaxMemLimited {
-                    max_kb: max_mem_limit_kb,
-                    actaul_kb: mem_size,
-                });
+            #[cfg(not(target_arch = "wasm32"))]
+            if options.lzma2_threads > 1 {
+                let lz = LZMA2ParallelReader::with_memory_limit(
+                    input,
+                    dic_size,
+                    options.lzma2_threads,
+                    options.max_mem_limit_kb,
+                )
+                .map_err(|e| Error::bad_password(e, !password.is_empty()))?;
+                return Ok(Decoder::LZMA2Parallel(lz));
             }
             let lz = LZMA2Reader::new(input, dic_size, None);
             Ok(Decoder::LZMA2(lz))
@@ -144,23 +186,84 @@ pub fn add_decoder<I: Read>(
             let de = Aes256Sha256Decoder::new(input, &coder.properties, password)?;
             Ok(Decoder::AES256SHA256(de))
         }
-        _ => Err(Error::UnsupportedCompressionMethod(
+        _ => Err(Error::unsupported(UnsupportedFeature::Method(
             method.name().to_string(),
-        )),
+        ))),
+    }
+}
+
+/// Estimated memory in KiB used to decode with `coder`.
+pub(crate) fn coder_memory_usage_kb(
+    coder: &Coder,
+    options: &DecodeOptions,
+) -> Result<usize, Error> {
+    let method = match SevenZMethod::by_id(coder.decompression_method_id()) {
+        Some(method) => method,
+        // fails once the decoder is built
+        None => return Ok(0),
+    };
+    let usage = match method.id() {
+        SevenZMethod::ID_LZMA => {
+            let dict_size = get_lzma_dic_size(coder)?;
+            lzma_get_memery_usage_by_props(dict_size, coder.properties[0]).map_err(Error::io)?
+                as usize
+        }
+        SevenZMethod::ID_LZMA2 => {
+            // every worker thread decodes with its own dictionary
+            let usage = lzma2_get_memery_usage(get_lzma2_dic_size(coder)?) as usize;
+            usage.saturating_mul(options.lzma2_threads.max(1))
+        }
+        SevenZMethod::ID_BCJ2 => crate::bcj2::MEMORY_USAGE / 1024,
+        SevenZMethod::ID_ZSTD => ZSTD_MEMORY_USAGE_KB,
+        SevenZMethod::ID_BZIP2 => BZIP2_MEMORY_USAGE_KB,
+        _ => 0,
+    };
+    Ok(usage)
+}
+
+/// Estimated memory in KiB used to decode `folder` with all its coders.
+pub(crate) fn folder_memory_usage_kb(
+    folder: &Folder,
+    options: &DecodeOptions,
+) -> Result<usize, Error> {
+    folder.coders.iter().try_fold(0usize, |total, coder| {
+        Ok(total.saturating_add(coder_memory_usage_kb(coder, options)?))
+    })
+}
+
+/// Fails with [`Error::MaxMemLimited`] if decoding `folder` needs more memory than allowed.
+pub(crate) fn check_folder_memory(folder: &Folder, options: &DecodeOptions) -> Result<(), Error> {
+    let usage = folder_memory_usage_kb(folder, options)?;
+    if usage > options.max_mem_limit_kb {
+        return Err(Error::MaxMemLimited {
+            folder: None,
+            max_kb: options.max_mem_limit_kb,
+            actual_kb: usage,
+        });
     }
+    Ok(())
 }
 
 #[inline]
 fn get_lzma2_dic_size(coder: &Coder) -> Result<u32, Error> {
     if coder.properties.is_empty() {
-        return Err(Error::other("LZMA2 properties too short"));
+        return Err(Error::header(HeaderError::InvalidCoderProperties {
+            method: "LZMA2",
+            reason: "too short",
+        }));
     }
     let dict_size_bits = 0xff & coder.properties[0] as u32;
     if (dict_size_bits & (!0x3f)) != 0 {
-        return Err(Error::other("Unsupported LZMA2 property bits"));
+        return Err(Error::header(HeaderError::InvalidCoderProperties {
+            method: "LZMA2",
+            reason: "unsupported property bits",
+        }));
     }
     if dict_size_bits > 40 {
-        return Err(Error::other("Dictionary larger than 4GiB maximum size"));
+        return Err(Error::header(HeaderError::InvalidCoderProperties {
+            method: "LZMA2",
+            reason: "dictionary larger than 4GiB",
+        }));
     }
     if dict_size_bits == 40 {
         return Ok(0xFFFFFFFF);
@@ -171,6 +274,13 @@ fn get_lzma2_dic_size(coder: &Coder) -> Result<u32, Error> {
 
 #[inline]
 fn get_lzma_dic_size(coder: &Coder) -> Result<u32, Error> {
-    let mut props = &coder.properties[1..5];
+    let mut props =
+        coder
+            .properties
+            .get(1..5)
+            .ok_or(Error::header(HeaderError::InvalidCoderProperties {
+                method: "LZMA",
+                reason: "too short",
+            }))?;
     props.read_u32::<LittleEndian>().map_err(Error::io)
 }
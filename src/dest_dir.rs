@@ -0,0 +1,424 @@
+//! Creating extracted entries below the destination directory.
+//!
+//! On Unix every directory below the destination is opened with `O_NOFOLLOW` and entries are
+//! created relative to the opened directory with `openat`, `mkdirat` and friends, so symbolic
+//! links in the destination, whether extracted from an earlier entry or planted by another
+//! process, can't redirect writes outside of it. Elsewhere the components of a path are checked
+//! for symbolic links before it's written, which doesn't protect against concurrent changes.
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// A component of a path below the destination is a symbolic link, see
+/// [`is_symlink_in_path`].
+#[derive(Debug)]
+struct SymlinkInPath;
+
+impl fmt::Display for SymlinkInPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("path traverses a symbolic link")
+    }
+}
+
+impl std::error::Error for SymlinkInPath {}
+
+fn symlink_in_path() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, SymlinkInPath)
+}
+
+/// Whether `e` was returned because a directory of the path is a symbolic link.
+pub(crate) fn is_symlink_in_path(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<SymlinkInPath>())
+}
+
+/// The destination directory of an extraction. Paths passed to its methods are relative to it
+/// and consist of normal components only, see [`crate::safe_entry_path`].
+pub(crate) struct DestDir {
+    root: PathBuf,
+    dir: Dir,
+}
+
+impl DestDir {
+    /// Opens `root`, creating it if needed. Symbolic links in `root` itself are followed.
+    pub(crate) fn open(root: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            dir: open_root(root)?,
+        })
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Opens the directory containing `relative` and returns it with the file name of
+    /// `relative`, creating missing directories if `create` is set.
+    fn parent<'p>(&self, relative: &'p Path, create: bool) -> io::Result<(Dir, &'p OsStr)> {
+        let mut components = normal_components(relative)?;
+        let name = components.pop().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "empty path below destination")
+        })?;
+        let mut dir = try_clone(&self.dir)?;
+        for component in components {
+            dir = open_dir_at(&dir, component, create)?;
+        }
+        Ok((dir, name))
+    }
+
+    /// Creates the directory `relative` and its missing parents.
+    pub(crate) fn create_dir_all(&self, relative: &Path) -> io::Result<()> {
+        let mut dir = try_clone(&self.dir)?;
+        for component in normal_components(relative)? {
+            dir = open_dir_at(&dir, component, true)?;
+        }
+        Ok(())
+    }
+
+    /// Opens the directory `relative`, e.g. to restore its times.
+    pub(crate) fn open_dir(&self, relative: &Path) -> io::Result<File> {
+        let mut dir = try_clone(&self.dir)?;
+        for component in normal_components(relative)? {
+            dir = open_dir_at(&dir, component, false)?;
+        }
+        dir_file(dir)
+    }
+
+    /// Creates the file `relative`, replacing a file or symbolic link at its place instead of
+    /// writing through it.
+    pub(crate) fn create_file(&self, relative: &Path) -> io::Result<File> {
+        let (dir, name) = self.parent(relative, true)?;
+        remove_existing_at(&dir, name)?;
+        create_file_at(&dir, name)
+    }
+
+    /// Opens the file `relative` for reading without following a symbolic link at its place.
+    fn open_file(&self, relative: &Path) -> io::Result<File> {
+        let (dir, name) = self.parent(relative, false)?;
+        open_file_at(&dir, name)
+    }
+
+    /// Creates a symbolic link to `target` at `relative`, replacing what's there.
+    pub(crate) fn symlink(&self, target: &str, relative: &Path) -> io::Result<()> {
+        let (dir, name) = self.parent(relative, true)?;
+        remove_existing_at(&dir, name)?;
+        symlink_at(target, &dir, name)
+    }
+
+    /// Creates a hard link at `relative` to the file `target`, replacing what's there.
+    pub(crate) fn hard_link(&self, target: &Path, relative: &Path) -> io::Result<()> {
+        let (target_dir, target_name) = self.parent(target, false)?;
+        let (dir, name) = self.parent(relative, true)?;
+        remove_existing_at(&dir, name)?;
+        hard_link_at(&target_dir, target_name, &dir, name)?;
+        // the target may have been replaced after the caller checked it
+        if !is_file_at(&dir, name) {
+            remove_at(&dir, name)?;
+            return Err(not_a_file());
+        }
+        Ok(())
+    }
+
+    /// Copies the file `target` to `relative`, replacing what's there.
+    pub(crate) fn copy(&self, target: &Path, relative: &Path) -> io::Result<()> {
+        let mut source = self.open_file(target)?;
+        if !source.metadata()?.is_file() {
+            return Err(not_a_file());
+        }
+        let mut file = self.create_file(relative)?;
+        io::copy(&mut source, &mut file)?;
+        Ok(())
+    }
+
+    /// Whether `relative` is a regular file, not following a symbolic link at its place.
+    pub(crate) fn is_file(&self, relative: &Path) -> io::Result<bool> {
+        let (dir, name) = self.parent(relative, false)?;
+        Ok(is_file_at(&dir, name))
+    }
+
+    /// Removes the file or symbolic link `relative`.
+    pub(crate) fn remove_file(&self, relative: &Path) -> io::Result<()> {
+        let (dir, name) = self.parent(relative, false)?;
+        remove_at(&dir, name)
+    }
+
+    /// Fails if a directory of `relative` is a symbolic link and removes a symbolic link at
+    /// `relative` itself, for entries written by path.
+    pub(crate) fn check(&self, relative: &Path) -> io::Result<()> {
+        match self.parent(relative, false) {
+            Ok((dir, name)) if is_symlink_at(&dir, name) => remove_at(&dir, name),
+            Ok(_) => Ok(()),
+            // nothing exists below a missing directory
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn not_a_file() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "link target is not a regular file",
+    )
+}
+
+fn normal_components(relative: &Path) -> io::Result<Vec<&OsStr>> {
+    relative
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| match component {
+            Component::Normal(name) => Ok(name),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path leaves the destination",
+            )),
+        })
+        .collect()
+}
+
+/// Removes a file or symbolic link at `name`, directories are left to fail the creation.
+fn remove_existing_at(dir: &Dir, name: &OsStr) -> io::Result<()> {
+    match remove_at(dir, name) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound && !is_dir_at(dir, name) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+type Dir = std::os::fd::OwnedFd;
+
+#[cfg(unix)]
+fn open_root(root: &Path) -> io::Result<Dir> {
+    use rustix::fs::{Mode, OFlags};
+    // an empty destination is the current directory, as for relative paths
+    let root = if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    };
+    Ok(rustix::fs::open(
+        root,
+        OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )?)
+}
+
+#[cfg(unix)]
+fn try_clone(dir: &Dir) -> io::Result<Dir> {
+    dir.try_clone()
+}
+
+#[cfg(unix)]
+fn open_dir_at(dir: &Dir, name: &OsStr, create: bool) -> io::Result<Dir> {
+    use rustix::{
+        fs::{Mode, OFlags},
+        io::Errno,
+    };
+    let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    match rustix::fs::openat(dir, name, flags, Mode::empty()) {
+        Ok(fd) => Ok(fd),
+        Err(Errno::NOENT) if create => {
+            match rustix::fs::mkdirat(dir, name, Mode::from_raw_mode(0o777)) {
+                Ok(()) | Err(Errno::EXIST) => {}
+                Err(e) => return Err(e.into()),
+            }
+            Ok(rustix::fs::openat(dir, name, flags, Mode::empty())?)
+        }
+        // depending on the system, O_NOFOLLOW fails with ELOOP, ENOTDIR or EMLINK
+        Err(_) if is_symlink_at(dir, name) => Err(symlink_in_path()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(unix)]
+fn dir_file(dir: Dir) -> io::Result<File> {
+    Ok(File::from(dir))
+}
+
+#[cfg(unix)]
+fn is_symlink_at(dir: &Dir, name: &OsStr) -> bool {
+    use rustix::fs::{AtFlags, FileType};
+    rustix::fs::statat(dir, name, AtFlags::SYMLINK_NOFOLLOW)
+        .is_ok_and(|stat| FileType::from_raw_mode(stat.st_mode) == FileType::Symlink)
+}
+
+#[cfg(unix)]
+fn is_dir_at(dir: &Dir, name: &OsStr) -> bool {
+    use rustix::fs::{AtFlags, FileType};
+    rustix::fs::statat(dir, name, AtFlags::SYMLINK_NOFOLLOW)
+        .is_ok_and(|stat| FileType::from_raw_mode(stat.st_mode) == FileType::Directory)
+}
+
+#[cfg(unix)]
+fn is_file_at(dir: &Dir, name: &OsStr) -> bool {
+    use rustix::fs::{AtFlags, FileType};
+    rustix::fs::statat(dir, name, AtFlags::SYMLINK_NOFOLLOW)
+        .is_ok_and(|stat| FileType::from_raw_mode(stat.st_mode) == FileType::RegularFile)
+}
+
+#[cfg(unix)]
+fn create_file_at(dir: &Dir, name: &OsStr) -> io::Result<File> {
+    use rustix::fs::{Mode, OFlags};
+    // O_EXCL fails if anything was created at `name` after it was removed
+    let flags = OFlags::WRONLY | OFlags::CREATE | OFlags::EXCL | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    let fd = rustix::fs::openat(dir, name, flags, Mode::from_raw_mode(0o666))?;
+    Ok(File::from(fd))
+}
+
+#[cfg(unix)]
+fn open_file_at(dir: &Dir, name: &OsStr) -> io::Result<File> {
+    use rustix::fs::{Mode, OFlags};
+    let flags = OFlags::RDONLY | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    let fd = rustix::fs::openat(dir, name, flags, Mode::empty())?;
+    Ok(File::from(fd))
+}
+
+#[cfg(unix)]
+fn symlink_at(target: &str, dir: &Dir, name: &OsStr) -> io::Result<()> {
+    Ok(rustix::fs::symlinkat(target, dir, name)?)
+}
+
+#[cfg(unix)]
+fn hard_link_at(target_dir: &Dir, target: &OsStr, dir: &Dir, name: &OsStr) -> io::Result<()> {
+    // without AT_SYMLINK_FOLLOW a symbolic link is linked itself, not its target
+    Ok(rustix::fs::linkat(
+        target_dir,
+        target,
+        dir,
+        name,
+        rustix::fs::AtFlags::empty(),
+    )?)
+}
+
+#[cfg(unix)]
+fn remove_at(dir: &Dir, name: &OsStr) -> io::Result<()> {
+    Ok(rustix::fs::unlinkat(
+        dir,
+        name,
+        rustix::fs::AtFlags::empty(),
+    )?)
+}
+
+#[cfg(not(unix))]
+type Dir = PathBuf;
+
+#[cfg(not(unix))]
+fn open_root(root: &Path) -> io::Result<Dir> {
+    Ok(root.to_path_buf())
+}
+
+#[cfg(not(unix))]
+fn try_clone(dir: &Dir) -> io::Result<Dir> {
+    Ok(dir.clone())
+}
+
+#[cfg(not(unix))]
+fn open_dir_at(dir: &Dir, name: &OsStr, create: bool) -> io::Result<Dir> {
+    let path = dir.join(name);
+    match std::fs::symlink_metadata(&path) {
+        Ok(meta) if meta.file_type().is_symlink() => Err(symlink_in_path()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound && create => {
+            std::fs::create_dir_all(&path)?;
+            Ok(path)
+        }
+        Err(e) => Err(e),
+        Ok(_) => Ok(path),
+    }
+}
+
+#[cfg(not(unix))]
+fn dir_file(dir: Dir) -> io::Result<File> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x02000000;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(dir)
+    }
+    #[cfg(not(windows))]
+    {
+        File::open(dir)
+    }
+}
+
+#[cfg(not(unix))]
+fn is_symlink_at(dir: &Dir, name: &OsStr) -> bool {
+    std::fs::symlink_metadata(dir.join(name)).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+#[cfg(not(unix))]
+fn is_dir_at(dir: &Dir, name: &OsStr) -> bool {
+    std::fs::symlink_metadata(dir.join(name)).is_ok_and(|meta| meta.is_dir())
+}
+
+#[cfg(not(unix))]
+fn is_file_at(dir: &Dir, name: &OsStr) -> bool {
+    std::fs::symlink_metadata(dir.join(name)).is_ok_and(|meta| meta.is_file())
+}
+
+#[cfg(not(unix))]
+fn create_file_at(dir: &Dir, name: &OsStr) -> io::Result<File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dir.join(name))
+}
+
+#[cfg(not(unix))]
+fn open_file_at(dir: &Dir, name: &OsStr) -> io::Result<File> {
+    let path = dir.join(name);
+    if std::fs::symlink_metadata(&path)?.file_type().is_symlink() {
+        return Err(symlink_in_path());
+    }
+    File::open(path)
+}
+
+#[cfg(windows)]
+fn symlink_at(target: &str, dir: &Dir, name: &OsStr) -> io::Result<()> {
+    let target = target.replace('/', "\\");
+    let path = dir.join(name);
+    if dir.join(&target).is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)
+    } else {
+        std::os::windows::fs::symlink_file(target, path)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_at(_target: &str, _dir: &Dir, _name: &OsStr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Symbolic links are not supported",
+    ))
+}
+
+#[cfg(not(unix))]
+fn hard_link_at(target_dir: &Dir, target: &OsStr, dir: &Dir, name: &OsStr) -> io::Result<()> {
+    std::fs::hard_link(target_dir.join(target), dir.join(name))
+}
+
+#[cfg(not(unix))]
+fn remove_at(dir: &Dir, name: &OsStr) -> io::Result<()> {
+    let path = dir.join(name);
+    let meta = std::fs::symlink_metadata(&path)?;
+    let mut permissions = meta.permissions();
+    if permissions.readonly() {
+        // Windows refuses to remove read-only files
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&path, permissions)?;
+    }
+    match std::fs::remove_file(&path) {
+        // Windows removes symbolic links to directories like directories, other directories
+        // are kept like on Unix
+        Err(e) if meta.file_type().is_symlink() => std::fs::remove_dir(&path).map_err(|_| e),
+        result => result,
+    }
+}
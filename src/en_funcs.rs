@@ -9,16 +9,9 @@ use std::{
 
 use crate::*;
 
-/// hepler function to compress `src` path to `dest` writer
+/// helper function to compress `src` path to `dest` writer
 pub fn compress<W: Write + Seek>(src: impl AsRef<Path>, dest: W) -> Result<W, Error> {
-    let mut z = SevenZWriter::new(dest)?;
-    let parent = if src.as_ref().is_dir() {
-        src.as_ref()
-    } else {
-        src.as_ref().parent().unwrap_or(src.as_ref())
-    };
-    compress_path(src.as_ref(), parent, &mut z)?;
-    z.finish().map_err(Error::io)
+    write_archive(dest, |z| push_source(src.as_ref(), z))
 }
 
 #[cfg(feature = "aes256")]
@@ -27,36 +20,105 @@ pub fn compress_encypted<W: Write + Seek>(
     dest: W,
     password: Password,
 ) -> Result<W, Error> {
-    let mut z = SevenZWriter::new(dest)?;
-    if !password.is_empty() {
-        z.set_content_methods(vec![
-            aes256sha256::AesEncoderOptions::new(password).into(),
-            SevenZMethod::LZMA2.into(),
-        ]);
-    }
-    let parent = if src.as_ref().is_dir() {
-        src.as_ref()
-    } else {
-        src.as_ref().parent().unwrap_or(src.as_ref())
-    };
-    compress_path(src.as_ref(), parent, &mut z)?;
-    z.finish().map_err(Error::io)
+    write_archive(dest, |z| {
+        set_password(z, password);
+        push_source(src.as_ref(), z)
+    })
 }
 
-/// hepler function to compress `src` path to `dest` path
+/// helper function to compress `src` path to `dest` path
 pub fn compress_to_path(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<(), Error> {
-    if let Some(p) = dest.as_ref().parent() {
-        if !p.exists() {
-            std::fs::create_dir_all(p)
-                .map_err(|e| Error::io_msg(e, format!("Create dir failed:{:?}", dest.as_ref())))?;
-        }
-    }
-    compress(
-        src,
-        File::create(dest.as_ref())
-            .map_err(|e| Error::file_open(e, dest.as_ref().to_string_lossy().to_string()))?,
-    )?;
-    Ok(())
+    write_archive_to_path(dest.as_ref(), |z| push_source(src.as_ref(), z))
+}
+
+/// helper function to compress `src` path to `dest` writer, reporting the progress to `observer`
+pub fn compress_with_progress<W: Write + Seek>(
+    src: impl AsRef<Path>,
+    dest: W,
+    observer: impl ProgressObserver + Send + 'static,
+) -> Result<W, Error> {
+    write_archive(dest, |z| {
+        set_progress(z, src.as_ref(), observer);
+        push_source(src.as_ref(), z)
+    })
+}
+
+/// helper function to compress `src` path to `dest` path, reporting the progress to `observer`
+pub fn compress_to_path_with_progress(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    observer: impl ProgressObserver + Send + 'static,
+) -> Result<(), Error> {
+    write_archive_to_path(dest.as_ref(), |z| {
+        set_progress(z, src.as_ref(), observer);
+        push_source(src.as_ref(), z)
+    })
+}
+
+/// helper function to compress `src` path to `dest` writer, failing with [`Error::Cancelled`]
+/// once `token` is cancelled
+pub fn compress_with_cancellation<W: Write + Seek>(
+    src: impl AsRef<Path>,
+    dest: W,
+    token: CancellationToken,
+) -> Result<W, Error> {
+    write_archive(dest, |z| {
+        z.set_cancellation_token(token);
+        push_source(src.as_ref(), z)
+    })
+}
+
+/// helper function to compress `src` path to `dest` path, failing with [`Error::Cancelled`]
+/// once `token` is cancelled. The partially written archive is removed then.
+/// # Example
+/// ```no_run
+/// use sevenz_rust::{CancellationToken, Error};
+/// let token = CancellationToken::new();
+/// let canceller = token.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     canceller.cancel();
+/// });
+/// match sevenz_rust::compress_to_path_with_cancellation("data", "data.7z", token) {
+///     Err(Error::Cancelled) => println!("took too long"),
+///     result => result.expect("complete"),
+/// }
+/// ```
+pub fn compress_to_path_with_cancellation(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    token: CancellationToken,
+) -> Result<(), Error> {
+    write_archive_to_path(dest.as_ref(), |z| {
+        z.set_cancellation_token(token);
+        push_source(src.as_ref(), z)
+    })
+}
+
+/// helper function to compress the paths selected by `walker` to `dest` writer
+/// # Example
+/// ```no_run
+/// use sevenz_rust::SourceWalker;
+/// let walker = SourceWalker::new("project").exclude("target");
+/// let file = std::fs::File::create("project.7z").unwrap();
+/// sevenz_rust::compress_with_walker(&walker, file).expect("complete");
+/// ```
+pub fn compress_with_walker<W: Write + Seek>(walker: &SourceWalker, dest: W) -> Result<W, Error> {
+    write_archive(dest, |z| {
+        z.push_source_walker_non_solid(walker)?;
+        Ok(())
+    })
+}
+
+/// helper function to compress the paths selected by `walker` to `dest` path
+pub fn compress_to_path_with_walker(
+    walker: &SourceWalker,
+    dest: impl AsRef<Path>,
+) -> Result<(), Error> {
+    write_archive_to_path(dest.as_ref(), |z| {
+        z.push_source_walker_non_solid(walker)?;
+        Ok(())
+    })
 }
 
 #[cfg(feature = "aes256")]
@@ -65,19 +127,85 @@ pub fn compress_to_path_encrypted(
     dest: impl AsRef<Path>,
     password: Password,
 ) -> Result<(), Error> {
-    if let Some(p) = dest.as_ref().parent() {
+    write_archive_to_path(dest.as_ref(), |z| {
+        set_password(z, password);
+        push_source(src.as_ref(), z)
+    })
+}
+
+/// Writes an archive to `dest` with the entries `push` adds, after it set up the writer.
+fn write_archive<W: Write + Seek>(
+    dest: W,
+    push: impl FnOnce(&mut SevenZWriter<W>) -> Result<(), Error>,
+) -> Result<W, Error> {
+    let mut z = SevenZWriter::new(dest)?;
+    push(&mut z)?;
+    z.finish().map_err(Error::io)
+}
+
+/// Like [`write_archive`], creating the file `dest` and its parent directories. A cancelled
+/// archive is removed.
+fn write_archive_to_path(
+    dest: &Path,
+    push: impl FnOnce(&mut SevenZWriter<File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    if let Some(p) = dest.parent() {
         if !p.exists() {
             std::fs::create_dir_all(p)
-                .map_err(|e| Error::io_msg(e, format!("Create dir failed:{:?}", dest.as_ref())))?;
+                .map_err(|e| Error::io_msg(e, format!("Create dir failed:{:?}", dest)))?;
         }
     }
-    compress_encypted(
-        src,
-        File::create(dest.as_ref())
-            .map_err(|e| Error::file_open(e, dest.as_ref().to_string_lossy().to_string()))?,
-        password,
-    )?;
-    Ok(())
+    let file =
+        File::create(dest).map_err(|e| Error::file_open(e, dest.to_string_lossy().to_string()))?;
+    match write_archive(file, push) {
+        Ok(_) => Ok(()),
+        Err(Error::Cancelled) => {
+            let _ = std::fs::remove_file(dest);
+            Err(Error::Cancelled)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Adds `src` with the entries named relative to it, or to its parent for a file.
+fn push_source<W: Write + Seek>(src: &Path, z: &mut SevenZWriter<W>) -> Result<(), Error> {
+    let parent = if src.is_dir() {
+        src
+    } else {
+        src.parent().unwrap_or(src)
+    };
+    compress_path(src, parent, z)
+}
+
+#[cfg(feature = "aes256")]
+fn set_password<W: Write + Seek>(z: &mut SevenZWriter<W>, password: Password) {
+    if !password.is_empty() {
+        z.set_content_methods(vec![
+            aes256sha256::AesEncoderOptions::new(password).into(),
+            SevenZMethod::LZMA2.into(),
+        ]);
+    }
+}
+
+fn set_progress<W: Write + Seek>(
+    z: &mut SevenZWriter<W>,
+    src: &Path,
+    observer: impl ProgressObserver + Send + 'static,
+) {
+    z.set_progress_observer(observer)
+        .set_progress_total(source_size(src));
+}
+
+/// Size of the files `compress_path` adds from `path`.
+fn source_size(path: &Path) -> u64 {
+    match path.symlink_metadata() {
+        Ok(meta) if meta.is_dir() => path
+            .read_dir()
+            .map(|dir| dir.flatten().map(|e| source_size(&e.path())).sum())
+            .unwrap_or(0),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
 }
 
 fn compress_path<W: Write + Seek, P: AsRef<Path>>(
@@ -91,17 +219,21 @@ fn compress_path<W: Write + Seek, P: AsRef<Path>>(
         .map_err(|e| Error::other(e.to_string()))?
         .to_string_lossy()
         .to_string();
-    let entry = SevenZArchiveEntry::from_path(src.as_ref(), entry_name);
+    let mut entry = SevenZArchiveEntry::from_path(src.as_ref(), entry_name);
+    z.stored_times.apply(&mut entry);
     let path = src.as_ref();
-    if path.is_dir() {
-        z.push_archive_entry::<&[u8]>(entry, None)?;
+    if entry.is_directory() {
+        // the root directory has no name of its own
+        if !entry.name().is_empty() {
+            z.push_archive_entry::<&[u8]>(entry, None)?;
+        }
         for dir in path
             .read_dir()
             .map_err(|e| Error::io_msg(e, "error read dir"))?
         {
             let dir = dir.map_err(Error::io)?;
             let ftype = dir.file_type().map_err(Error::io)?;
-            if ftype.is_dir() || ftype.is_file() {
+            if ftype.is_dir() || ftype.is_file() || ftype.is_symlink() {
                 compress_path(dir.path(), root, z)?;
             }
         }
@@ -109,7 +241,7 @@ fn compress_path<W: Write + Seek, P: AsRef<Path>>(
         z.push_archive_entry(
             entry,
             Some(
-                File::open(path)
+                SourceFile::open(path, false)
                     .map_err(|e| Error::file_open(e, path.to_string_lossy().to_string()))?,
             ),
         )?;
@@ -119,9 +251,11 @@ fn compress_path<W: Write + Seek, P: AsRef<Path>>(
 
 impl<W: Write + Seek> SevenZWriter<W> {
     /// [Solid compression](https://en.wikipedia.org/wiki/Solid_compression)
-    /// compress all files in [path].
+    /// compress all files and directories in [path].
     /// With multiple files in one block.
     ///
+    /// Entries are named by their path relative to [path], below the prefix set with
+    /// [`SevenZWriter::set_path_prefix`].
     #[inline]
     pub fn push_source_path(
         &mut self,
@@ -133,7 +267,7 @@ impl<W: Write + Seek> SevenZWriter<W> {
     }
 
     /// [Non-solid compression]
-    /// compress all files in [path].
+    /// compress all files and directories in [path].
     /// With one file per block.
     pub fn push_source_path_non_solid(
         &mut self,
@@ -143,6 +277,27 @@ impl<W: Write + Seek> SevenZWriter<W> {
         encode_path(false, &path, self, filter)?;
         Ok(self)
     }
+
+    /// [Solid compression](https://en.wikipedia.org/wiki/Solid_compression)
+    /// compress the files and directories selected by `walker`.
+    /// With multiple files in one block.
+    pub fn push_source_walker(&mut self, walker: &SourceWalker) -> Result<&mut Self, crate::Error> {
+        let paths = walker.walk()?;
+        encode_paths(true, walker.root(), paths, walker.follow_symlinks, self)?;
+        Ok(self)
+    }
+
+    /// [Non-solid compression]
+    /// compress the files and directories selected by `walker`.
+    /// With one file per block.
+    pub fn push_source_walker_non_solid(
+        &mut self,
+        walker: &SourceWalker,
+    ) -> Result<&mut Self, crate::Error> {
+        let paths = walker.walk()?;
+        encode_paths(false, walker.root(), paths, walker.follow_symlinks, self)?;
+        Ok(self)
+    }
 }
 
 fn collect_file_paths(
@@ -154,20 +309,53 @@ fn collect_file_paths(
     if !filter(path) {
         return Ok(());
     }
-    if path.is_dir() {
+    paths.push(path.to_path_buf());
+    if path.is_dir() && !path.is_symlink() {
         for dir in path.read_dir()? {
             let dir = dir?;
             let ftype = dir.file_type()?;
-            if ftype.is_file() || ftype.is_dir() {
+            if ftype.is_file() || ftype.is_dir() || ftype.is_symlink() {
                 collect_file_paths(dir.path(), paths, filter)?;
             }
         }
-    } else {
-        paths.push(path.to_path_buf())
     }
     Ok(())
 }
 
+/// Returns for each path the index of the first path linking to the same file, if it is a
+/// later link. The order of `paths` is kept, so directories stay before their contents.
+#[cfg(unix)]
+fn group_hard_links(
+    paths: Vec<PathBuf>,
+    follow_symlinks: bool,
+) -> std::io::Result<Vec<(PathBuf, Option<usize>)>> {
+    use std::{collections::HashMap, os::unix::fs::MetadataExt};
+
+    let mut first_of_inode: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut grouped = Vec::with_capacity(paths.len());
+    for path in paths {
+        let meta = source_metadata(&path, follow_symlinks)?;
+        let mut link_to = None;
+        if meta.is_file() && meta.nlink() > 1 {
+            let index = grouped.len();
+            let first = *first_of_inode
+                .entry((meta.dev(), meta.ino()))
+                .or_insert(index);
+            link_to = (first != index).then_some(first);
+        }
+        grouped.push((path, link_to));
+    }
+    Ok(grouped)
+}
+
+#[cfg(not(unix))]
+fn group_hard_links(
+    paths: Vec<PathBuf>,
+    _follow_symlinks: bool,
+) -> std::io::Result<Vec<(PathBuf, Option<usize>)>> {
+    Ok(paths.into_iter().map(|path| (path, None)).collect())
+}
+
 const MAX_BLOCK_SIZE: u64 = 4 * 1024 * 1024 * 1024; //4G
 fn encode_path<W: Write + Seek>(
     solid: bool,
@@ -175,7 +363,6 @@ fn encode_path<W: Write + Seek>(
     zip: &mut SevenZWriter<W>,
     filter: impl Fn(&Path) -> bool,
 ) -> Result<(), crate::Error> {
-    let mut entries = Vec::new();
     let mut paths = Vec::new();
     collect_file_paths(&src, &mut paths, &filter).map_err(|e| {
         crate::Error::io_msg(
@@ -183,34 +370,83 @@ fn encode_path<W: Write + Seek>(
             format!("Failed to collect entries from path:{:?}", src.as_ref()),
         )
     })?;
-    if !solid {
-        for ele in paths.into_iter() {
-            let name = ele
-                .strip_prefix(&src)
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
-            zip.push_archive_entry(
-                SevenZArchiveEntry::from_path(ele.as_path(), name),
-                Some(File::open(ele.as_path()).map_err(crate::Error::io)?),
-            )?;
-        }
-        return Ok(());
+    encode_paths(solid, src.as_ref(), paths, false, zip)
+}
+
+fn source_metadata(path: &Path, follow_symlinks: bool) -> std::io::Result<std::fs::Metadata> {
+    match path.metadata() {
+        Ok(meta) if follow_symlinks => Ok(meta),
+        _ => path.symlink_metadata(),
     }
+}
+
+/// Adds `paths` below `src`, directories before their contents.
+fn encode_paths<W: Write + Seek>(
+    solid: bool,
+    src: &Path,
+    paths: Vec<PathBuf>,
+    follow_symlinks: bool,
+    zip: &mut SevenZWriter<W>,
+) -> Result<(), crate::Error> {
+    let mut entries = Vec::new();
+    let paths = group_hard_links(paths, follow_symlinks).map_err(|e| {
+        crate::Error::io_msg(e, format!("Failed to collect entries from path:{:?}", src))
+    })?;
+    zip.add_progress_total(|| {
+        paths
+            .iter()
+            .filter_map(|(path, _)| source_metadata(path, follow_symlinks).ok())
+            .filter(|meta| !meta.is_dir())
+            .map(|meta| meta.len())
+            .sum()
+    });
+    let mut names: Vec<String> = Vec::with_capacity(paths.len());
     let mut files = Vec::new();
     let mut file_size = 0;
-    for ele in paths.into_iter() {
-        let size = ele.metadata()?.len();
-        let name = ele
-            .strip_prefix(&src)
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        if size >= MAX_BLOCK_SIZE {
-            zip.push_archive_entry(
-                SevenZArchiveEntry::from_path(ele.as_path(), name),
-                Some(File::open(ele.as_path()).map_err(crate::Error::io)?),
-            )?;
+    for (ele, link_to) in paths.into_iter() {
+        let mut relative = ele.strip_prefix(src).unwrap().to_string_lossy().to_string();
+        if relative.is_empty() && !ele.is_dir() {
+            // a single file is named after itself
+            relative = ele
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        }
+        let name = match (zip.path_prefix.as_str(), relative.as_str()) {
+            // the source directory itself is only stored under a prefix
+            ("", "") => {
+                names.push(String::new());
+                continue;
+            }
+            ("", relative) => relative.to_string(),
+            (prefix, "") => prefix.to_string(),
+            (prefix, relative) => format!("{prefix}/{relative}"),
+        };
+        let mut entry = SevenZArchiveEntry::from_path_following(&ele, name, follow_symlinks);
+        zip.stored_times.apply(&mut entry);
+        names.push(entry.name().to_string());
+        if entry.is_directory() {
+            zip.push_archive_entry::<&[u8]>(entry, None)?;
+            continue;
+        }
+        let link_target = match link_to {
+            Some(first) if zip.store_hard_links => {
+                entry.set_hard_link_mode();
+                Some(names[first].clone().into_bytes())
+            }
+            _ => None,
+        };
+        let size = match &link_target {
+            Some(target) => target.len() as u64,
+            None => source_metadata(&ele, follow_symlinks)?.len(),
+        };
+        let reader = match link_target {
+            Some(target) => LazyFileReader::with_content(target),
+            None => LazyFileReader::new(ele, follow_symlinks),
+        };
+        if !solid || size >= MAX_BLOCK_SIZE {
+            zip.push_archive_entry(entry, Some(reader))?;
             continue;
         }
         if file_size + size >= MAX_BLOCK_SIZE {
@@ -220,8 +456,8 @@ fn encode_path<W: Write + Seek>(
             file_size = 0;
         }
         file_size += size;
-        entries.push(SevenZArchiveEntry::from_path(ele.as_path(), name));
-        files.push(LazyFileReader::new(ele).into());
+        entries.push(entry);
+        files.push(reader.into());
     }
     if !entries.is_empty() {
         zip.push_archive_entries(entries, SeqReader::new(files))?;
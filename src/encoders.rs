@@ -4,8 +4,12 @@ use std::io::Write;
 use crate::aes256sha256::Aes256Sha256Encoder;
 use crate::{
     archive::{SevenZMethod, SevenZMethodConfiguration},
+    error::UnsupportedFeature,
     lzma::CountingWriter,
-    lzma::{LZMA2Options, LZMA2Writer, LZMAWriter},
+    lzma::{
+        lzma2_get_memery_usage, lzma_get_memery_usage_by_props, LZMA2Options, LZMA2Writer,
+        LZMAWriter,
+    },
     method_options::MethodOptions,
     Error,
 };
@@ -66,9 +70,42 @@ pub fn add_encoder<W: Write>(
 
             Ok(Encoder::AES(Aes256Sha256Encoder::new(input, options)?))
         }
-        _ => Err(Error::UnsupportedCompressionMethod(
+        _ => Err(Error::unsupported(UnsupportedFeature::Method(
             method.name().to_string(),
-        )),
+        ))),
+    }
+}
+
+/// Memory in KiB for the AES block buffers, rounded up.
+#[cfg(feature = "aes256")]
+const AES_MEMORY_USAGE_KB: u64 = 1;
+
+/// Estimated memory in KiB used by the encoder for `method_config` and by the decoder of
+/// its output, in this order.
+pub(crate) fn method_memory_usage_kb(
+    method_config: &SevenZMethodConfiguration,
+) -> Result<(u64, u64), Error> {
+    let method = method_config.method;
+    match method.id() {
+        SevenZMethod::ID_LZMA => {
+            let mut def_opts = LZMA2Options::default();
+            let options = get_lzma2_options(method_config.options.as_ref(), &mut def_opts);
+            let decode = lzma_get_memery_usage_by_props(options.dict_size, options.get_props())
+                .map_err(Error::io)?;
+            Ok((options.get_memery_usage() as u64, decode as u64))
+        }
+        SevenZMethod::ID_LZMA2 => {
+            let mut def_opts = LZMA2Options::default();
+            let options = get_lzma2_options(method_config.options.as_ref(), &mut def_opts);
+            let decode = lzma2_get_memery_usage(options.dict_size);
+            Ok((options.get_memery_usage() as u64, decode as u64))
+        }
+        #[cfg(feature = "aes256")]
+        SevenZMethod::ID_AES256SHA256 => Ok((AES_MEMORY_USAGE_KB, AES_MEMORY_USAGE_KB)),
+        // the same methods `add_encoder` rejects
+        _ => Err(Error::unsupported(UnsupportedFeature::Method(
+            method.name().to_string(),
+        ))),
     }
 }
 
@@ -0,0 +1,149 @@
+//! Selection of the archive entries to extract.
+
+use crate::{
+    glob::{self, Glob},
+    Archive, Error,
+};
+
+/// Include and exclude patterns selecting the entries to extract, see
+/// [`SevenZReader::set_entry_filter`](crate::SevenZReader::set_entry_filter) and
+/// [`ExtractOptions::filter`](crate::ExtractOptions::filter).
+///
+/// Patterns are matched against [`Archive::files`] before anything is decoded, so blocks
+/// without a selected entry are skipped and blocks are only decoded up to their last selected
+/// entry. An entry is selected if it matches one of the include patterns, or there are none,
+/// and matches none of the exclude patterns.
+///
+/// Globs are matched against the whole entry name, a glob without `/` matches the last
+/// component of the name. `\` escapes the next character, e.g. `\[1\]` matches a literal `[1]`. Regular expressions, with the `regex` feature, match anywhere in the
+/// entry name unless anchored.
+///
+/// # Examples
+/// ```no_run
+/// use sevenz_rust::*;
+/// let mut sz = SevenZReader::open("example.7z", Password::empty()).unwrap();
+/// sz.set_entry_filter(EntryFilter::new().include("docs/**").exclude("*.bak"));
+/// sz.for_each_entries(|entry, _reader| {
+///     println!("{}", entry.name());
+///     Ok(true)
+/// })
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+    include: Vec<EntryPattern>,
+    exclude: Vec<EntryPattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EntryPattern {
+    Glob(String),
+    #[cfg(feature = "regex")]
+    Regex(String),
+}
+
+impl EntryFilter {
+    /// Selects every entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the entries matching the glob `pattern`.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(EntryPattern::Glob(pattern.into()));
+        self
+    }
+
+    /// Skips the entries matching the glob `pattern`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(EntryPattern::Glob(pattern.into()));
+        self
+    }
+
+    /// Selects the entries matching the regular expression `pattern`.
+    #[cfg(feature = "regex")]
+    pub fn include_regex(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(EntryPattern::Regex(pattern.into()));
+        self
+    }
+
+    /// Skips the entries matching the regular expression `pattern`.
+    #[cfg(feature = "regex")]
+    pub fn exclude_regex(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(EntryPattern::Regex(pattern.into()));
+        self
+    }
+
+    /// Whether the filter selects every entry.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Returns whether each entry of `archive` is selected, `None` if all are.
+    pub(crate) fn select(&self, archive: &Archive) -> Result<Option<Vec<bool>>, Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let compile = |patterns: &[EntryPattern]| -> Result<Vec<Matcher>, Error> {
+            patterns.iter().map(Matcher::new).collect()
+        };
+        let include = compile(&self.include)?;
+        let exclude = compile(&self.exclude)?;
+        Ok(Some(
+            archive
+                .files
+                .iter()
+                .map(|file| {
+                    let name = file.name();
+                    (include.is_empty() || include.iter().any(|m| m.matches(name)))
+                        && !exclude.iter().any(|m| m.matches(name))
+                })
+                .collect(),
+        ))
+    }
+}
+
+enum Matcher {
+    Glob {
+        glob: Glob,
+        /// Whether the glob is matched against the last component instead of the whole name.
+        name_only: bool,
+    },
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn new(pattern: &EntryPattern) -> Result<Self, Error> {
+        match pattern {
+            EntryPattern::Glob(pattern) => Ok(Self::Glob {
+                glob: Glob::new(pattern)?,
+                name_only: !glob::has_separator(pattern),
+            }),
+            #[cfg(feature = "regex")]
+            EntryPattern::Regex(pattern) => {
+                regex::Regex::new(pattern)
+                    .map(Self::Regex)
+                    .map_err(|e| Error::InvalidPattern {
+                        pattern: pattern.clone(),
+                        reason: e.to_string(),
+                    })
+            }
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Glob { glob, name_only } => {
+                if *name_only {
+                    let name = name.trim_end_matches(['/', '\\']);
+                    glob.matches(name.rsplit(['/', '\\']).next().unwrap_or(name))
+                } else {
+                    glob.matches(name)
+                }
+            }
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
@@ -1,25 +1,184 @@
-use std::{borrow::Cow, fmt::Display};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display},
+};
+
+/// Errors of reading, writing and extracting archives.
+///
+/// Variants carry the name of the entry and the index of the block (folder) that failed where
+/// they are known, see [`Error::entry`] and [`Error::folder`]. I/O errors are available from
+/// [`std::error::Error::source`]. New variants may be added in minor releases.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     BadSignature([u8; 6]),
-    UnsupportedVersion { major: u8, minor: u8 },
-    ChecksumVerificationFailed,
-    NextHeaderCrcMismatch,
-    Io(std::io::Error, Cow<'static, str>),
-    FileOpen(std::io::Error, String),
-    Other(Cow<'static, str>),
-    BadTerminatedStreamsInfo(u8),
-    BadTerminatedUnpackInfo,
-    BadTerminatedPackInfo(u8),
-    BadTerminatedSubStreamsInfo,
-    BadTerminatedheader(u8),
-
-    ExternalUnsupported,
-    UnsupportedCompressionMethod(String),
-    MaxMemLimited { max_kb: usize, actaul_kb: usize },
+    UnsupportedVersion {
+        major: u8,
+        minor: u8,
+    },
+    /// The header of the archive is malformed.
+    InvalidHeader {
+        kind: HeaderError,
+        /// Position in the header where parsing stopped. It's relative to the decoded header
+        /// once a compressed header was decoded.
+        offset: Option<u64>,
+    },
+    /// The archive uses a feature that isn't implemented.
+    Unsupported {
+        feature: UnsupportedFeature,
+        folder: Option<usize>,
+    },
+    /// Data doesn't match the CRC32 stored for it.
+    ChecksumMismatch {
+        target: ChecksumTarget,
+        expected: u32,
+        actual: u32,
+        entry: Option<String>,
+        folder: Option<usize>,
+    },
+    Io {
+        source: std::io::Error,
+        /// What was being done, e.g. the path of a file that failed to open. May be empty.
+        context: Cow<'static, str>,
+        entry: Option<String>,
+        folder: Option<usize>,
+    },
     PasswordRequired,
-    Unsupported(Cow<'static, str>),
-    MaybeBadPassword(std::io::Error),
+    /// Decoding encrypted data failed with the contained error, most likely because the
+    /// password is wrong.
+    MaybeBadPassword(Box<Error>),
+    /// Decoding or encoding needs more memory than allowed.
+    MaxMemLimited {
+        folder: Option<usize>,
+        max_kb: usize,
+        actual_kb: usize,
+    },
+    /// Compressing needs more memory than [`crate::SevenZWriter::set_memory_budget`] allows,
+    /// see [`crate::SevenZWriter::estimate_memory`].
+    MemoryBudgetExceeded {
+        budget: u64,
+        estimate: u64,
+    },
+    /// An entry name that would be extracted outside of the destination directory.
+    UnsafeEntryPath {
+        name: String,
+        reason: &'static str,
+    },
+    /// An entry name rejected by [`crate::SevenZWriter`], see
+    /// [`crate::SevenZWriter::set_normalize_names`].
+    InvalidEntryName {
+        name: String,
+        reason: &'static str,
+    },
+    ExtractLimitExceeded {
+        limit: crate::ExtractLimit,
+        actual: u64,
+        max: u64,
+    },
+    ReadLimitExceeded {
+        limit: crate::ReadLimit,
+        actual: u64,
+        max: u64,
+    },
+    /// An include or exclude pattern that isn't a valid glob or regular expression.
+    InvalidPattern {
+        pattern: String,
+        reason: String,
+    },
+    /// A file or folder index passed to a method is beyond the entries of the archive.
+    IndexOutOfRange {
+        what: &'static str,
+        index: usize,
+    },
+    /// The operation was stopped with a [`crate::CancellationToken`].
+    Cancelled,
+    Other(Cow<'static, str>),
+}
+
+/// The malformed part of an archive header, see [`Error::InvalidHeader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderError {
+    /// A property id other than the one the format requires, e.g. a section not ended by `kEnd`.
+    UnexpectedProperty {
+        section: &'static str,
+        expected: &'static str,
+        found: u8,
+    },
+    /// The decoded header doesn't start with `kHeader`.
+    MissingHeader,
+    /// The start header is corrupt and no end header was found by scanning the archive.
+    EndHeaderNotFound,
+    /// The next header ends beyond the end of the archive.
+    HeaderOutOfBounds { end: u64, archive_len: u64 },
+    /// A compressed header without a folder or packed stream to decode it from.
+    MissingHeaderStreams,
+    /// A property that must follow another one appears before it.
+    MisorderedProperty {
+        property: &'static str,
+        requires: &'static str,
+    },
+    /// The names property has an odd size or doesn't hold a name for every entry.
+    InvalidNames,
+    /// A count or size that doesn't fit in the header or in memory.
+    ValueOutOfRange { field: &'static str, value: u64 },
+    /// Entries with content need more folders than the archive has.
+    TooFewFolders,
+    /// Folders need more packed streams than the archive has.
+    TooFewPackStreams,
+    /// Entries have content but the sizes of the sub streams are missing.
+    MissingSubStreamsInfo,
+    /// More entries have content than there are sub streams.
+    TooManyStreamEntries,
+    /// The sub stream sizes of a folder add up to more than its unpack size.
+    SubStreamSizesExceedFolder,
+    /// A folder has no output streams.
+    NoOutputStreams,
+    /// A folder has fewer input streams than bind pairs.
+    TooFewInputStreams,
+    /// No bind pair or packed stream feeds an input stream of a coder.
+    MissingBindPair { in_stream: usize },
+    /// A bind pair refers to a coder that doesn't exist.
+    CoderIndexOutOfRange { index: usize },
+    /// The properties of a coder are malformed.
+    InvalidCoderProperties {
+        method: &'static str,
+        reason: &'static str,
+    },
+}
+
+/// A feature of the 7z format that isn't implemented, see [`Error::Unsupported`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsupportedFeature {
+    /// Header properties stored outside of the header.
+    ExternalData { property: &'static str },
+    /// The `kAdditionalStreamsInfo` header property.
+    AdditionalStreams,
+    /// The `kStartPos` file property.
+    StartPos,
+    /// Coders with alternative methods.
+    AlternativeMethods,
+    /// Coders with several input or output streams, except for BCJ2.
+    MultiStreamCoders,
+    /// Folders with more coders than the decoder handles.
+    TooManyCoders(usize),
+    /// A compression, filter or encryption method, by name or id.
+    Method(String),
+}
+
+/// The data whose CRC32 didn't match, see [`Error::ChecksumMismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumTarget {
+    StartHeader,
+    NextHeader,
+    /// The whole decoded content of a folder.
+    Folder,
+    /// The content of an entry.
+    Entry,
+    /// A packed stream as stored in the archive, checked by [`crate::SevenZReader::test`].
+    PackStream,
 }
 
 impl From<std::io::Error> for Error {
@@ -33,31 +192,48 @@ impl Error {
     pub fn other<S: Into<Cow<'static, str>>>(s: S) -> Self {
         Self::Other(s.into())
     }
+
     #[inline]
-    pub fn unsupported<S: Into<Cow<'static, str>>>(s: S) -> Self {
-        Self::Unsupported(s.into())
+    pub(crate) fn header(kind: HeaderError) -> Self {
+        Self::InvalidHeader { kind, offset: None }
+    }
+
+    #[inline]
+    pub(crate) fn unsupported(feature: UnsupportedFeature) -> Self {
+        Self::Unsupported {
+            feature,
+            folder: None,
+        }
     }
 
+    /// Wraps `e`, unwrapping an [`Error`] carried by it, e.g. a checksum mismatch raised
+    /// while reading an entry.
     #[inline]
     pub fn io(e: std::io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = e.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
         Self::io_msg(e, "")
     }
+
     #[inline]
     pub fn io_msg(e: std::io::Error, msg: impl Into<Cow<'static, str>>) -> Self {
-        Self::Io(e, msg.into())
+        Self::Io {
+            source: e,
+            context: msg.into(),
+            entry: None,
+            folder: None,
+        }
     }
 
     pub fn bad_password(e: std::io::Error, encryped: bool) -> Self {
-        if encryped {
-            Self::MaybeBadPassword(e)
-        } else {
-            Self::io(e)
-        }
+        Self::io(e).maybe_bad_password(encryped)
     }
 
     #[inline]
     pub(crate) fn file_open(e: std::io::Error, filename: impl Into<Cow<'static, str>>) -> Self {
-        Self::Io(e, filename.into())
+        Self::io_msg(e, filename)
     }
 
     pub(crate) fn maybe_bad_password(self, encryped: bool) -> Self {
@@ -65,16 +241,230 @@ impl Error {
             return self;
         }
         match self {
-            Self::Io(e, s) if s.is_empty() => Self::MaybeBadPassword(e),
+            Self::Io { ref context, .. } if context.is_empty() => {
+                Self::MaybeBadPassword(Box::new(self))
+            }
+            Self::ChecksumMismatch { .. } => Self::MaybeBadPassword(Box::new(self)),
             _ => self,
         }
     }
+
+    /// Name of the entry the error happened in, if known.
+    pub fn entry(&self) -> Option<&str> {
+        match self {
+            Self::ChecksumMismatch { entry, .. } | Self::Io { entry, .. } => entry.as_deref(),
+            Self::UnsafeEntryPath { name, .. } | Self::InvalidEntryName { name, .. } => Some(name),
+            Self::MaybeBadPassword(e) => e.entry(),
+            _ => None,
+        }
+    }
+
+    /// Index of the folder the error happened in, if known.
+    pub fn folder(&self) -> Option<usize> {
+        match self {
+            Self::Unsupported { folder, .. }
+            | Self::ChecksumMismatch { folder, .. }
+            | Self::Io { folder, .. }
+            | Self::MaxMemLimited { folder, .. } => *folder,
+            Self::MaybeBadPassword(e) => e.folder(),
+            _ => None,
+        }
+    }
+
+    /// Records the entry the error happened in, unless it's already known.
+    pub(crate) fn with_entry(mut self, name: &str) -> Self {
+        match &mut self {
+            Self::ChecksumMismatch { entry, .. } | Self::Io { entry, .. } => {
+                entry.get_or_insert_with(|| name.to_string());
+            }
+            Self::MaybeBadPassword(e) => {
+                **e = std::mem::replace(&mut **e, Self::PasswordRequired).with_entry(name);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Records the folder the error happened in, unless it's already known.
+    pub(crate) fn with_folder(mut self, folder_index: usize) -> Self {
+        match &mut self {
+            Self::Unsupported { folder, .. }
+            | Self::ChecksumMismatch { folder, .. }
+            | Self::Io { folder, .. }
+            | Self::MaxMemLimited { folder, .. } => {
+                folder.get_or_insert(folder_index);
+            }
+            Self::MaybeBadPassword(e) => {
+                **e = std::mem::replace(&mut **e, Self::PasswordRequired).with_folder(folder_index);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Records where in the header parsing stopped, unless it's already known.
+    pub(crate) fn at_offset(mut self, position: u64) -> Self {
+        if let Self::InvalidHeader { offset, .. } = &mut self {
+            offset.get_or_insert(position);
+        }
+        self
+    }
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Debug::fmt(&self, f)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSignature(signature) => write!(f, "not a 7z archive, signature {signature:?}"),
+            Self::UnsupportedVersion { major, minor } => {
+                write!(f, "unsupported 7z version {major}.{minor}")
+            }
+            Self::InvalidHeader { kind, offset } => {
+                write!(f, "invalid header: {kind}")?;
+                if let Some(offset) = offset {
+                    write!(f, " at header offset {offset}")?;
+                }
+                Ok(())
+            }
+            Self::Unsupported { feature, .. } => write!(f, "unsupported: {feature}"),
+            Self::ChecksumMismatch {
+                target,
+                expected,
+                actual,
+                ..
+            } => write!(
+                f,
+                "{target} checksum mismatch, expected {expected:08x}, got {actual:08x}"
+            ),
+            Self::Io {
+                source, context, ..
+            } if context.is_empty() => write!(f, "{source}"),
+            Self::Io {
+                source, context, ..
+            } => write!(f, "{context}: {source}"),
+            Self::PasswordRequired => write!(f, "a password is required"),
+            Self::MaybeBadPassword(e) => write!(f, "{e}, the password may be wrong"),
+            Self::MaxMemLimited {
+                max_kb, actual_kb, ..
+            } => write!(
+                f,
+                "needs {actual_kb} KiB of memory, the limit is {max_kb} KiB"
+            ),
+            Self::MemoryBudgetExceeded { budget, estimate } => write!(
+                f,
+                "compressing needs an estimated {estimate} bytes of memory, the budget is {budget} bytes"
+            ),
+            Self::UnsafeEntryPath { name, reason } | Self::InvalidEntryName { name, reason } => {
+                write!(f, "entry name {name:?} {reason}")
+            }
+            Self::ExtractLimitExceeded { limit, actual, max } => {
+                write!(f, "extract limit {limit:?} exceeded: {actual} > {max}")
+            }
+            Self::ReadLimitExceeded { limit, actual, max } => {
+                write!(f, "read limit {limit:?} exceeded: {actual} > {max}")
+            }
+            Self::InvalidPattern { pattern, reason } => {
+                write!(f, "invalid pattern {pattern:?}: {reason}")
+            }
+            Self::IndexOutOfRange { what, index } => write!(f, "{what} index {index} out of range"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Other(msg) => write!(f, "{msg}"),
+        }?;
+        let (entry, folder) = match self {
+            Self::ChecksumMismatch { entry, folder, .. } | Self::Io { entry, folder, .. } => {
+                (entry.as_deref(), *folder)
+            }
+            Self::Unsupported { folder, .. } | Self::MaxMemLimited { folder, .. } => {
+                (None, *folder)
+            }
+            _ => (None, None),
+        };
+        match (entry, folder) {
+            (Some(entry), _) => write!(f, " in entry {entry:?}"),
+            (None, Some(folder)) => write!(f, " in folder {folder}"),
+            (None, None) => Ok(()),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedProperty {
+                section,
+                expected,
+                found,
+            } => write!(f, "expected {expected} in {section}, found {found:#04x}"),
+            Self::MissingHeader => write!(f, "no kHeader"),
+            Self::EndHeaderNotFound => {
+                write!(f, "start header corrupt and unable to guess end header")
+            }
+            Self::HeaderOutOfBounds { end, archive_len } => write!(
+                f,
+                "next header ends at {end} beyond the archive length {archive_len}"
+            ),
+            Self::MissingHeaderStreams => write!(f, "no streams to decode the header from"),
+            Self::MisorderedProperty { property, requires } => {
+                write!(f, "{requires} must appear before {property}")
+            }
+            Self::InvalidNames => write!(f, "invalid file names"),
+            Self::ValueOutOfRange { field, value } => write!(f, "{field} {value} out of range"),
+            Self::TooFewFolders => write!(f, "too few folders"),
+            Self::TooFewPackStreams => write!(f, "too few packed streams"),
+            Self::MissingSubStreamsInfo => write!(f, "files with streams but no subStreamsInfo"),
+            Self::TooManyStreamEntries => {
+                write!(f, "more files with streams than subStreamsInfo")
+            }
+            Self::SubStreamSizesExceedFolder => {
+                write!(f, "sum of unpack sizes of folder exceeds total unpack size")
+            }
+            Self::NoOutputStreams => write!(f, "total output streams can't be 0"),
+            Self::TooFewInputStreams => write!(
+                f,
+                "total input streams can't be less than the number of bind pairs"
+            ),
+            Self::MissingBindPair { in_stream } => {
+                write!(f, "couldn't find bind pair for stream {in_stream}")
+            }
+            Self::CoderIndexOutOfRange { index } => write!(f, "coder index {index} out of range"),
+            Self::InvalidCoderProperties { method, reason } => {
+                write!(f, "{method} properties: {reason}")
+            }
+        }
+    }
+}
+
+impl Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExternalData { property } => write!(f, "external {property}"),
+            Self::AdditionalStreams => write!(f, "additional streams"),
+            Self::StartPos => write!(f, "kStartPos"),
+            Self::AlternativeMethods => write!(f, "alternative methods"),
+            Self::MultiStreamCoders => write!(f, "multi input/output stream coders"),
+            Self::TooManyCoders(count) => write!(f, "{count} coders in a folder"),
+            Self::Method(method) => write!(f, "method {method}"),
+        }
+    }
+}
+
+impl Display for ChecksumTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::StartHeader => "start header",
+            Self::NextHeader => "next header",
+            Self::Folder => "folder",
+            Self::Entry => "entry",
+            Self::PackStream => "pack stream",
+        })
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::MaybeBadPassword(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Glob matching of slash-separated paths.
+//!
+//! Supported syntax: `*` matches any run of characters within a path component, `?` matches
+//! a single character, `[abc]`, `[a-z]` and `[!abc]` match character classes, `**` as a whole
+//! component matches any number of components and `\` escapes the next character. Patterns are
+//! split at unescaped `/`, matched paths at both `/` and `\`.
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Component {
+    /// `**`
+    AnyDepth,
+    Tokens(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyRun,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Result<Self, Error> {
+        let mut components: Vec<Component> = split_pattern(pattern)
+            .into_iter()
+            .map(|component| {
+                if component == "**" {
+                    Ok(Component::AnyDepth)
+                } else {
+                    parse_component(component).map(Component::Tokens)
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|msg| Error::InvalidPattern {
+                pattern: pattern.to_string(),
+                reason: msg.to_string(),
+            })?;
+        // `**/**` matches the same as `**`
+        components.dedup_by(|a, b| *a == Component::AnyDepth && *b == Component::AnyDepth);
+        Ok(Self { components })
+    }
+
+    /// Whether `path` matches the whole pattern. Both `/` and `\` separate components.
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        let components: Vec<Vec<char>> = split_path(path).map(|c| c.chars().collect()).collect();
+        match_wildcards(
+            &self.components,
+            &components,
+            |pattern| *pattern == Component::AnyDepth,
+            |pattern, chars| match pattern {
+                Component::Tokens(tokens) => match_tokens(tokens, chars),
+                Component::AnyDepth => unreachable!("handled by match_wildcards"),
+            },
+        )
+    }
+}
+
+/// Whether `pattern` has more than one component, i.e. contains an unescaped `/`.
+pub(crate) fn has_separator(pattern: &str) -> bool {
+    split_pattern(pattern).len() > 1 || pattern.starts_with('/')
+}
+
+fn split_pattern(pattern: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '/' => {
+                components.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    components.push(&pattern[start..]);
+    components.retain(|component| !component.is_empty() && *component != ".");
+    components
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+}
+
+fn parse_component(component: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => {
+                if tokens.last() == Some(&Token::AnyRun) {
+                    continue;
+                }
+                Token::AnyRun
+            }
+            '?' => Token::AnyChar,
+            '[' => parse_class(&mut chars)?,
+            '\\' => Token::Char(chars.next().ok_or("dangling escape")?),
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_class(chars: &mut std::str::Chars) -> Result<Token, &'static str> {
+    let mut negated = false;
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = chars.next().ok_or("unclosed character class")?;
+        match c {
+            '!' | '^' if first && !negated => {
+                negated = true;
+                continue;
+            }
+            // a leading `]` is a literal
+            ']' if !(first && ranges.is_empty()) => break,
+            _ => {}
+        }
+        first = false;
+        let c = if c == '\\' {
+            chars.next().ok_or("dangling escape")?
+        } else {
+            c
+        };
+        let mut lookahead = chars.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                chars.next();
+                chars.next();
+                ranges.push((c, end));
+            }
+            _ => ranges.push((c, c)),
+        }
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+/// Whether `items` match `patterns`, where patterns for which `is_any` holds match any run of
+/// items and the others the single item `matches` accepts.
+///
+/// Only the most recent wildcard is backtracked to: once a later wildcard matched, the
+/// earlier ones never need to match more. This keeps matching within
+/// `patterns.len() * items.len()` steps.
+fn match_wildcards<P, T>(
+    patterns: &[P],
+    items: &[T],
+    is_any: impl Fn(&P) -> bool,
+    matches: impl Fn(&P, &T) -> bool,
+) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // pattern index after the last wildcard and the item index it's retried from
+    let mut backtrack = None;
+    while i < items.len() {
+        match patterns.get(p) {
+            Some(pattern) if is_any(pattern) => {
+                p += 1;
+                backtrack = Some((p, i));
+            }
+            Some(pattern) if matches(pattern, &items[i]) => {
+                p += 1;
+                i += 1;
+            }
+            // the wildcard takes one more item
+            _ => match backtrack {
+                Some((after_any, start)) => {
+                    p = after_any;
+                    i = start + 1;
+                    backtrack = Some((after_any, i));
+                }
+                None => return false,
+            },
+        }
+    }
+    patterns[p..].iter().all(is_any)
+}
+
+fn match_tokens(tokens: &[Token], chars: &[char]) -> bool {
+    match_wildcards(
+        tokens,
+        chars,
+        |token| *token == Token::AnyRun,
+        |token, &c| match_char(token, c),
+    )
+}
+
+fn match_char(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(expected) => *expected == c,
+        Token::AnyChar => true,
+        Token::Class { negated, ranges } => {
+            ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+        }
+        Token::AnyRun => unreachable!("handled by match_wildcards"),
+    }
+}
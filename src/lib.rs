@@ -13,33 +13,60 @@ pub use aes256sha256::*;
 mod wasm;
 extern crate filetime_creation as ft;
 pub(crate) mod archive;
+mod archive_fs;
 mod bcj;
+mod block_cache;
+mod cancel;
 #[cfg(not(target_arch = "wasm32"))]
 mod de_funcs;
 pub(crate) mod decoders;
 mod delta;
+#[cfg(not(target_arch = "wasm32"))]
+mod dest_dir;
 #[cfg(feature = "compress")]
 mod en_funcs;
 #[cfg(feature = "compress")]
 mod encoders;
+mod entry_filter;
 mod error;
 pub(crate) mod folder;
+mod glob;
+mod limits;
+#[cfg(not(target_arch = "wasm32"))]
+mod lzma2_parallel;
 mod method_options;
 pub use method_options::*;
 mod password;
+mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+mod read_ahead;
 mod reader;
 #[cfg(feature = "compress")]
+mod source_walker;
+#[cfg(feature = "compress")]
 mod writer;
 pub use archive::*;
+pub use archive_fs::{ArchiveFs, ArchiveFsEntry};
+pub use cancel::CancellationToken;
 #[cfg(not(target_arch = "wasm32"))]
 pub use de_funcs::*;
 #[cfg(feature = "compress")]
 pub use en_funcs::*;
-pub use error::Error;
+pub use entry_filter::EntryFilter;
+pub use error::{ChecksumTarget, Error, HeaderError, UnsupportedFeature};
+pub use limits::{ExtractLimit, ExtractLimits, ReadLimit, ReadLimits};
+#[cfg(not(target_arch = "wasm32"))]
+pub use lzma2_parallel::LZMA2ParallelReader;
 pub use lzma_rust as lzma;
 pub use nt_time;
 pub use password::Password;
+pub use progress::{Progress, ProgressObserver};
+#[cfg(not(target_arch = "wasm32"))]
+pub use read_ahead::ReadAhead;
 pub use reader::BlockDecoder;
 pub use reader::SevenZReader;
+pub use reader::{EntryStatus, SalvageLimits, SalvageMethod, SalvageReport, TestReport};
+#[cfg(feature = "compress")]
+pub use source_walker::SourceWalker;
 #[cfg(feature = "compress")]
 pub use writer::*;
@@ -0,0 +1,173 @@
+use crate::{Archive, Error};
+
+/// Limits guarding extraction against archives that expand to huge amounts of data.
+///
+/// The limits are checked against the sizes declared in the archive header before anything
+/// is decoded. Entry readers never return more than the declared size of their entry, so
+/// the actual output stays within the checked limits too.
+///
+/// All limits are disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Maximum sum of the uncompressed sizes of all entries.
+    pub max_total_size: Option<u64>,
+    /// Maximum uncompressed size of a single entry.
+    pub max_entry_size: Option<u64>,
+    /// Maximum ratio of uncompressed to compressed size of a block.
+    pub max_ratio: Option<u64>,
+    /// Maximum number of entries.
+    pub max_entries: Option<usize>,
+}
+
+/// The limit of [`ExtractLimits`] that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractLimit {
+    TotalSize,
+    EntrySize,
+    Ratio,
+    Entries,
+}
+
+impl ExtractLimits {
+    /// Checks the sizes declared by `archive` against all limits.
+    pub fn check(&self, archive: &Archive) -> Result<(), Error> {
+        self.check_selected(archive, None)
+    }
+
+    /// Checks the limits against the entries flagged in `selected` and the blocks containing
+    /// them, or against all entries if `selected` is `None`.
+    pub(crate) fn check_selected(
+        &self,
+        archive: &Archive,
+        selected: Option<&[bool]>,
+    ) -> Result<(), Error> {
+        let is_selected = |file_index: usize| match selected {
+            Some(selected) => selected[file_index],
+            None => true,
+        };
+        let entries = (0..archive.files.len())
+            .filter(|&file_index| is_selected(file_index))
+            .count();
+        check(
+            ExtractLimit::Entries,
+            entries as u64,
+            self.max_entries.map(|m| m as u64),
+        )?;
+        let mut total = 0u64;
+        let mut folders = vec![false; archive.folders.len()];
+        for (file_index, file) in archive.files.iter().enumerate() {
+            if !is_selected(file_index) {
+                continue;
+            }
+            check(ExtractLimit::EntrySize, file.size, self.max_entry_size)?;
+            total = total.saturating_add(file.size);
+            if let Some(folder_index) = archive.stream_map.file_folder_index[file_index] {
+                folders[folder_index] = true;
+            }
+        }
+        check(ExtractLimit::TotalSize, total, self.max_total_size)?;
+        for folder_index in 0..archive.folders.len() {
+            if folders[folder_index] || selected.is_none() {
+                self.check_ratio(archive, folder_index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the limits that apply to reading the single entry at `file_index`.
+    pub(crate) fn check_entry(&self, archive: &Archive, file_index: usize) -> Result<(), Error> {
+        check(
+            ExtractLimit::EntrySize,
+            archive.files[file_index].size,
+            self.max_entry_size,
+        )?;
+        match archive.stream_map.file_folder_index[file_index] {
+            Some(folder_index) => self.check_ratio(archive, folder_index),
+            None => Ok(()),
+        }
+    }
+
+    fn check_ratio(&self, archive: &Archive, folder_index: usize) -> Result<(), Error> {
+        let Some(max_ratio) = self.max_ratio else {
+            return Ok(());
+        };
+        let folder = &archive.folders[folder_index];
+        let first_pack_stream_index =
+            archive.stream_map.folder_first_pack_stream_index[folder_index];
+        let pack_size: u64 = archive
+            .pack_sizes
+            .iter()
+            .skip(first_pack_stream_index)
+            .take(folder.packed_streams.len())
+            .sum();
+        let ratio = folder.get_unpack_size() / pack_size.max(1);
+        check(ExtractLimit::Ratio, ratio, Some(max_ratio))
+    }
+}
+
+fn check(limit: ExtractLimit, actual: u64, max: Option<u64>) -> Result<(), Error> {
+    match max {
+        Some(max) if actual > max => Err(Error::ExtractLimitExceeded { limit, actual, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Limits on the resources used to parse the header of an untrusted archive.
+///
+/// Counts declared in the header are checked before anything is allocated for them.
+/// The defaults accept any archive written by common tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadLimits {
+    /// Maximum size of the header, both as stored and after decoding an encoded header.
+    pub max_header_size: u64,
+    /// Maximum number of entries.
+    pub max_files: usize,
+    /// Maximum number of blocks.
+    pub max_folders: usize,
+    /// Maximum number of coders in a block.
+    pub max_coders: usize,
+    /// Maximum size in bytes of all entry names, encoded as UTF-16.
+    pub max_name_bytes: usize,
+    /// Maximum memory in bytes the coders of an encoded header may use, mostly for the LZMA
+    /// dictionary. Entries are limited by [`crate::SevenZReader::set_memory_limit`].
+    pub max_header_memory: u64,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_header_size: 1024 * 1024 * 1024,
+            max_files: 64 * 1024 * 1024,
+            max_folders: 64 * 1024 * 1024,
+            max_coders: 64,
+            max_name_bytes: 512 * 1024 * 1024,
+            max_header_memory: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The limit of [`ReadLimits`] that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadLimit {
+    HeaderSize,
+    Files,
+    Folders,
+    Coders,
+    NameBytes,
+}
+
+impl ReadLimits {
+    pub(crate) fn check(&self, limit: ReadLimit, actual: u64) -> Result<(), Error> {
+        let max = match limit {
+            ReadLimit::HeaderSize => self.max_header_size,
+            ReadLimit::Files => self.max_files as u64,
+            ReadLimit::Folders => self.max_folders as u64,
+            ReadLimit::Coders => self.max_coders as u64,
+            ReadLimit::NameBytes => self.max_name_bytes as u64,
+        };
+        if actual > max {
+            return Err(Error::ReadLimitExceeded { limit, actual, max });
+        }
+        Ok(())
+    }
+}
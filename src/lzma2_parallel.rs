@@ -0,0 +1,518 @@
+//! Parallel decoding of LZMA2 streams.
+//!
+//! Encoders such as multi-threaded 7-Zip and xz reset the dictionary between independently
+//! compressed blocks. The data following a dictionary reset does not depend on anything before
+//! it, so the stream can be split at those points and the segments decoded concurrently.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{Chain, Cursor, ErrorKind, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::lzma::{lzma2_get_memery_usage, LZMA2Reader};
+
+/// Compressed bytes looked ahead for the next dictionary reset. Longer segments are decoded on
+/// the calling thread.
+const MAX_LOOK_AHEAD: usize = 16 * 1024 * 1024;
+/// Compressed and decompressed bytes of the segments in flight, per worker thread.
+const MAX_BUFFERED_PER_THREAD: usize = 128 * 1024 * 1024;
+
+type SequentialReader<R> = LZMA2Reader<Chain<Cursor<Vec<u8>>, R>>;
+
+/// Decodes a raw LZMA2 stream using a pool of worker threads.
+///
+/// The stream is read on the calling thread, split at dictionary resets and the resulting
+/// segments are decoded by `threads` workers. Output is returned in stream order.
+/// Streams without a dictionary reset after the first chunk are decoded sequentially.
+///
+/// Up to two segments per worker are held in memory, both compressed and decompressed, within
+/// a budget of 128 MiB per worker or the limit given to [`Self::with_memory_limit`]. Segments
+/// which don't fit, or whose compressed size exceeds 16 MiB, are decoded on the calling thread.
+/// Dropping the reader stops the workers and waits for them to exit.
+pub struct LZMA2ParallelReader<R> {
+    mode: Mode<R>,
+}
+
+enum Mode<R> {
+    Sequential(Box<SequentialReader<R>>),
+    Parallel(Box<Parallel<R>>),
+}
+
+impl<R: Read> LZMA2ParallelReader<R> {
+    pub fn new(inner: R, dict_size: u32, threads: usize) -> std::io::Result<Self> {
+        Self::with_memory_limit(inner, dict_size, threads, usize::MAX / 1024)
+    }
+
+    /// Like [`Self::new`], keeping the dictionaries of the workers and the buffered segments
+    /// within `max_mem_limit_kb`.
+    pub fn with_memory_limit(
+        inner: R,
+        dict_size: u32,
+        threads: usize,
+        max_mem_limit_kb: usize,
+    ) -> std::io::Result<Self> {
+        let mut scanner = ChunkScanner::new(inner);
+        if threads <= 1 {
+            return Ok(Self::sequential(scanner, Vec::new(), dict_size));
+        }
+        let first = match scanner.next_segment(MAX_LOOK_AHEAD)? {
+            Some(segment) => segment,
+            None => return Ok(Self::sequential(scanner, Vec::new(), dict_size)),
+        };
+        if !first.complete || scanner.finished {
+            // no dictionary reset found ahead: nothing to split
+            return Ok(Self::sequential(scanner, first.data, dict_size));
+        }
+        let dictionaries_kb = (lzma2_get_memery_usage(dict_size) as usize).saturating_mul(threads);
+        let max_buffered = max_mem_limit_kb
+            .saturating_sub(dictionaries_kb)
+            .saturating_mul(1024)
+            .min(MAX_BUFFERED_PER_THREAD.saturating_mul(threads));
+        let mut parallel = Parallel::new(scanner, dict_size, threads, max_buffered);
+        parallel.held = Some(first);
+        Ok(Self {
+            mode: Mode::Parallel(Box::new(parallel)),
+        })
+    }
+
+    /// Continues with a sequential reader, replaying the bytes consumed by the scanner.
+    fn sequential(scanner: ChunkScanner<R>, mut buffered: Vec<u8>, dict_size: u32) -> Self {
+        if let Some(control) = scanner.pending_control {
+            buffered.push(control);
+        }
+        if scanner.finished {
+            buffered.push(0x00);
+        }
+        let inner = Cursor::new(buffered).chain(scanner.inner);
+        Self {
+            mode: Mode::Sequential(Box::new(LZMA2Reader::new(inner, dict_size, None))),
+        }
+    }
+
+    /// Whether the stream is decoded on the worker pool.
+    pub fn is_parallel(&self) -> bool {
+        matches!(self.mode, Mode::Parallel(_))
+    }
+}
+
+impl<R: Read> Read for LZMA2ParallelReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.mode {
+            Mode::Sequential(r) => r.read(buf),
+            Mode::Parallel(r) => r.read(buf),
+        }
+    }
+}
+
+struct Segment {
+    /// Raw chunks, without the end marker.
+    data: Vec<u8>,
+    unpack_size: u64,
+    /// `false` if scanning stopped at the look-ahead limit before the segment ended.
+    complete: bool,
+}
+
+impl Segment {
+    /// Bytes held while the segment is decoded by a worker.
+    fn footprint(&self) -> usize {
+        self.data.len().saturating_add(self.unpack_size as usize)
+    }
+}
+
+struct ChunkScanner<R> {
+    inner: R,
+    /// Control byte of a dictionary reset chunk that starts the next segment.
+    pending_control: Option<u8>,
+    finished: bool,
+}
+
+impl<R: Read> ChunkScanner<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending_control: None,
+            finished: false,
+        }
+    }
+
+    /// Reads chunks up to the next dictionary reset, the end of the stream or until
+    /// `look_ahead_limit` bytes are read.
+    fn next_segment(&mut self, look_ahead_limit: usize) -> std::io::Result<Option<Segment>> {
+        let mut segment = Segment {
+            data: Vec::new(),
+            unpack_size: 0,
+            complete: true,
+        };
+        loop {
+            let at_reset = segment.data.is_empty();
+            let unpack_size = match self.next_chunk(&mut segment.data, at_reset)? {
+                Some(size) => size,
+                None => break,
+            };
+            segment.unpack_size += unpack_size;
+            if segment.data.len() >= look_ahead_limit {
+                segment.complete = false;
+                break;
+            }
+        }
+        if segment.data.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(segment))
+    }
+
+    /// Appends the next chunk to `out` and returns its unpacked size, or returns `None` at the
+    /// end of the stream or, unless `at_reset`, at a dictionary reset.
+    fn next_chunk(&mut self, out: &mut Vec<u8>, at_reset: bool) -> std::io::Result<Option<u64>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let control = match self.pending_control.take() {
+            Some(c) => c,
+            None => match read_u8(&mut self.inner) {
+                Ok(c) => c,
+                // the end marker may be missing when the unpacked size is known, as the
+                // sequential reader never asks for it
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => 0x00,
+                Err(e) => return Err(e),
+            },
+        };
+        if control == 0x00 {
+            self.finished = true;
+            return Ok(None);
+        }
+        let dict_reset = control == 0x01 || control >= 0xE0;
+        if dict_reset && !at_reset {
+            self.pending_control = Some(control);
+            return Ok(None);
+        }
+        out.push(control);
+        let (unpack_size, pack_size) = if control >= 0x80 {
+            let mut header = [0u8; 4];
+            self.inner.read_exact(&mut header)?;
+            out.extend_from_slice(&header);
+            let unpack = (((control & 0x1F) as u64) << 16)
+                + u16::from_be_bytes([header[0], header[1]]) as u64
+                + 1;
+            let pack = u16::from_be_bytes([header[2], header[3]]) as u64 + 1;
+            if control >= 0xC0 {
+                out.push(read_u8(&mut self.inner)?);
+            }
+            (unpack, pack)
+        } else if control <= 0x02 {
+            let mut header = [0u8; 2];
+            self.inner.read_exact(&mut header)?;
+            out.extend_from_slice(&header);
+            let size = u16::from_be_bytes(header) as u64 + 1;
+            (size, size)
+        } else {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "Corrupted input data (LZMA2:2)",
+            ));
+        };
+        let start = out.len();
+        out.resize(start + pack_size as usize, 0);
+        self.inner.read_exact(&mut out[start..])?;
+        Ok(Some(unpack_size))
+    }
+}
+
+/// The chunks of the current segment following the ones already scanned, terminated by an
+/// end marker.
+struct SegmentTail<R> {
+    scanner: ChunkScanner<R>,
+    chunk: Vec<u8>,
+    pos: usize,
+    ended: bool,
+}
+
+impl<R: Read> Read for SegmentTail<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.chunk.len() {
+            if self.ended {
+                return Ok(0);
+            }
+            self.chunk.clear();
+            self.pos = 0;
+            if self.scanner.next_chunk(&mut self.chunk, false)?.is_none() {
+                self.chunk.push(0x00);
+                self.ended = true;
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+type StreamingReader<R> = LZMA2Reader<Chain<Cursor<Vec<u8>>, SegmentTail<R>>>;
+
+struct Job {
+    index: usize,
+    segment: Segment,
+    dict_size: u32,
+}
+
+type JobResult = (usize, std::io::Result<Vec<u8>>);
+
+/// A segment in stream order.
+enum Slot {
+    /// Decoded by a worker.
+    Job { index: usize, footprint: usize },
+    /// Decoded on the calling thread.
+    Stream(Segment),
+    /// Scanning the stream failed.
+    Failed(std::io::Error),
+}
+
+struct Parallel<R> {
+    /// `None` while a segment is streamed.
+    scanner: Option<ChunkScanner<R>>,
+    dict_size: u32,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    max_in_flight: usize,
+    max_buffered: usize,
+    buffered: usize,
+    /// A scanned segment waiting for buffered segments to be read.
+    held: Option<Segment>,
+    queue: VecDeque<Slot>,
+    next_index: usize,
+    done: BTreeMap<usize, std::io::Result<Vec<u8>>>,
+    streaming: Option<Box<StreamingReader<R>>>,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Parallel<R> {
+    fn new(scanner: ChunkScanner<R>, dict_size: u32, threads: usize, max_buffered: usize) -> Self {
+        let (jobs, job_rx) = channel::<Job>();
+        let (result_tx, results) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let stop = Arc::new(AtomicBool::new(false));
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let result_tx = result_tx.clone();
+                let stop = stop.clone();
+                std::thread::spawn(move || loop {
+                    let job = match job_rx.lock() {
+                        Ok(rx) => match rx.recv() {
+                            Ok(job) => job,
+                            Err(_) => return,
+                        },
+                        Err(_) => return,
+                    };
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let index = job.index;
+                    if result_tx.send((index, decode_segment(job))).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            scanner: Some(scanner),
+            dict_size,
+            jobs: Some(jobs),
+            results,
+            workers,
+            stop,
+            max_in_flight: threads * 2,
+            max_buffered,
+            buffered: 0,
+            held: None,
+            queue: VecDeque::new(),
+            next_index: 0,
+            done: BTreeMap::new(),
+            streaming: None,
+            current: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn submit(&mut self, segment: Segment) {
+        let index = self.next_index;
+        let footprint = segment.footprint();
+        self.next_index += 1;
+        self.buffered += footprint;
+        self.queue.push_back(Slot::Job { index, footprint });
+        let job = Job {
+            index,
+            segment,
+            dict_size: self.dict_size,
+        };
+        let sent = match &self.jobs {
+            Some(jobs) => jobs.send(job).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.done.insert(
+                index,
+                Err(std::io::Error::new(
+                    ErrorKind::Other,
+                    "LZMA2 worker threads stopped",
+                )),
+            );
+        }
+    }
+
+    fn fill_queue(&mut self) {
+        while self.queue.len() < self.max_in_flight {
+            // the scanner is needed to stream a segment, scanning goes on once it's read
+            if matches!(self.queue.back(), Some(Slot::Stream(_) | Slot::Failed(_))) {
+                break;
+            }
+            let segment = match self.held.take() {
+                Some(segment) => segment,
+                None => {
+                    let scanner = match &mut self.scanner {
+                        Some(scanner) => scanner,
+                        None => break,
+                    };
+                    match scanner.next_segment(MAX_LOOK_AHEAD) {
+                        Ok(Some(segment)) => segment,
+                        Ok(None) => break,
+                        Err(e) => {
+                            scanner.finished = true;
+                            self.queue.push_back(Slot::Failed(e));
+                            break;
+                        }
+                    }
+                }
+            };
+            let footprint = segment.footprint();
+            if !segment.complete || footprint > self.max_buffered {
+                self.queue.push_back(Slot::Stream(segment));
+                break;
+            }
+            if self.buffered + footprint > self.max_buffered {
+                self.held = Some(segment);
+                break;
+            }
+            self.submit(segment);
+        }
+    }
+
+    fn wait_for(&mut self, index: usize) -> std::io::Result<Vec<u8>> {
+        loop {
+            if let Some(result) = self.done.remove(&index) {
+                return result;
+            }
+            match self.results.recv() {
+                Ok((i, result)) => {
+                    self.done.insert(i, result);
+                }
+                Err(_) => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::Other,
+                        "LZMA2 worker threads stopped",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for Parallel<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(reader) = &mut self.streaming {
+                let n = reader.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+                if let Some(reader) = self.streaming.take() {
+                    let (_, tail) = reader.into_inner().into_inner();
+                    self.scanner = Some(tail.scanner);
+                }
+                continue;
+            }
+            if self.pos < self.current.len() {
+                let n = buf.len().min(self.current.len() - self.pos);
+                buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            self.fill_queue();
+            match self.queue.pop_front() {
+                None => return Ok(0),
+                Some(Slot::Job { index, footprint }) => {
+                    let result = self.wait_for(index);
+                    self.buffered -= footprint;
+                    self.current = result?;
+                    self.pos = 0;
+                }
+                Some(Slot::Stream(segment)) => {
+                    let scanner = match self.scanner.take() {
+                        Some(scanner) => scanner,
+                        None => return Ok(0),
+                    };
+                    let tail = SegmentTail {
+                        scanner,
+                        chunk: Vec::new(),
+                        pos: 0,
+                        ended: false,
+                    };
+                    let inner = Cursor::new(segment.data).chain(tail);
+                    let reader = LZMA2Reader::new(inner, self.dict_size, None);
+                    self.streaming = Some(Box::new(reader));
+                }
+                Some(Slot::Failed(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R> Drop for Parallel<R> {
+    fn drop(&mut self) {
+        // queued jobs are skipped, running ones finish their segment
+        self.stop.store(true, Ordering::Relaxed);
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn decode_segment(job: Job) -> std::io::Result<Vec<u8>> {
+    let Job {
+        segment, dict_size, ..
+    } = job;
+    let unpack_size = segment.unpack_size;
+    // distances never reach back further than the segment itself
+    let dict_size = (dict_size as u64).min(unpack_size.max(4096)) as u32;
+    let input = Cursor::new(segment.data).chain([0u8].as_slice());
+    let mut reader = LZMA2Reader::new(input, dict_size, None);
+    let mut out = Vec::with_capacity(unpack_size as usize);
+    reader.read_to_end(&mut out)?;
+    if out.len() as u64 != unpack_size {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "LZMA2 segment size mismatch",
+        ));
+    }
+    Ok(out)
+}
+
+#[inline]
+fn read_u8<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut buf = [0];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
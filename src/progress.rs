@@ -0,0 +1,186 @@
+//! Progress reporting of extraction and compression.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::SevenZArchiveEntry;
+
+/// A snapshot of the progress passed to a [`ProgressObserver`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Unpacked size of all entries to process. Extraction knows it from the header,
+    /// compression only if it was set with `SevenZWriter::set_progress_total` or by the
+    /// helper functions, which measure their sources first.
+    pub total_bytes: Option<u64>,
+    /// Unpacked bytes read from entries, or from the sources of entries.
+    pub processed_bytes: u64,
+    /// Packed bytes read from the archive, or written to it.
+    pub packed_bytes: u64,
+    /// Index of the current entry, in [`crate::Archive::files`] when extracting and in the order
+    /// entries were pushed when compressing.
+    pub entry_index: Option<usize>,
+    /// Index of the block (folder) of the current entry, `None` for entries without data.
+    pub folder_index: Option<usize>,
+}
+
+/// Receives progress events of [`SevenZReader::for_each_entries`](crate::SevenZReader::for_each_entries),
+/// of a `SevenZWriter` and of the helper functions taking an observer.
+///
+/// All methods do nothing by default.
+///
+/// # Examples
+/// ```no_run
+/// use sevenz_rust::*;
+/// struct Printer;
+/// impl ProgressObserver for Printer {
+///     fn on_progress(&mut self, progress: &Progress) {
+///         if let Some(total) = progress.total_bytes.filter(|&total| total > 0) {
+///             println!("{:.1}%", progress.processed_bytes as f64 * 100.0 / total as f64);
+///         }
+///     }
+/// }
+/// decompress_file_with_progress("sample.7z", "sample", ExtractOptions::default(), Printer)
+///     .expect("complete");
+/// ```
+pub trait ProgressObserver {
+    /// Called before the data of `entry` is processed.
+    fn on_entry_start(&mut self, _entry: &SevenZArchiveEntry, _progress: &Progress) {}
+
+    /// Called whenever a chunk of data was processed.
+    fn on_progress(&mut self, _progress: &Progress) {}
+
+    /// Called after the data of `entry` was processed.
+    ///
+    /// When compressing, `entry` has its size and CRC set. Its `compressed_size` is the packed
+    /// size of its block, and is only set on the first entry of a solid block, like
+    /// [`crate::Archive::read`] does. The entries of a solid block finish together once the
+    /// block is written.
+    fn on_entry_finish(&mut self, _entry: &SevenZArchiveEntry, _progress: &Progress) {}
+}
+
+impl<T: ProgressObserver + ?Sized> ProgressObserver for Box<T> {
+    fn on_entry_start(&mut self, entry: &SevenZArchiveEntry, progress: &Progress) {
+        (**self).on_entry_start(entry, progress)
+    }
+
+    fn on_progress(&mut self, progress: &Progress) {
+        (**self).on_progress(progress)
+    }
+
+    fn on_entry_finish(&mut self, entry: &SevenZArchiveEntry, progress: &Progress) {
+        (**self).on_entry_finish(entry, progress)
+    }
+}
+
+/// Forwards progress to an observer and keeps the running totals.
+pub(crate) struct ProgressTracker {
+    observer: Box<dyn ProgressObserver + Send>,
+    progress: Progress,
+    /// Packed bytes counted by the decoders, which may run on other threads.
+    packed_counter: Arc<AtomicU64>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(observer: Box<dyn ProgressObserver + Send>) -> Self {
+        Self {
+            observer,
+            progress: Default::default(),
+            packed_counter: Default::default(),
+        }
+    }
+
+    pub(crate) fn into_observer(self) -> Box<dyn ProgressObserver + Send> {
+        self.observer
+    }
+
+    pub(crate) fn packed_counter(&self) -> Arc<AtomicU64> {
+        self.packed_counter.clone()
+    }
+
+    pub(crate) fn set_total(&mut self, total_bytes: Option<u64>) {
+        self.progress.total_bytes = total_bytes;
+    }
+
+    #[cfg(feature = "compress")]
+    pub(crate) fn add_total(&mut self, bytes: u64) {
+        self.progress.total_bytes = Some(self.progress.total_bytes.unwrap_or(0) + bytes);
+    }
+
+    #[cfg(feature = "compress")]
+    /// Sets the packed bytes written so far.
+    pub(crate) fn set_packed(&mut self, packed_bytes: u64) {
+        self.packed_counter.store(packed_bytes, Ordering::Relaxed);
+    }
+
+    fn sync(&mut self) {
+        self.progress.packed_bytes = self.packed_counter.load(Ordering::Relaxed);
+    }
+
+    pub(crate) fn entry_started(
+        &mut self,
+        entry: &SevenZArchiveEntry,
+        entry_index: usize,
+        folder_index: Option<usize>,
+    ) {
+        self.progress.entry_index = Some(entry_index);
+        self.progress.folder_index = folder_index;
+        self.sync();
+        self.observer.on_entry_start(entry, &self.progress);
+    }
+
+    pub(crate) fn advance(&mut self, bytes: u64) {
+        self.progress.processed_bytes += bytes;
+        self.sync();
+        self.observer.on_progress(&self.progress);
+    }
+
+    pub(crate) fn entry_finished(&mut self, entry: &SevenZArchiveEntry, entry_index: usize) {
+        self.progress.entry_index = Some(entry_index);
+        self.sync();
+        self.observer.on_entry_finish(entry, &self.progress);
+    }
+}
+
+/// Reports the bytes read from an entry.
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    tracker: &'a mut ProgressTracker,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, tracker: &'a mut ProgressTracker) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<'a, R: std::io::Read> std::io::Read for ProgressReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.tracker.advance(n as u64);
+        }
+        Ok(n)
+    }
+}
+
+/// Counts the packed bytes read by a decoder.
+pub(crate) struct PackedCounter<R> {
+    inner: R,
+    counter: Arc<AtomicU64>,
+}
+
+impl<R> PackedCounter<R> {
+    pub(crate) fn new(inner: R, counter: Arc<AtomicU64>) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<R: std::io::Read> std::io::Read for PackedCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
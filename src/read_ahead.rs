@@ -0,0 +1,163 @@
+//! Background read-ahead for block decoding.
+//!
+//! A decoder thread fills a bounded ring of buffers from the decode stack while the
+//! consumer drains them, so CPU-bound decompression overlaps with I/O-bound writing.
+
+use std::{
+    io::{ErrorKind, Read},
+    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+};
+
+use crate::Error;
+
+/// Configuration of the buffer ring used by pipelined decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadAhead {
+    /// Number of buffers in flight between the decoder thread and the consumer.
+    pub buffer_count: usize,
+    /// Size in bytes of each buffer.
+    pub buffer_size: usize,
+}
+
+impl Default for ReadAhead {
+    fn default() -> Self {
+        Self {
+            buffer_count: 4,
+            buffer_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ReadAhead {
+    pub fn new(buffer_count: usize, buffer_size: usize) -> Self {
+        Self {
+            buffer_count: buffer_count.max(1),
+            buffer_size: buffer_size.max(1),
+        }
+    }
+}
+
+/// Creates the two halves of a read-ahead pipe.
+pub(crate) fn pipe(read_ahead: ReadAhead) -> (PipeReader, PipeWriter) {
+    let ReadAhead {
+        buffer_count,
+        buffer_size,
+    } = ReadAhead::new(read_ahead.buffer_count, read_ahead.buffer_size);
+    let (filled_tx, filled_rx) = sync_channel(buffer_count);
+    let (free_tx, free_rx) = channel();
+    for _ in 0..buffer_count {
+        let _ = free_tx.send(vec![0; buffer_size]);
+    }
+    (
+        PipeReader {
+            filled: filled_rx,
+            free: free_tx,
+            current: Vec::new(),
+            pos: 0,
+            eof: false,
+        },
+        PipeWriter {
+            filled: filled_tx,
+            free: free_rx,
+        },
+    )
+}
+
+/// Producer half of the pipe, driven by the decoder thread.
+pub(crate) struct PipeWriter {
+    filled: SyncSender<std::io::Result<Vec<u8>>>,
+    free: Receiver<Vec<u8>>,
+}
+
+impl PipeWriter {
+    /// Drains `source` into the ring until it is exhausted or the consumer goes away.
+    ///
+    /// Read errors, including CRC failures, are handed over to the consumer as is.
+    pub(crate) fn run<S: Read>(self, mut source: S) {
+        loop {
+            let mut buf = match self.free.recv() {
+                Ok(buf) => buf,
+                // The consumer dropped its half: nothing left to do.
+                Err(_) => return,
+            };
+            let capacity = buf.capacity().max(1);
+            buf.resize(capacity, 0);
+            let mut filled = 0;
+            while filled < buf.len() {
+                match source.read(&mut buf[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        if filled > 0 {
+                            buf.truncate(filled);
+                            if self.filled.send(Ok(buf)).is_err() {
+                                return;
+                            }
+                        }
+                        let _ = self.filled.send(Err(e));
+                        return;
+                    }
+                }
+            }
+            if filled == 0 {
+                return;
+            }
+            buf.truncate(filled);
+            let eof = filled < capacity;
+            if self.filled.send(Ok(buf)).is_err() || eof {
+                return;
+            }
+        }
+    }
+
+    /// Reports a failure to build the decode stack to the consumer.
+    pub(crate) fn fail(self, e: &Error) {
+        let _ = self
+            .filled
+            .send(Err(std::io::Error::new(ErrorKind::Other, e.to_string())));
+    }
+}
+
+/// Consumer half of the pipe.
+pub(crate) struct PipeReader {
+    filled: Receiver<std::io::Result<Vec<u8>>>,
+    free: Sender<Vec<u8>>,
+    current: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos >= self.current.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            match self.filled.recv() {
+                Ok(Ok(next)) => {
+                    let used = std::mem::replace(&mut self.current, next);
+                    self.pos = 0;
+                    if used.capacity() > 0 {
+                        let _ = self.free.send(used);
+                    }
+                }
+                Ok(Err(e)) => {
+                    self.eof = true;
+                    return Err(e);
+                }
+                Err(_) => {
+                    self.eof = true;
+                    return Ok(0);
+                }
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -1,17 +1,45 @@
 use std::{
+    cell::{Cell, RefCell},
     fs::File,
     io::{ErrorKind, Read, Seek, SeekFrom},
     path::Path,
+    rc::Rc,
+    sync::Arc,
 };
 
 use bit_set::BitSet;
 use crc::Crc;
 
-use crate::{archive::*, decoders::add_decoder, error::Error, folder::*, password::Password};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::read_ahead::ReadAhead;
+use crate::{
+    archive::*,
+    block_cache::{BlockCache, CachedEntryReader},
+    cancel::{CancellableReader, CancellationToken},
+    decoders::{add_decoder, check_folder_memory, folder_memory_usage_kb, DecodeOptions},
+    entry_filter::EntryFilter,
+    error::{ChecksumTarget, Error, HeaderError, UnsupportedFeature},
+    folder::*,
+    limits::{ExtractLimits, ReadLimit, ReadLimits},
+    password::Password,
+    progress::{PackedCounter, ProgressObserver, ProgressReader, ProgressTracker},
+};
+
+mod salvage;
+mod seekable;
+mod verify;
+
+pub use salvage::{SalvageLimits, SalvageMethod, SalvageReport};
+pub(crate) use seekable::seek_position;
+use seekable::{DecodingEntryReader, EntrySeekReader, StoredEntryReader};
+use verify::is_encrypted;
+pub use verify::{EntryStatus, TestReport};
+
 pub(crate) const CRC32: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-const MAX_MEM_LIMIT_KB: usize = usize::MAX / 1024;
 
-pub(crate) trait SeedRead: Read + Seek {}
+/// Largest ratio of decoded to packed size accepted for an encoded header, beyond what LZMA
+/// reaches on the most repetitive data.
+const MAX_HEADER_RATIO: usize = 8 * 1024;
 
 pub struct BoundedReader<R: Read> {
     inner: R,
@@ -59,8 +87,6 @@ pub struct SeekableBoundedReader<R: Read + Seek> {
     bounds: (u64, u64),
 }
 
-impl<R: Read + Seek> SeedRead for SeekableBoundedReader<R> {}
-
 impl<R: Read + Seek> Seek for SeekableBoundedReader<R> {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         let new_pos = match pos {
@@ -110,15 +136,17 @@ struct Crc32VerifyingReader<R> {
     crc_digest: crc::Digest<'static, u32>,
     expected_value: u64,
     remaining: i64,
+    target: ChecksumTarget,
 }
 
 impl<R: Read> Crc32VerifyingReader<R> {
-    fn new(inner: R, remaining: usize, expected_value: u64) -> Self {
+    fn new(inner: R, remaining: usize, expected_value: u64, target: ChecksumTarget) -> Self {
         Self {
             inner,
             crc_digest: CRC32.digest(),
             expected_value,
             remaining: remaining as i64,
+            target,
         }
     }
 }
@@ -137,8 +165,14 @@ impl<R: Read> Read for Crc32VerifyingReader<R> {
             let d = std::mem::replace(&mut self.crc_digest, CRC32.digest()).finalize();
             if d as u64 != self.expected_value {
                 return Err(std::io::Error::new(
-                    ErrorKind::Other,
-                    Error::ChecksumVerificationFailed,
+                    ErrorKind::InvalidData,
+                    Error::ChecksumMismatch {
+                        target: self.target,
+                        expected: self.expected_value as u32,
+                        actual: d,
+                        entry: None,
+                        folder: None,
+                    },
                 ));
             }
         }
@@ -187,6 +221,20 @@ impl Archive {
         reader: &mut R,
         reader_len: u64,
         password: &[u8],
+    ) -> Result<Archive, Error> {
+        Self::read_with_limits(reader, reader_len, password, ReadLimits::default())
+    }
+
+    /// Like [`Archive::read`], parsing the header within `limits`.
+    ///
+    /// Use this with tight limits to read archives from untrusted sources, failing with
+    /// [`Error::ReadLimitExceeded`] before large amounts of memory are allocated.
+    /// The archive can then be extracted with [`SevenZReader::from_archive`].
+    pub fn read_with_limits<R: Read + Seek>(
+        reader: &mut R,
+        reader_len: u64,
+        password: &[u8],
+        limits: ReadLimits,
     ) -> Result<Archive, Error> {
         let mut signature = [0; 6];
         reader.read_exact(&mut signature).map_err(Error::io)?;
@@ -219,12 +267,26 @@ impl Archive {
         };
         if header_valid {
             let start_header = Self::read_start_header(reader, start_header_crc)?;
-            Self::init_archive(reader, start_header, password, true)
+            Self::init_archive(reader, reader_len, start_header, password, true, limits)
         } else {
-            Self::try_to_locale_end_header(reader, reader_len, password)
+            Self::try_to_locale_end_header(reader, reader_len, password, limits)
         }
     }
 
+    /// Estimates the memory in bytes used by the coders to decode the block at `folder_index`
+    /// on a single thread, so archives can be rejected before decoding starts.
+    pub fn estimate_decode_memory(&self, folder_index: usize) -> Result<u64, Error> {
+        let folder = self
+            .folders
+            .get(folder_index)
+            .ok_or(Error::IndexOutOfRange {
+                what: "folder",
+                index: folder_index,
+            })?;
+        let usage_kb = folder_memory_usage_kb(folder, &DecodeOptions::default())?;
+        Ok(usage_kb as u64 * 1024)
+    }
+
     fn read_start_header<R: Read>(
         reader: &mut R,
         start_header_crc: u32,
@@ -233,7 +295,13 @@ impl Archive {
         reader.read_exact(&mut buf).map_err(Error::io)?;
         let value = crc32_cksum(&buf);
         if value != start_header_crc {
-            return Err(Error::ChecksumVerificationFailed);
+            return Err(Error::ChecksumMismatch {
+                target: ChecksumTarget::StartHeader,
+                expected: start_header_crc,
+                actual: value,
+                entry: None,
+                folder: None,
+            });
         }
         let mut buf_read = buf.as_slice();
         let offset = read_u64le(&mut buf_read)?;
@@ -247,7 +315,11 @@ impl Archive {
         })
     }
 
-    fn read_header<R: Read + Seek>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_header<R: Read + Seek>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         let mut nid = read_u8(header)?;
         if nid == K_ARCHIVE_PROPERTIES {
             Self::read_archive_properties(header)?;
@@ -255,18 +327,18 @@ impl Archive {
         }
 
         if nid == K_ADDITIONAL_STREAMS_INFO {
-            return Err(Error::other("Additional streams unsupported"));
+            return Err(Error::unsupported(UnsupportedFeature::AdditionalStreams));
         }
         if nid == K_MAIN_STREAMS_INFO {
-            Self::read_streams_info(header, archive)?;
+            Self::read_streams_info(header, archive, limits)?;
             nid = read_u8(header)?;
         }
         if nid == K_FILES_INFO {
-            Self::read_files_info(header, archive)?;
+            Self::read_files_info(header, archive, limits)?;
             nid = read_u8(header)?;
         }
         if nid != K_END {
-            return Err(Error::BadTerminatedheader(nid));
+            return Err(unexpected_property("header", "kEnd", nid));
         }
 
         Ok(())
@@ -288,6 +360,7 @@ impl Archive {
         reader: &mut R,
         reader_len: u64,
         password: &[u8],
+        limits: ReadLimits,
     ) -> Result<Self, Error> {
         let search_limit = 1024 * 1024;
         let prev_data_size = reader.stream_position().map_err(Error::io)? + 20;
@@ -311,29 +384,40 @@ impl Archive {
                     next_header_size: reader_len - pos,
                     next_header_crc: 0,
                 };
-                let result = Self::init_archive(reader, start_header, password, false)?;
+                let result =
+                    Self::init_archive(reader, reader_len, start_header, password, false, limits)?;
 
                 if !result.files.is_empty() {
                     return Ok(result);
                 }
             }
         }
-        Err(Error::other(
-            "Start header corrupt and unable to guess end header",
-        ))
+        Err(Error::header(HeaderError::EndHeaderNotFound))
     }
 
     fn init_archive<R: Read + Seek>(
         reader: &mut R,
+        reader_len: u64,
         start_header: StartHeader,
         password: &[u8],
         verify_crc: bool,
+        limits: ReadLimits,
     ) -> Result<Self, Error> {
         if start_header.next_header_size > usize::MAX as u64 {
-            return Err(Error::other(format!(
-                "Cannot handle next_header_size {}",
-                start_header.next_header_size
-            )));
+            return Err(Error::header(HeaderError::ValueOutOfRange {
+                field: "next header size",
+                value: start_header.next_header_size,
+            }));
+        }
+        limits.check(ReadLimit::HeaderSize, start_header.next_header_size)?;
+        let header_end = SIGNATURE_HEADER_SIZE
+            .saturating_add(start_header.next_header_offset)
+            .saturating_add(start_header.next_header_size);
+        if header_end > reader_len {
+            return Err(Error::header(HeaderError::HeaderOutOfBounds {
+                end: header_end,
+                archive_len: reader_len,
+            }));
         }
 
         let next_header_size_int = start_header.next_header_size as usize;
@@ -346,21 +430,44 @@ impl Archive {
 
         let mut buf = vec![0; next_header_size_int];
         reader.read_exact(&mut buf).map_err(Error::io)?;
-        if verify_crc && crc32_cksum(&buf) as u64 != start_header.next_header_crc {
-            return Err(Error::NextHeaderCrcMismatch);
+        let crc = crc32_cksum(&buf);
+        if verify_crc && crc as u64 != start_header.next_header_crc {
+            return Err(Error::ChecksumMismatch {
+                target: ChecksumTarget::NextHeader,
+                expected: start_header.next_header_crc as u32,
+                actual: crc,
+                entry: None,
+                folder: None,
+            });
         }
 
         let mut archive = Archive::default();
         let mut buf_reader = buf.as_slice();
         let mut nid = read_u8(&mut buf_reader)?;
         let mut header = if nid == K_ENCODED_HEADER {
-            let (mut out_reader, buf_size) =
-                Self::read_encoded_header(&mut buf_reader, reader, &mut archive, password)?;
+            let header_limits = HeaderLimits::new(limits, buf.len());
+            let (out_reader, buf_size) = Self::read_encoded_header(
+                &mut buf_reader,
+                reader,
+                &mut archive,
+                password,
+                header_limits,
+                true,
+            )
+            .map_err(|e| e.at_offset((header_limits.header_size - buf_reader.len()) as u64))?;
+            limits.check(ReadLimit::HeaderSize, buf_size as u64)?;
+            // grows with the decoded data instead of trusting the declared size
             buf.clear();
-            buf.resize(buf_size, 0);
             out_reader
-                .read_exact(&mut buf)
+                .take(buf_size as u64)
+                .read_to_end(&mut buf)
                 .map_err(|e| Error::bad_password(e, !password.is_empty()))?;
+            if buf.len() < buf_size {
+                return Err(Error::bad_password(
+                    std::io::ErrorKind::UnexpectedEof.into(),
+                    !password.is_empty(),
+                ));
+            }
             archive = Archive::default();
             buf_reader = buf.as_slice();
             nid = read_u8(&mut buf_reader)?;
@@ -368,11 +475,13 @@ impl Archive {
         } else {
             buf_reader
         };
+        let header_limits = HeaderLimits::new(limits, header.len());
         let mut header = std::io::Cursor::new(&mut header);
         if nid == K_HEADER {
-            Self::read_header(&mut header, &mut archive)?;
+            Self::read_header(&mut header, &mut archive, header_limits)
+                .map_err(|e| e.at_offset(header.position()))?;
         } else {
-            return Err(Error::other("Broken or unsupported archive: no Header"));
+            return Err(Error::header(HeaderError::MissingHeader));
         }
         Ok(archive)
     }
@@ -382,16 +491,18 @@ impl Archive {
         reader: &'r mut RI,
         archive: &mut Archive,
         password: &[u8],
+        limits: HeaderLimits,
+        verify_crc: bool,
     ) -> Result<(Box<dyn Read + 'r>, usize), Error> {
-        Self::read_streams_info(header, archive)?;
+        Self::read_streams_info(header, archive, limits)?;
         let folder = archive
             .folders
             .first()
-            .ok_or(Error::other("no folders, can't read encoded header"))?;
+            .ok_or(Error::header(HeaderError::MissingHeaderStreams))?;
         let first_pack_stream_index = 0;
         let folder_offset = SIGNATURE_HEADER_SIZE + archive.pack_pos;
         if archive.pack_sizes.is_empty() {
-            return Err(Error::other("no packed streams, can't read encoded header"));
+            return Err(Error::header(HeaderError::MissingHeaderStreams));
         }
 
         reader
@@ -400,22 +511,31 @@ impl Archive {
         let coder_len = folder.coders.len();
         let unpack_size = folder.get_unpack_size() as usize;
         let pack_size = archive.pack_sizes[first_pack_stream_index] as usize;
+        if unpack_size > pack_size.saturating_mul(MAX_HEADER_RATIO) {
+            return Err(Error::header(HeaderError::ValueOutOfRange {
+                field: "encoded header size",
+                value: unpack_size as u64,
+            }));
+        }
+        let options = DecodeOptions {
+            max_mem_limit_kb: (limits.limits.max_header_memory / 1024) as usize,
+            ..Default::default()
+        };
+        check_folder_memory(folder, &options)?;
         let input_reader =
             SeekableBoundedReader::new(reader, (folder_offset, folder_offset + pack_size as u64));
         let mut decoder: Box<dyn Read> = Box::new(input_reader);
         let mut decoder = if coder_len > 0 {
             for (index, coder) in folder.ordered_coder_iter() {
                 if coder.num_in_streams != 1 || coder.num_out_streams != 1 {
-                    return Err(Error::other(
-                        "Multi input/output stream coders are not yet supported",
-                    ));
+                    return Err(Error::unsupported(UnsupportedFeature::MultiStreamCoders));
                 }
                 let next = crate::decoders::add_decoder(
                     decoder,
                     folder.get_unpack_size_at_index(index) as usize,
                     coder,
                     password,
-                    MAX_MEM_LIMIT_KB,
+                    &options,
                 )?;
                 decoder = Box::new(next);
             }
@@ -423,39 +543,54 @@ impl Archive {
         } else {
             decoder
         };
-        if folder.has_crc {
-            decoder = Box::new(Crc32VerifyingReader::new(decoder, unpack_size, folder.crc));
+        if verify_crc && folder.has_crc {
+            decoder = Box::new(Crc32VerifyingReader::new(
+                decoder,
+                unpack_size,
+                folder.crc,
+                ChecksumTarget::Folder,
+            ));
         }
 
         Ok((decoder, unpack_size))
     }
 
-    fn read_streams_info<R: Read>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_streams_info<R: Read>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         let mut nid = read_u8(header)?;
         if nid == K_PACK_INFO {
-            Self::read_pack_info(header, archive)?;
+            Self::read_pack_info(header, archive, limits)?;
             nid = read_u8(header)?;
         }
 
         if nid == K_UNPACK_INFO {
-            Self::read_unpack_info(header, archive)?;
+            Self::read_unpack_info(header, archive, limits)?;
             nid = read_u8(header)?;
         } else {
             archive.folders.clear();
         }
         if nid == K_SUB_STREAMS_INFO {
-            Self::read_sub_streams_info(header, archive)?;
+            Self::read_sub_streams_info(header, archive, limits)?;
             nid = read_u8(header)?;
         }
         if nid != K_END {
-            return Err(Error::BadTerminatedStreamsInfo(nid));
+            return Err(unexpected_property("streams info", "kEnd", nid));
         }
 
         Ok(())
     }
 
-    fn read_files_info<R: Read + Seek>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_files_info<R: Read + Seek>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         let num_files = read_usize(header, "num files")?;
+        limits.check(ReadLimit::Files, num_files)?;
+        limits.check_fits("num files", num_files, 1)?;
         let mut files: Vec<SevenZArchiveEntry> = vec![Default::default(); num_files];
 
         let mut is_empty_stream: Option<BitSet> = None;
@@ -475,9 +610,10 @@ impl Archive {
                     let n = if let Some(s) = &is_empty_stream {
                         s.len()
                     } else {
-                        return Err(Error::other(
-                            "Header format error: kEmptyStream must appear before kEmptyFile",
-                        ));
+                        return Err(Error::header(HeaderError::MisorderedProperty {
+                            property: "kEmptyFile",
+                            requires: "kEmptyStream",
+                        }));
                     };
                     is_empty_file = Some(read_bits(header, n)?);
                 }
@@ -485,44 +621,50 @@ impl Archive {
                     let n = if let Some(s) = is_empty_stream.as_ref() {
                         s.len()
                     } else {
-                        return Err(Error::other(
-                            "Header format error: kEmptyStream must appear before kEmptyFile",
-                        ));
+                        return Err(Error::header(HeaderError::MisorderedProperty {
+                            property: "kAnti",
+                            requires: "kEmptyStream",
+                        }));
                     };
                     is_anti = Some(read_bits(header, n)?);
                 }
                 K_NAME => {
                     let external = read_u8(header)?;
                     if external != 0 {
-                        return Err(Error::other("Not implemented:external != 0"));
+                        return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                            property: "kName",
+                        }));
                     }
-                    if (size - 1) & 1 != 0 {
-                        return Err(Error::other("file names length invalid"));
+                    if size == 0 || (size - 1) & 1 != 0 {
+                        return Err(Error::header(HeaderError::InvalidNames));
                     }
 
                     let size = assert_usize(size, "file names length")?;
+                    limits.check(ReadLimit::NameBytes, size - 1)?;
                     // let mut names = vec![0u8; size - 1];
                     // header.read_exact(&mut names).map_err(Error::io)?;
                     let names_reader = NamesReader::new(header, size - 1);
 
                     let mut next_file = 0;
                     for s in names_reader {
-                        files[next_file].name = s?;
+                        let file = files
+                            .get_mut(next_file)
+                            .ok_or(Error::header(HeaderError::InvalidNames))?;
+                        file.set_raw_name(s?);
                         next_file += 1;
                     }
 
                     if next_file != files.len() {
-                        return Err(Error::other("Error parsing file names"));
+                        return Err(Error::header(HeaderError::InvalidNames));
                     }
                 }
                 K_C_TIME => {
                     let times_defined = read_all_or_bits(header, num_files)?;
                     let external = read_u8(header)?;
                     if external != 0 {
-                        return Err(Error::other(format!(
-                            "kCTime Unimplemented:external={}",
-                            external
-                        )));
+                        return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                            property: "kCTime",
+                        }));
                     }
                     for i in 0..num_files {
                         files[i].has_creation_date = times_defined.contains(i);
@@ -535,10 +677,9 @@ impl Archive {
                     let times_defined = read_all_or_bits(header, num_files)?;
                     let external = read_u8(header)?;
                     if external != 0 {
-                        return Err(Error::other(format!(
-                            "kATime Unimplemented:external={}",
-                            external
-                        )));
+                        return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                            property: "kATime",
+                        }));
                     }
                     for i in 0..num_files {
                         files[i].has_access_date = times_defined.contains(i);
@@ -551,10 +692,9 @@ impl Archive {
                     let times_defined = read_all_or_bits(header, num_files)?;
                     let external = read_u8(header)?;
                     if external != 0 {
-                        return Err(Error::other(format!(
-                            "kMTime Unimplemented:external={}",
-                            external
-                        )));
+                        return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                            property: "kMTime",
+                        }));
                     }
                     for i in 0..num_files {
                         files[i].has_last_modified_date = times_defined.contains(i);
@@ -567,10 +707,9 @@ impl Archive {
                     let times_defined = read_all_or_bits(header, num_files)?;
                     let external = read_u8(header)?;
                     if external != 0 {
-                        return Err(Error::other(format!(
-                            "kWinAttributes Unimplemented:external={}",
-                            external
-                        )));
+                        return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                            property: "kWinAttributes",
+                        }));
                     }
                     for i in 0..num_files {
                         files[i].has_windows_attributes = times_defined.contains(i);
@@ -579,7 +718,7 @@ impl Archive {
                         }
                     }
                 }
-                K_START_POS => return Err(Error::other("kStartPos is unsupported, please report")),
+                K_START_POS => return Err(Error::unsupported(UnsupportedFeature::StartPos)),
                 K_DUMMY => {
                     header
                         .seek(SeekFrom::Current(size as i64))
@@ -605,12 +744,13 @@ impl Archive {
                 let sub_stream_info = if let Some(s) = archive.sub_streams_info.as_ref() {
                     s
                 } else {
-                    return Err(Error::other(
-                        "Archive contains file with streams but no subStreamsInfo",
-                    ));
+                    return Err(Error::header(HeaderError::MissingSubStreamsInfo));
                 };
                 file.is_directory = false;
                 file.is_anti_item = false;
+                if non_empty_file_counter >= sub_stream_info.unpack_sizes.len() {
+                    return Err(Error::header(HeaderError::TooManyStreamEntries));
+                }
                 file.has_crc = sub_stream_info.has_crc.contains(non_empty_file_counter);
                 file.crc = sub_stream_info.crcs[non_empty_file_counter];
                 file.size = sub_stream_info.unpack_sizes[non_empty_file_counter];
@@ -673,7 +813,7 @@ impl Archive {
                     next_folder_index += 1;
                 }
                 if next_folder_index >= archive.folders.len() {
-                    return Err(Error::other("Too few folders in archive"));
+                    return Err(Error::header(HeaderError::TooFewFolders));
                 }
             }
             stream_map.file_folder_index[i] = Some(next_folder_index);
@@ -685,7 +825,11 @@ impl Archive {
             if stream_map.folder_first_file_index[next_folder_index] == i {
                 let first_pack_stream_index =
                     stream_map.folder_first_pack_stream_index[next_folder_index];
-                let pack_size = archive.pack_sizes[first_pack_stream_index];
+                let pack_size = archive
+                    .pack_sizes
+                    .get(first_pack_stream_index)
+                    .copied()
+                    .ok_or(Error::header(HeaderError::TooFewPackStreams))?;
 
                 archive.files[i].compressed_size = pack_size;
             }
@@ -703,9 +847,14 @@ impl Archive {
         Ok(())
     }
 
-    fn read_pack_info<R: Read>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_pack_info<R: Read>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         archive.pack_pos = read_u64(header)?;
         let num_pack_streams = read_usize(header, "num pack streams")?;
+        limits.check_fits("num pack streams", num_pack_streams, 1)?;
         let mut nid = read_u8(header)?;
         if nid == K_SIZE {
             archive.pack_sizes = vec![0u64; num_pack_streams];
@@ -727,38 +876,44 @@ impl Archive {
         }
 
         if nid != K_END {
-            return Err(Error::BadTerminatedPackInfo(nid));
+            return Err(unexpected_property("pack info", "kEnd", nid));
         }
 
         Ok(())
     }
-    fn read_unpack_info<R: Read>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_unpack_info<R: Read>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         let nid = read_u8(header)?;
         if nid != K_FOLDER {
-            return Err(Error::other(format!("Expected kFolder, got {}", nid)));
+            return Err(unexpected_property("unpack info", "kFolder", nid));
         }
         let num_folders = read_usize(header, "num folders")?;
+        limits.check(ReadLimit::Folders, num_folders)?;
+        limits.check_fits("num folders", num_folders, 8)?;
 
         archive.folders.reserve_exact(num_folders);
         let external = read_u8(header)?;
         if external != 0 {
-            return Err(Error::ExternalUnsupported);
+            return Err(Error::unsupported(UnsupportedFeature::ExternalData {
+                property: "kFolder",
+            }));
         }
 
         for _ in 0..num_folders {
-            archive.folders.push(Self::read_folder(header)?);
+            archive.folders.push(Self::read_folder(header, limits)?);
         }
 
         let nid = read_u8(header)?;
         if nid != K_CODERS_UNPACK_SIZE {
-            return Err(Error::other(format!(
-                "Expected kCodersUnpackSize, got {}",
-                nid
-            )));
+            return Err(unexpected_property("unpack info", "kCodersUnpackSize", nid));
         }
 
         for folder in archive.folders.iter_mut() {
             let tos = folder.total_output_streams;
+            limits.check_fits("total output streams", tos, 8)?;
             folder.unpack_sizes.reserve_exact(tos);
             for _ in 0..tos {
                 folder.unpack_sizes.push(read_u64(header)?);
@@ -779,13 +934,17 @@ impl Archive {
             nid = read_u8(header)?;
         }
         if nid != K_END {
-            return Err(Error::BadTerminatedUnpackInfo);
+            return Err(unexpected_property("unpack info", "kEnd", nid));
         }
 
         Ok(())
     }
 
-    fn read_sub_streams_info<R: Read>(header: &mut R, archive: &mut Archive) -> Result<(), Error> {
+    fn read_sub_streams_info<R: Read>(
+        header: &mut R,
+        archive: &mut Archive,
+        limits: HeaderLimits,
+    ) -> Result<(), Error> {
         for folder in archive.folders.iter_mut() {
             folder.num_unpack_sub_streams = 1;
         }
@@ -797,10 +956,13 @@ impl Archive {
             for folder in archive.folders.iter_mut() {
                 let num_streams = read_usize(header, "numStreams")?;
                 folder.num_unpack_sub_streams = num_streams;
-                total_unpack_streams += num_streams;
+                total_unpack_streams = total_unpack_streams.saturating_add(num_streams);
             }
             nid = read_u8(header)?;
         }
+        // every unpack stream is the content of a file
+        limits.check(ReadLimit::Files, total_unpack_streams)?;
+        limits.check_fits("num unpack streams", total_unpack_streams, 1)?;
 
         let mut sub_streams_info = SubStreamsInfo::default();
         sub_streams_info
@@ -822,13 +984,11 @@ impl Archive {
                     let size = read_u64(header)?;
                     sub_streams_info.unpack_sizes[next_unpack_stream] = size;
                     next_unpack_stream += 1;
-                    sum += size;
+                    sum = size.saturating_add(sum);
                 }
             }
             if sum > folder.get_unpack_size() {
-                return Err(Error::other(
-                    "sum of unpack sizes of folder exceeds total unpack size",
-                ));
+                return Err(Error::header(HeaderError::SubStreamSizesExceedFolder));
             }
             sub_streams_info.unpack_sizes[next_unpack_stream] = folder.get_unpack_size() - sum;
             next_unpack_stream += 1;
@@ -877,17 +1037,19 @@ impl Archive {
         }
 
         if nid != K_END {
-            return Err(Error::BadTerminatedSubStreamsInfo);
+            return Err(unexpected_property("sub streams info", "kEnd", nid));
         }
 
         archive.sub_streams_info = Some(sub_streams_info);
         Ok(())
     }
 
-    fn read_folder<R: Read>(header: &mut R) -> Result<Folder, Error> {
+    fn read_folder<R: Read>(header: &mut R, limits: HeaderLimits) -> Result<Folder, Error> {
         let mut folder = Folder::default();
 
         let num_coders = read_usize(header, "num coders")?;
+        limits.check(ReadLimit::Coders, num_coders)?;
+        limits.check_fits("num coders", num_coders, 8)?;
         let mut coders = Vec::with_capacity(num_coders);
         let mut total_in_streams = 0;
         let mut total_out_streams = 0;
@@ -911,10 +1073,11 @@ impl Archive {
                 coder.num_in_streams = read_u64(header)?;
                 coder.num_out_streams = read_u64(header)?;
             }
-            total_in_streams += coder.num_in_streams;
-            total_out_streams += coder.num_out_streams;
+            total_in_streams = coder.num_in_streams.saturating_add(total_in_streams);
+            total_out_streams = coder.num_out_streams.saturating_add(total_out_streams);
             if has_attributes {
                 let properties_size = read_usize(header, "properties size")?;
+                limits.check_fits("properties size", properties_size, 8)?;
                 let mut props = vec![0u8; properties_size];
                 header.read(&mut props).map_err(Error::io)?;
                 coder.properties = props;
@@ -922,7 +1085,7 @@ impl Archive {
             coders.push(coder);
             // would need to keep looping as above:
             if more_alternative_methods {
-                return Err(Error::other("Alternative methods are unsupported, please report. The reference implementation doesn't support them either."));
+                return Err(Error::unsupported(UnsupportedFeature::AlternativeMethods));
             }
         }
         folder.coders = coders;
@@ -932,9 +1095,10 @@ impl Archive {
         folder.total_output_streams = total_out_streams;
 
         if total_out_streams == 0 {
-            return Err(Error::other("Total output streams can't be 0"));
+            return Err(Error::header(HeaderError::NoOutputStreams));
         }
         let num_bind_pairs = total_out_streams - 1;
+        limits.check_fits("num bind pairs", num_bind_pairs, 16)?;
         let mut bind_pairs = Vec::with_capacity(num_bind_pairs);
         for _ in 0..num_bind_pairs {
             let bp = BindPair {
@@ -946,11 +1110,12 @@ impl Archive {
         folder.bind_pairs = bind_pairs;
 
         if total_in_streams < num_bind_pairs {
-            return Err(Error::other(
-                "Total input streams can't be less than the number of bind pairs",
-            ));
+            return Err(Error::header(HeaderError::TooFewInputStreams));
         }
         let num_packed_streams = total_in_streams - num_bind_pairs;
+        if num_packed_streams > 1 {
+            limits.check_fits("num packed streams", num_packed_streams, 8)?;
+        }
         let mut packed_streams = vec![0; num_packed_streams];
         if num_packed_streams == 1 {
             let mut index = u64::MAX;
@@ -961,7 +1126,9 @@ impl Archive {
                 }
             }
             if index == u64::MAX {
-                return Err(Error::other("Couldn't find stream's bind pair index"));
+                return Err(Error::header(HeaderError::MissingBindPair {
+                    in_stream: total_in_streams,
+                }));
             }
             packed_streams[0] = index;
         } else {
@@ -975,21 +1142,65 @@ impl Archive {
     }
 }
 
+/// [`ReadLimits`] applied to a header of `header_size` bytes.
+#[derive(Debug, Clone, Copy)]
+struct HeaderLimits {
+    limits: ReadLimits,
+    header_size: usize,
+}
+
+impl HeaderLimits {
+    fn new(limits: ReadLimits, header_size: usize) -> Self {
+        Self {
+            limits,
+            header_size,
+        }
+    }
+
+    #[inline]
+    fn check(&self, limit: ReadLimit, actual: usize) -> Result<(), Error> {
+        self.limits.check(limit, actual as u64)
+    }
+
+    /// Fails if `count` items taking at least `min_bits` each can't be stored in the header,
+    /// before anything is allocated for them.
+    fn check_fits(&self, field: &'static str, count: usize, min_bits: usize) -> Result<(), Error> {
+        if count.saturating_mul(min_bits) > self.header_size.saturating_mul(8) {
+            return Err(Error::header(HeaderError::ValueOutOfRange {
+                field,
+                value: count as u64,
+            }));
+        }
+        Ok(())
+    }
+}
+
+fn unexpected_property(section: &'static str, expected: &'static str, found: u8) -> Error {
+    Error::header(HeaderError::UnexpectedProperty {
+        section,
+        expected,
+        found,
+    })
+}
+
 #[inline]
 fn crc32_cksum(data: &[u8]) -> u32 {
     CRC32.checksum(data)
 }
 
 #[inline]
-fn read_usize<R: Read>(reader: &mut R, field: &str) -> Result<usize, Error> {
+fn read_usize<R: Read>(reader: &mut R, field: &'static str) -> Result<usize, Error> {
     let size = read_u64(reader)?;
     assert_usize(size, field)
 }
 
 #[inline]
-fn assert_usize(size: u64, field: &str) -> Result<usize, Error> {
+fn assert_usize(size: u64, field: &'static str) -> Result<usize, Error> {
     if size > usize::MAX as u64 {
-        return Err(Error::other(format!("Cannot handle {} {}", field, size)));
+        return Err(Error::header(HeaderError::ValueOutOfRange {
+            field,
+            value: size,
+        }));
     }
     Ok(size as usize)
 }
@@ -1079,13 +1290,12 @@ impl<'a, R: Read> NamesReader<'a, R> {
 }
 
 impl<'a, R: Read> Iterator for NamesReader<'a, R> {
-    type Item = Result<String, Error>;
+    type Item = Result<Vec<u16>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.max_bytes <= self.read_bytes {
             return None;
         }
-        self.cache.clear();
         let mut buf = [0; 2];
         while self.read_bytes < self.max_bytes {
             let r = self.reader.read_exact(&mut buf).map_err(Error::io);
@@ -1100,7 +1310,7 @@ impl<'a, R: Read> Iterator for NamesReader<'a, R> {
             self.cache.push(u);
         }
 
-        Some(String::from_utf16(&self.cache).map_err(|e| Error::other(e.to_string())))
+        Some(Ok(std::mem::take(&mut self.cache)))
     }
 }
 
@@ -1108,6 +1318,12 @@ pub struct SevenZReader<R: Read + Seek> {
     source: R,
     archive: Archive,
     password: Vec<u8>,
+    options: DecodeOptions,
+    block_cache: Option<BlockCache>,
+    limits: ExtractLimits,
+    filter: EntryFilter,
+    progress: Option<Box<dyn ProgressObserver + Send>>,
+    cancellation: Option<CancellationToken>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -1130,6 +1346,12 @@ impl<R: Read + Seek> SevenZReader<R> {
             source,
             archive,
             password,
+            options: Default::default(),
+            block_cache: None,
+            limits: Default::default(),
+            filter: Default::default(),
+            progress: None,
+            cancellation: None,
         })
     }
 
@@ -1139,6 +1361,12 @@ impl<R: Read + Seek> SevenZReader<R> {
             source,
             archive,
             password: password.to_vec(),
+            options: Default::default(),
+            block_cache: None,
+            limits: Default::default(),
+            filter: Default::default(),
+            progress: None,
+            cancellation: None,
         }
     }
 
@@ -1147,15 +1375,274 @@ impl<R: Read + Seek> SevenZReader<R> {
         &self.archive
     }
 
-    fn build_decode_stack<'r>(
-        source: &'r mut R,
+    /// Sets the number of worker threads used to decode LZMA2 streams.
+    /// See [`BlockDecoder::set_lzma2_threads`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_lzma2_threads(&mut self, threads: usize) -> &mut Self {
+        self.options.lzma2_threads = threads.max(1);
+        self
+    }
+
+    /// Limits the memory used to decode a block. See [`BlockDecoder::set_memory_limit`].
+    pub fn set_memory_limit(&mut self, max_bytes: usize) -> &mut Self {
+        self.options.max_mem_limit_kb = max_bytes / 1024;
+        self
+    }
+
+    /// Enables an in-memory cache of decoded blocks used by [`SevenZReader::open_entry`].
+    ///
+    /// Blocks are cached whole, so entries of a solid block read after one another don't
+    /// decompress the block from its start again. Blocks larger than `max_bytes` are never
+    /// cached. Once the cached blocks exceed `max_bytes`, the least recently used ones are evicted.
+    /// A size of 0 disables the cache, which is the default.
+    pub fn set_block_cache_size(&mut self, max_bytes: usize) -> &mut Self {
+        self.block_cache = if max_bytes > 0 {
+            Some(BlockCache::new(max_bytes))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Sets the limits checked before entries are extracted, see [`ExtractLimits`].
+    ///
+    /// [`SevenZReader::for_each_entries`] checks all limits against the whole archive before
+    /// decoding anything. Opening a single entry checks its size and the ratio of its block.
+    pub fn set_extract_limits(&mut self, limits: ExtractLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Restricts [`SevenZReader::for_each_entries`] to the entries selected by `filter`.
+    ///
+    /// Entries are selected before decoding, blocks without a selected entry are skipped and
+    /// the decoding of a block stops after its last selected entry. The extraction limits are
+    /// only checked against the selected entries.
+    pub fn set_entry_filter(&mut self, filter: EntryFilter) -> &mut Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Reports the progress of [`SevenZReader::for_each_entries`] to `observer`.
+    ///
+    /// The total is the size of the selected entries, the processed bytes are the bytes read
+    /// from the entry readers, and the packed bytes are the bytes read from the archive.
+    pub fn set_progress_observer(
+        &mut self,
+        observer: impl ProgressObserver + Send + 'static,
+    ) -> &mut Self {
+        self.progress = Some(Box::new(observer));
+        self
+    }
+
+    /// Stops [`SevenZReader::for_each_entries`] with [`Error::Cancelled`] once `token` is
+    /// cancelled. The token is checked before each entry and each read from an entry reader.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Opens a reader of the content of the entry at `file_index` in [`Archive::files`].
+    ///
+    /// Unless the block is cached, see [`SevenZReader::set_block_cache_size`], the block
+    /// containing the entry is decoded from its start and the data in front of the entry is skipped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::io::Read;
+    /// use sevenz_rust::*;
+    /// let mut sz = SevenZReader::open("example.7z", Password::empty()).unwrap();
+    /// sz.set_block_cache_size(64 * 1024 * 1024);
+    /// let index = sz.archive().files.iter().position(|f| f.name() == "a.txt").unwrap();
+    /// let mut content = String::new();
+    /// sz.open_entry(index).unwrap().read_to_string(&mut content).unwrap();
+    /// ```
+    pub fn open_entry(&mut self, file_index: usize) -> Result<Box<dyn Read + '_>, Error> {
+        let file = self
+            .archive
+            .files
+            .get(file_index)
+            .ok_or(Error::IndexOutOfRange {
+                what: "file",
+                index: file_index,
+            })?;
+        self.limits.check_entry(&self.archive, file_index)?;
+        let folder_index = match self.archive.stream_map.file_folder_index[file_index] {
+            Some(folder_index) if file.has_stream && file.size > 0 => folder_index,
+            _ => return Ok(Box::new([0u8; 0].as_slice())),
+        };
+        let (size, has_crc, crc) = (file.size, file.has_crc, file.crc);
+        let offset = self.entry_offset_in_folder(folder_index, file_index);
+
+        let mut reader: Box<dyn Read + '_> = match self.cached_block(folder_index)? {
+            Some(block) => Box::new(
+                CachedEntryReader::new(block, offset, size)
+                    .map_err(|e| Error::io(e).with_folder(folder_index))?,
+            ),
+            None => {
+                let (mut folder_reader, _) = Self::build_decode_stack(
+                    &mut self.source,
+                    &self.archive,
+                    folder_index,
+                    &self.password,
+                    &self.options,
+                )?;
+                std::io::copy(&mut (&mut folder_reader).take(offset), &mut std::io::sink())
+                    .map_err(|e| {
+                        Error::bad_password(e, !self.password.is_empty()).with_folder(folder_index)
+                    })?;
+                Box::new(BoundedReader::new(folder_reader, size as usize))
+            }
+        };
+        if has_crc {
+            reader = Box::new(Crc32VerifyingReader::new(
+                reader,
+                size as usize,
+                crc,
+                ChecksumTarget::Entry,
+            ));
+        }
+        Ok(reader)
+    }
+
+    /// Opens a seekable reader of the content of the entry at `file_index` in [`Archive::files`].
+    ///
+    /// Entries of uncompressed blocks are read straight from the archive. Entries of cached
+    /// blocks, see [`SevenZReader::set_block_cache_size`], are read from memory. Otherwise
+    /// seeking forward decodes and discards data, and seeking backward decodes the block again
+    /// from its start, so random access is cheapest in non-solid archives.
+    ///
+    /// Unlike [`SevenZReader::open_entry`], the CRC of the entry is not verified.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::io::{Read, Seek, SeekFrom};
+    /// use sevenz_rust::*;
+    /// let mut sz = SevenZReader::open("example.7z", Password::empty()).unwrap();
+    /// let mut entry = sz.open_entry_seekable(0).unwrap();
+    /// entry.seek(SeekFrom::End(-16)).unwrap();
+    /// let mut tail = Vec::new();
+    /// entry.read_to_end(&mut tail).unwrap();
+    /// ```
+    pub fn open_entry_seekable(
+        &mut self,
+        file_index: usize,
+    ) -> Result<impl Read + Seek + '_, Error> {
+        let file = self
+            .archive
+            .files
+            .get(file_index)
+            .ok_or(Error::IndexOutOfRange {
+                what: "file",
+                index: file_index,
+            })?;
+        self.limits.check_entry(&self.archive, file_index)?;
+        let folder_index = match self.archive.stream_map.file_folder_index[file_index] {
+            Some(folder_index) if file.has_stream && file.size > 0 => folder_index,
+            _ => return Ok(EntrySeekReader::Empty),
+        };
+        let size = file.size;
+        let offset = self.entry_offset_in_folder(folder_index, file_index);
+        if let Some(block) = self.cached_block(folder_index)? {
+            let reader = CachedEntryReader::new(block, offset, size)
+                .map_err(|e| Error::io(e).with_folder(folder_index))?;
+            return Ok(EntrySeekReader::Cached(reader));
+        }
+        let folder = &self.archive.folders[folder_index];
+        if folder.coders.len() == 1
+            && folder.packed_streams.len() == 1
+            && folder.coders[0].decompression_method_id() == SevenZMethod::ID_COPY
+        {
+            let first_pack_stream_index =
+                self.archive.stream_map.folder_first_pack_stream_index[folder_index];
+            let start = SIGNATURE_HEADER_SIZE
+                + self.archive.pack_pos
+                + self.archive.stream_map.pack_stream_offsets[first_pack_stream_index]
+                + offset;
+            return Ok(EntrySeekReader::Stored(StoredEntryReader::new(
+                &mut self.source,
+                start,
+                size,
+            )));
+        }
+        Ok(EntrySeekReader::Decoding(DecodingEntryReader::new(
+            &mut self.source,
+            &self.archive,
+            &self.password,
+            self.options.clone(),
+            folder_index,
+            offset,
+            size,
+        )))
+    }
+
+    /// Returns the decoded block from the cache, decoding and caching it first if needed.
+    /// Returns `None` if the cache is disabled or the block does not fit in it.
+    fn cached_block(&mut self, folder_index: usize) -> Result<Option<Arc<Vec<u8>>>, Error> {
+        let unpack_size = self.archive.folders[folder_index].get_unpack_size();
+        let cache = match self.block_cache.as_mut() {
+            Some(cache) if cache.accepts(unpack_size) => cache,
+            _ => return Ok(None),
+        };
+        if let Some(block) = cache.get(folder_index) {
+            return Ok(Some(block));
+        }
+        let (mut folder_reader, _) = Self::build_decode_stack(
+            &mut self.source,
+            &self.archive,
+            folder_index,
+            &self.password,
+            &self.options,
+        )?;
+        let mut block = Vec::with_capacity(unpack_size as usize);
+        folder_reader.read_to_end(&mut block).map_err(|e| {
+            Error::bad_password(e, !self.password.is_empty()).with_folder(folder_index)
+        })?;
+        if block.len() as u64 != unpack_size {
+            // e.g. a copied block whose packed stream is shorter than the block
+            let e = std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "block is shorter than its unpack size",
+            );
+            return Err(Error::io(e).with_folder(folder_index));
+        }
+        let block = Arc::new(block);
+        cache.insert(folder_index, block.clone());
+        Ok(Some(block))
+    }
+
+    /// Offset of the data of `file_index` in the decoded block.
+    fn entry_offset_in_folder(&self, folder_index: usize, file_index: usize) -> u64 {
+        let start = self.archive.stream_map.folder_first_file_index[folder_index];
+        self.archive.files[start..file_index]
+            .iter()
+            .filter(|f| f.has_stream)
+            .map(|f| f.size)
+            .sum()
+    }
+
+    fn build_decode_stack<'r, S: Read + Seek + 'r>(
+        source: S,
         archive: &Archive,
         folder_index: usize,
         password: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<(Box<dyn Read + 'r>, usize), Error> {
+        Self::build_decode_stack1(source, archive, folder_index, password, options)
+            .map_err(|e| e.with_folder(folder_index))
+    }
+
+    fn build_decode_stack1<'r, S: Read + Seek + 'r>(
+        mut source: S,
+        archive: &Archive,
+        folder_index: usize,
+        password: &[u8],
+        options: &DecodeOptions,
     ) -> Result<(Box<dyn Read + 'r>, usize), Error> {
         let folder = &archive.folders[folder_index];
+        check_folder_memory(folder, options)?;
         if folder.total_input_streams > folder.total_output_streams {
-            return Self::build_decode_stack2(source, archive, folder_index, password);
+            return Self::build_decode_stack2(source, archive, folder_index, password, options);
         }
         let first_pack_stream_index =
             archive.stream_map.folder_first_pack_stream_index[folder_index];
@@ -1169,19 +1656,20 @@ impl<R: Read + Seek> SevenZReader<R> {
         let pack_size = archive.pack_sizes[first_pack_stream_index] as usize;
 
         let mut decoder: Box<dyn Read> = Box::new(BoundedReader::new(source, pack_size));
+        if let Some(counter) = &options.packed_counter {
+            decoder = Box::new(PackedCounter::new(decoder, counter.clone()));
+        }
         let folder = &archive.folders[folder_index];
         for (index, coder) in folder.ordered_coder_iter() {
             if coder.num_in_streams != 1 || coder.num_out_streams != 1 {
-                return Err(Error::unsupported(
-                    "Multi input/output stream coders are not yet supported",
-                ));
+                return Err(Error::unsupported(UnsupportedFeature::MultiStreamCoders));
             }
             let next = crate::decoders::add_decoder(
                 decoder,
                 folder.get_unpack_size_at_index(index) as usize,
                 coder,
                 password,
-                MAX_MEM_LIMIT_KB,
+                options,
             )?;
             decoder = Box::new(next);
         }
@@ -1190,29 +1678,30 @@ impl<R: Read + Seek> SevenZReader<R> {
                 decoder,
                 folder.get_unpack_size() as usize,
                 folder.crc,
+                ChecksumTarget::Folder,
             ));
         }
 
         Ok((decoder, pack_size))
     }
 
-    fn build_decode_stack2<'r>(
-        source: &'r mut R,
+    fn build_decode_stack2<'r, S: Read + Seek + 'r>(
+        source: S,
         archive: &Archive,
         folder_index: usize,
         password: &[u8],
+        options: &DecodeOptions,
     ) -> Result<(Box<dyn Read + 'r>, usize), Error> {
         const MAX_CODER_COUNT: usize = 32;
         let folder = &archive.folders[folder_index];
         if folder.coders.len() > MAX_CODER_COUNT {
-            return Err(Error::unsupported(format!(
-                "Too many coders: {}",
-                folder.coders.len()
+            return Err(Error::unsupported(UnsupportedFeature::TooManyCoders(
+                folder.coders.len(),
             )));
         }
 
         assert!(folder.total_input_streams > folder.total_output_streams);
-        let source = ReaderPtr::new(source);
+        let source = SharedReader::new(source);
         let first_pack_stream_index =
             archive.stream_map.folder_first_pack_stream_index[folder_index];
         let start_pos = SIGNATURE_HEADER_SIZE + archive.pack_pos;
@@ -1252,7 +1741,10 @@ impl<R: Read + Seek> SevenZReader<R> {
 
         let id = folder.coders[main_coder_index].decompression_method_id();
         if id != SevenZMethod::ID_BCJ2 {
-            return Err(Error::unsupported(format!("Unsupported method: {:?}", id)));
+            return Err(Error::unsupported(UnsupportedFeature::Method(format!(
+                "{:?}",
+                id
+            ))));
         }
 
         let num_in_streams = folder.coders[main_coder_index].num_in_streams as usize;
@@ -1264,6 +1756,7 @@ impl<R: Read + Seek> SevenZReader<R> {
                 &sources,
                 &coder_to_stream_map,
                 password,
+                options,
                 i,
             )?);
         }
@@ -1276,6 +1769,7 @@ impl<R: Read + Seek> SevenZReader<R> {
                 decoder,
                 folder.get_unpack_size() as usize,
                 folder.crc,
+                ChecksumTarget::Folder,
             ));
         }
         Ok((
@@ -1284,64 +1778,73 @@ impl<R: Read + Seek> SevenZReader<R> {
         ))
     }
 
-    fn get_in_stream<'r>(
+    fn get_in_stream<'r, S: Read + Seek + 'r>(
         folder: &Folder,
-        sources: &[SeekableBoundedReader<ReaderPtr<R>>],
+        sources: &[SeekableBoundedReader<SharedReader<S>>],
         coder_to_stream_map: &[usize],
         password: &[u8],
-
+        options: &DecodeOptions,
         in_stream_index: usize,
-    ) -> Result<Box<dyn Read + 'r>, Error>
-    where
-        R: 'r,
-    {
+    ) -> Result<Box<dyn Read + 'r>, Error> {
         let index = folder
             .packed_streams
             .iter()
             .position(|&i| i == in_stream_index as u64);
         if let Some(index) = index {
-            return Ok(Box::new(sources[index].clone()));
+            let source = sources[index].clone();
+            return Ok(match &options.packed_counter {
+                Some(counter) => Box::new(PackedCounter::new(source, counter.clone())),
+                None => Box::new(source),
+            });
         }
 
         let bp = folder
             .find_bind_pair_for_in_stream(in_stream_index)
-            .ok_or_else(|| {
-                Error::other(format!(
-                    "Couldn't find bind pair for stream {}",
-                    in_stream_index
-                ))
-            })?;
+            .ok_or(Error::header(HeaderError::MissingBindPair {
+                in_stream: in_stream_index,
+            }))?;
         let index = folder.bind_pairs[bp].out_index as usize;
 
-        Self::get_in_stream2(folder, sources, coder_to_stream_map, password, index)
+        Self::get_in_stream2(
+            folder,
+            sources,
+            coder_to_stream_map,
+            password,
+            options,
+            index,
+        )
     }
 
-    fn get_in_stream2<'r>(
+    fn get_in_stream2<'r, S: Read + Seek + 'r>(
         folder: &Folder,
-        sources: &[SeekableBoundedReader<ReaderPtr<R>>],
+        sources: &[SeekableBoundedReader<SharedReader<S>>],
         coder_to_stream_map: &[usize],
         password: &[u8],
+        options: &DecodeOptions,
         in_stream_index: usize,
-    ) -> Result<Box<dyn Read + 'r>, Error>
-    where
-        R: 'r,
-    {
+    ) -> Result<Box<dyn Read + 'r>, Error> {
         let coder = &folder.coders[in_stream_index];
         let start_index = coder_to_stream_map[in_stream_index];
         if start_index == usize::MAX {
-            return Err(Error::other("in_stream_index out of range"));
+            return Err(Error::header(HeaderError::CoderIndexOutOfRange {
+                index: in_stream_index,
+            }));
         }
         let uncompressed_len = folder.unpack_sizes[in_stream_index] as usize;
         if coder.num_in_streams == 1 {
-            let input =
-                Self::get_in_stream(folder, sources, coder_to_stream_map, password, start_index)?;
+            let input = Self::get_in_stream(
+                folder,
+                sources,
+                coder_to_stream_map,
+                password,
+                options,
+                start_index,
+            )?;
 
-            let decoder = add_decoder(input, uncompressed_len, coder, password, MAX_MEM_LIMIT_KB)?;
+            let decoder = add_decoder(input, uncompressed_len, coder, password, options)?;
             return Ok(Box::new(decoder));
         }
-        Err(Error::unsupported(
-            "Multi input stream coders are not yet supported",
-        ))
+        Err(Error::unsupported(UnsupportedFeature::MultiStreamCoders))
     }
 
     /// Takes a closure to decode each files in the archive.
@@ -1356,28 +1859,161 @@ impl<R: Read + Seek> SevenZReader<R> {
     /// And this example [mt_decompress.rs](https://github.com/dyz1990/sevenz-rust/blob/main/examples/mt_decompress.rs) if you want use multi-thread.
     ///
     pub fn for_each_entries<F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>>(
+        &mut self,
+        each: F,
+    ) -> Result<(), Error> {
+        self.for_each_entries_with(each, None, |forder_dec, each| {
+            forder_dec.for_each_entries(each)
+        })
+    }
+
+    /// Same as [`SevenZReader::for_each_entries`], but entries failing to decode don't stop it.
+    ///
+    /// The failure is recorded in the returned report. An entry not matching its CRC is
+    /// followed by the next entry of its block. Other failures, like an unsupported method, a
+    /// wrong password or truncated data, are recorded for the remaining selected entries of the
+    /// block too, and decoding goes on with the next block.
+    ///
+    /// `each` gets the data of broken entries up to the failure, it decides whether to keep it,
+    /// like `7z -kb`. Errors not raised by the entry reader, like `each` failing to write, and
+    /// cancellation still stop with an error.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use sevenz_rust::*;
+    /// let mut sz = SevenZReader::open("damaged.7z", Password::empty()).unwrap();
+    /// let report = sz
+    ///     .for_each_entries_recovering(|entry, reader| {
+    ///         std::io::copy(reader, &mut std::io::sink())?;
+    ///         Ok(true)
+    ///     })
+    ///     .unwrap();
+    /// for (index, status) in report.failures() {
+    ///     println!("{}: {:?}", sz.archive().files[index].name(), status);
+    /// }
+    /// ```
+    pub fn for_each_entries_recovering<
+        F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>,
+    >(
+        &mut self,
+        each: F,
+    ) -> Result<TestReport, Error> {
+        let mut entries = vec![EntryStatus::Ok; self.archive.files.len()];
+        self.for_each_entries_with(each, Some(&mut entries), |forder_dec, each| {
+            forder_dec.for_each_entries(each)
+        })?;
+        Ok(TestReport { entries })
+    }
+
+    /// Same as [`SevenZReader::for_each_entries`], but each block is decoded on a background thread.
+    ///
+    /// See [`BlockDecoder::for_each_entries_pipelined`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn for_each_entries_pipelined<
+        F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>,
+    >(
+        &mut self,
+        read_ahead: ReadAhead,
+        each: F,
+    ) -> Result<(), Error>
+    where
+        R: Send,
+    {
+        self.for_each_entries_with(each, None, |forder_dec, each| {
+            forder_dec.for_each_entries_pipelined(read_ahead, each)
+        })
+    }
+
+    fn for_each_entries_with<
+        F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>,
+        D: FnMut(BlockDecoder<R>, &mut F) -> Result<bool, Error>,
+    >(
         &mut self,
         mut each: F,
+        failures: Option<&mut [EntryStatus]>,
+        mut decode_block: D,
     ) -> Result<(), Error> {
+        let selected = self.filter.select(&self.archive)?;
+        let selected = selected.as_deref();
+        self.limits.check_selected(&self.archive, selected)?;
+        let mut progress = self.progress.take().map(ProgressTracker::new);
+        let result = self.decode_entries(
+            selected,
+            progress.as_mut(),
+            failures,
+            &mut each,
+            &mut decode_block,
+        );
+        self.progress = progress.map(ProgressTracker::into_observer);
+        result
+    }
+
+    fn decode_entries<
+        F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>,
+        D: FnMut(BlockDecoder<R>, &mut F) -> Result<bool, Error>,
+    >(
+        &mut self,
+        selected: Option<&[bool]>,
+        mut progress: Option<&mut ProgressTracker>,
+        mut failures: Option<&mut [EntryStatus]>,
+        each: &mut F,
+        decode_block: &mut D,
+    ) -> Result<(), Error> {
+        let mut options = self.options.clone();
+        if let Some(progress) = progress.as_deref_mut() {
+            let total = self
+                .archive
+                .files
+                .iter()
+                .enumerate()
+                .filter(|(i, f)| f.has_stream && selected.map_or(true, |selected| selected[*i]))
+                .map(|(_, f)| f.size)
+                .sum();
+            progress.set_total(Some(total));
+            options.packed_counter = Some(progress.packed_counter());
+        }
         let folder_count = self.archive.folders.len();
         for folder_index in 0..folder_count {
-            let forder_dec = BlockDecoder::new(
+            if let Some(selected) = selected {
+                let start = self.archive.stream_map.folder_first_file_index[folder_index];
+                let file_count = self.archive.folders[folder_index].num_unpack_sub_streams;
+                if !selected[start..start + file_count].contains(&true) {
+                    continue;
+                }
+            }
+            let mut forder_dec = BlockDecoder::new(
                 folder_index,
                 &self.archive,
                 &self.password,
                 &mut self.source,
             );
-            forder_dec.for_each_entries(&mut each)?;
+            forder_dec.options = options.clone();
+            forder_dec.controls = EntryControls {
+                selected,
+                progress: progress.as_deref_mut(),
+                cancellation: self.cancellation.as_ref(),
+                failures: failures.as_deref_mut(),
+            };
+            decode_block(forder_dec, each)?;
         }
         // decode empty files
         for file_index in 0..self.archive.files.len() {
             let folder_index = self.archive.stream_map.file_folder_index[file_index];
-            if folder_index.is_none() {
+            if folder_index.is_none() && selected.map_or(true, |selected| selected[file_index]) {
+                if let Some(token) = &self.cancellation {
+                    token.check()?;
+                }
                 let file = &self.archive.files[file_index];
+                if let Some(progress) = progress.as_deref_mut() {
+                    progress.entry_started(file, file_index, None);
+                }
                 let empty_reader: &mut dyn Read = &mut ([0u8; 0].as_slice());
                 if !each(file, empty_reader)? {
                     return Ok(());
                 }
+                if let Some(progress) = progress.as_deref_mut() {
+                    progress.entry_finished(file, file_index);
+                }
             }
         }
         Ok(())
@@ -1394,6 +2030,20 @@ pub struct BlockDecoder<'a, R: Read + Seek> {
     archive: &'a Archive,
     password: &'a [u8],
     source: &'a mut R,
+    options: DecodeOptions,
+    controls: EntryControls<'a>,
+}
+
+/// Settings of [`SevenZReader::for_each_entries`] applied to the entries of a block.
+#[derive(Default)]
+struct EntryControls<'a> {
+    /// Whether each entry of the archive is selected, see [`SevenZReader::set_entry_filter`].
+    selected: Option<&'a [bool]>,
+    progress: Option<&'a mut ProgressTracker>,
+    cancellation: Option<&'a CancellationToken>,
+    /// Status of each entry of the archive, recording failures instead of returning them, see
+    /// [`SevenZReader::for_each_entries_recovering`].
+    failures: Option<&'a mut [EntryStatus]>,
 }
 
 impl<'a, R: Read + Seek> BlockDecoder<'a, R> {
@@ -1408,9 +2058,30 @@ impl<'a, R: Read + Seek> BlockDecoder<'a, R> {
             archive,
             password,
             source,
+            options: Default::default(),
+            controls: Default::default(),
         }
     }
 
+    /// Sets the number of worker threads used to decode LZMA2 streams.
+    /// Streams are split at dictionary resets, see [`crate::LZMA2ParallelReader`].
+    /// The default is 1, decoding on the calling thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_lzma2_threads(&mut self, threads: usize) -> &mut Self {
+        self.options.lzma2_threads = threads.max(1);
+        self
+    }
+
+    /// Limits the memory used by the coders decoding the block, e.g. LZMA dictionaries,
+    /// zstd windows and BCJ2 buffers. Blocks estimated to need more,
+    /// see [`Archive::estimate_decode_memory`], fail with [`Error::MaxMemLimited`] before
+    /// decoding starts. LZMA2 decoding on several threads needs a dictionary per thread.
+    /// Unlimited by default.
+    pub fn set_memory_limit(&mut self, max_bytes: usize) -> &mut Self {
+        self.options.max_mem_limit_kb = max_bytes / 1024;
+        self
+    }
+
     pub fn entries(&self) -> &[SevenZArchiveEntry] {
         let start = self.archive.stream_map.folder_first_file_index[self.folder_index];
         let file_count = self.archive.folders[self.folder_index].num_unpack_sub_streams;
@@ -1440,69 +2111,288 @@ impl<'a, R: Read + Seek> BlockDecoder<'a, R> {
             archive,
             password,
             source,
+            options,
+            mut controls,
         } = self;
-        let (mut folder_reader, _size) =
-            SevenZReader::build_decode_stack(source, archive, folder_index, password)?;
-        let start = archive.stream_map.folder_first_file_index[folder_index];
-        let file_count = archive.folders[folder_index].num_unpack_sub_streams;
+        let mut folder_reader = match SevenZReader::<R>::build_decode_stack(
+            source,
+            archive,
+            folder_index,
+            password,
+            &options,
+        ) {
+            Ok((folder_reader, _size)) => folder_reader,
+            Err(e) => {
+                return controls.record_failure(archive, folder_index, 0, e);
+            }
+        };
+        decode_block_entries(
+            archive,
+            folder_index,
+            password,
+            &mut controls,
+            &mut folder_reader,
+            each,
+        )
+    }
 
-        for file_index in start..(file_count + start) {
-            let file = &archive.files[file_index];
-            if file.has_stream && file.size > 0 {
-                let mut decoder: Box<dyn Read> =
-                    Box::new(BoundedReader::new(&mut folder_reader, file.size as usize));
-                if file.has_crc {
-                    decoder = Box::new(Crc32VerifyingReader::new(
-                        decoder,
-                        file.size as usize,
-                        file.crc,
-                    ));
+    /// Same as [`BlockDecoder::for_each_entries`], but the block is decoded on a background thread.
+    ///
+    /// The decoder thread fills a bounded ring of buffers, configured by `read_ahead`, while `each`
+    /// drains them on the calling thread, so decompression and writing overlap.
+    /// The decoder thread waits whenever all buffers are full.
+    /// Errors raised while decoding, including CRC failures, are returned by the entry readers.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn for_each_entries_pipelined<
+        F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>,
+    >(
+        self,
+        read_ahead: ReadAhead,
+        each: &mut F,
+    ) -> Result<bool, Error>
+    where
+        R: Send,
+    {
+        let Self {
+            folder_index,
+            archive,
+            password,
+            source,
+            options,
+            mut controls,
+        } = self;
+        let (mut pipe_reader, pipe_writer) = crate::read_ahead::pipe(read_ahead);
+        std::thread::scope(|scope| {
+            let decoder = scope.spawn(move || {
+                match SevenZReader::<R>::build_decode_stack(
+                    source,
+                    archive,
+                    folder_index,
+                    password,
+                    &options,
+                ) {
+                    Ok((folder_reader, _size)) => {
+                        pipe_writer.run(folder_reader);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        pipe_writer.fail(&e);
+                        Err(e)
+                    }
+                }
+            });
+            let result = decode_block_entries(
+                archive,
+                folder_index,
+                password,
+                &mut controls,
+                &mut pipe_reader,
+                each,
+            );
+            // unblocks the decoder thread if `each` stopped early
+            drop(pipe_reader);
+            match decoder.join() {
+                Ok(Ok(())) => result,
+                // the entries only got a description of the error
+                Ok(Err(e)) if controls.failures.is_some() && result.is_ok() => {
+                    controls.record_failure(archive, folder_index, 0, e)
                 }
-                if !each(file, &mut decoder)
-                    .map_err(|e| e.maybe_bad_password(!self.password.is_empty()))?
-                {
-                    return Ok(false);
+                Ok(Err(e)) => Err(e),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+}
+
+fn decode_block_entries<F: FnMut(&SevenZArchiveEntry, &mut dyn Read) -> Result<bool, Error>>(
+    archive: &Archive,
+    folder_index: usize,
+    password: &[u8],
+    controls: &mut EntryControls,
+    folder_reader: &mut dyn Read,
+    each: &mut F,
+) -> Result<bool, Error> {
+    let selected = controls.selected;
+    let cancellation = controls.cancellation;
+    let mut cancellable;
+    let folder_reader: &mut dyn Read = match cancellation {
+        Some(token) => {
+            cancellable = CancellableReader::new(folder_reader, token.clone());
+            &mut cancellable
+        }
+        None => folder_reader,
+    };
+    let start = archive.stream_map.folder_first_file_index[folder_index];
+    let mut end = start + archive.folders[folder_index].num_unpack_sub_streams;
+    if let Some(selected) = selected {
+        // the rest of the block doesn't need to be decoded
+        end = (start..end)
+            .rfind(|&i| selected[i])
+            .map_or(start, |last| last + 1);
+    }
+
+    for file_index in start..end {
+        if let Some(token) = cancellation {
+            token.check()?;
+        }
+        let file = &archive.files[file_index];
+        if selected.is_some_and(|selected| !selected[file_index]) {
+            if file.has_stream && file.size > 0 {
+                let mut skipped = BoundedReader::new(&mut *folder_reader, file.size as usize);
+                if let Err(e) = std::io::copy(&mut skipped, &mut std::io::sink()) {
+                    let e = Error::bad_password(e, !password.is_empty()).with_folder(folder_index);
+                    return controls.record_failure(archive, folder_index, file_index - start, e);
                 }
-            } else {
-                let empty_reader: &mut dyn Read = &mut ([0u8; 0].as_slice());
-                if !each(file, empty_reader)? {
-                    return Ok(false);
+            }
+            continue;
+        }
+        if let Some(progress) = controls.progress.as_deref_mut() {
+            progress.entry_started(file, file_index, Some(folder_index));
+        }
+        if file.has_stream && file.size > 0 {
+            let read_failed = Cell::new(false);
+            let mut decoder: Box<dyn Read> =
+                Box::new(BoundedReader::new(&mut *folder_reader, file.size as usize));
+            if file.has_crc {
+                decoder = Box::new(Crc32VerifyingReader::new(
+                    decoder,
+                    file.size as usize,
+                    file.crc,
+                    ChecksumTarget::Entry,
+                ));
+            }
+            if let Some(progress) = controls.progress.as_deref_mut() {
+                decoder = Box::new(ProgressReader::new(decoder, progress));
+            }
+            if controls.failures.is_some() {
+                decoder = Box::new(FailureFlagReader {
+                    inner: decoder,
+                    failed: &read_failed,
+                });
+            }
+            let result = each(file, &mut decoder);
+            drop(decoder);
+            match result {
+                Ok(true) => {}
+                Ok(false) => return Ok(false),
+                Err(e) => {
+                    let e = e
+                        .maybe_bad_password(!password.is_empty())
+                        .with_entry(file.name())
+                        .with_folder(folder_index);
+                    if !read_failed.get() || matches!(e, Error::Cancelled) {
+                        return Err(e);
+                    }
+                    let entry_mismatch = matches!(
+                        e,
+                        Error::ChecksumMismatch {
+                            target: ChecksumTarget::Entry,
+                            ..
+                        }
+                    );
+                    if !entry_mismatch {
+                        return controls.record_failure(
+                            archive,
+                            folder_index,
+                            file_index - start,
+                            e,
+                        );
+                    }
+                    // the whole entry was read, the rest of the block is intact
+                    if let Some(failures) = controls.failures.as_deref_mut() {
+                        failures[file_index] = EntryStatus::of_error(e, false);
+                    }
+                    continue;
                 }
             }
+        } else {
+            let empty_reader: &mut dyn Read = &mut ([0u8; 0].as_slice());
+            if !each(file, empty_reader).map_err(|e| e.with_entry(file.name()))? {
+                return Ok(false);
+            }
+        }
+        if let Some(progress) = controls.progress.as_deref_mut() {
+            progress.entry_finished(file, file_index);
+        }
+    }
+    Ok(true)
+}
+
+impl EntryControls<'_> {
+    /// Records the failure `e` of decoding a block for its selected entries from the
+    /// `from`th one on, returning `e` unless failures are recorded.
+    fn record_failure(
+        &mut self,
+        archive: &Archive,
+        folder_index: usize,
+        from: usize,
+        e: Error,
+    ) -> Result<bool, Error> {
+        let Some(failures) = self.failures.as_deref_mut() else {
+            return Err(e);
+        };
+        if let Error::Cancelled = e {
+            return Err(e);
+        }
+        let start = archive.stream_map.folder_first_file_index[folder_index];
+        let end = start + archive.folders[folder_index].num_unpack_sub_streams;
+        let status = EntryStatus::of_error(e, is_encrypted(&archive.folders[folder_index]));
+        for file_index in start + from..end {
+            if self.selected.map_or(true, |selected| selected[file_index]) {
+                failures[file_index] = status.clone();
+            }
         }
         Ok(true)
     }
 }
 
-#[derive(Debug, Copy)]
-struct ReaderPtr<R> {
-    reader: *mut R,
+/// Remembers whether reading an entry failed, telling decoding errors from errors of the
+/// closure passed to [`SevenZReader::for_each_entries_recovering`].
+struct FailureFlagReader<'a, R> {
+    inner: R,
+    failed: &'a Cell<bool>,
+}
+
+impl<R: Read> Read for FailureFlagReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).map_err(|e| {
+            self.failed.set(true);
+            e
+        })
+    }
+}
+
+/// A reader shared by the decoders of several packed streams, or kept by a reader that
+/// rebuilds its decoders.
+#[derive(Debug)]
+pub(crate) struct SharedReader<R> {
+    reader: Rc<RefCell<R>>,
 }
 
-impl<R> Clone for ReaderPtr<R> {
+impl<R> Clone for SharedReader<R> {
     fn clone(&self) -> Self {
         Self {
-            reader: self.reader,
+            reader: self.reader.clone(),
         }
     }
 }
 
-impl<R> ReaderPtr<R> {
-    fn new(reader: &mut R) -> Self {
+impl<R> SharedReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
         Self {
-            reader: reader as *mut R,
+            reader: Rc::new(RefCell::new(reader)),
         }
     }
 }
 
-impl<R: Read> Read for ReaderPtr<R> {
+impl<R: Read> Read for SharedReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        unsafe { (*self.reader).read(buf) }
+        self.reader.borrow_mut().read(buf)
     }
 }
 
-impl<R: Seek> Seek for ReaderPtr<R> {
+impl<R: Seek> Seek for SharedReader<R> {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        unsafe { (*self.reader).seek(pos) }
+        self.reader.borrow_mut().seek(pos)
     }
 }
@@ -0,0 +1,713 @@
+use std::{
+    io::{Cursor, ErrorKind, Read, Seek, SeekFrom},
+    panic::AssertUnwindSafe,
+};
+
+use super::{crc32_cksum, Archive, HeaderLimits};
+use crate::{
+    archive::*,
+    error::Error,
+    folder::{Coder, Folder},
+    lzma::{LZMA2Reader, DICT_SIZE_MIN},
+    ReadLimit, ReadLimits, SevenZArchiveEntry,
+};
+
+/// Longest LZMA chunk resetting everything: control, unpacked and packed size, properties and
+/// at most 64 KiB of data.
+const MAX_FIRST_CHUNK: usize = 6 + (1 << 16);
+
+/// Decoded bytes of a candidate's first chunk checked before the stream is accepted.
+const PROBE_SIZE: usize = 64 * 1024;
+
+/// How [`Archive::salvage`] recovered an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SalvageMethod {
+    /// The archive is intact, it was read like [`Archive::read`] does.
+    Intact,
+    /// The header was read despite damage. Entries whose names and attributes were lost are
+    /// named after their blocks, `block{block}_{index}`.
+    Header,
+    /// No usable header was found, the entries are the LZMA2 streams found by scanning the
+    /// archive, named `block{block}_0`. Sizes of damaged streams are the decodable part.
+    Scan,
+}
+
+/// Limits of [`Archive::salvage_with_limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SalvageLimits {
+    /// Limits on reading the intact or damaged headers found.
+    pub read: ReadLimits,
+    /// Dictionary size used to decode the LZMA2 streams found without a header. Streams
+    /// referring further back are reported as damaged. Extracting the salvaged archive is
+    /// limited by [`crate::SevenZReader::set_memory_limit`].
+    pub max_dict_size: u32,
+}
+
+impl Default for SalvageLimits {
+    fn default() -> Self {
+        Self {
+            read: ReadLimits::default(),
+            max_dict_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Diagnostics of [`Archive::salvage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SalvageReport {
+    pub method: SalvageMethod,
+    /// Position of the header that was used in the archive, `None` if none was.
+    pub header_offset: Option<u64>,
+    /// Damage found, in the order it was found.
+    pub problems: Vec<String>,
+}
+
+impl SalvageReport {
+    pub fn is_intact(&self) -> bool {
+        self.method == SalvageMethod::Intact
+    }
+}
+
+impl Archive {
+    /// Reads as much as possible of a damaged or truncated archive, for forensic recovery.
+    ///
+    /// An intact archive is read like [`Archive::read`] does. Otherwise the header the start
+    /// header points to is used even if it doesn't match its CRC, is cut off, or its encoded form
+    /// decodes only partially or to data not matching its CRC. Entries lost with a damaged header
+    /// are rebuilt from the blocks it describes. Without a start header pointing to a usable
+    /// header, the whole archive is searched for one. As a last resort the archive is scanned for
+    /// LZMA2 streams, which is what [`crate::SevenZWriter`] and 7-Zip write by default.
+    ///
+    /// Pass the archive with [`crate::SevenZReader::from_archive`] to
+    /// [`crate::SevenZReader::for_each_entries_recovering`] to extract what is left.
+    /// The default [`SalvageLimits`] apply, see [`Archive::salvage_with_limits`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use sevenz_rust::*;
+    /// let mut file = std::fs::File::open("upload.7z").unwrap();
+    /// let len = file.metadata().unwrap().len();
+    /// let (archive, report) = Archive::salvage(&mut file, len, &[]).unwrap();
+    /// for problem in &report.problems {
+    ///     println!("{problem}");
+    /// }
+    /// let mut sz = SevenZReader::from_archive(archive, file, Password::empty());
+    /// let failures = sz
+    ///     .for_each_entries_recovering(|entry, reader| {
+    ///         let mut file = std::fs::File::create(entry.name())?;
+    ///         std::io::copy(reader, &mut file)?;
+    ///         Ok(true)
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn salvage<R: Read + Seek>(
+        reader: &mut R,
+        reader_len: u64,
+        password: &[u8],
+    ) -> Result<(Archive, SalvageReport), Error> {
+        Self::salvage_with_limits(reader, reader_len, password, SalvageLimits::default())
+    }
+
+    /// Like [`Archive::salvage`], reading headers within `limits.read` and decoding the streams
+    /// found by scanning with a dictionary of at most `limits.max_dict_size`.
+    pub fn salvage_with_limits<R: Read + Seek>(
+        reader: &mut R,
+        reader_len: u64,
+        password: &[u8],
+        limits: SalvageLimits,
+    ) -> Result<(Archive, SalvageReport), Error> {
+        reader.seek(SeekFrom::Start(0)).map_err(Error::io)?;
+        let read = Self::read_with_limits(reader, reader_len, password, limits.read);
+        let mut problems = match read {
+            Ok(archive) => {
+                let report = SalvageReport {
+                    method: SalvageMethod::Intact,
+                    header_offset: None,
+                    problems: Vec::new(),
+                };
+                return Ok((archive, report));
+            }
+            Err(e) => vec![e.to_string()],
+        };
+        let mut salvager = Salvager {
+            reader,
+            reader_len,
+            password,
+            limits: limits.read,
+            max_dict_size: limits.max_dict_size,
+        };
+        let found = match salvager.read_start_header(&mut problems)? {
+            Some(found) => Some(found),
+            None => salvager.search_header(&mut problems)?,
+        };
+        let (method, header_offset, archive) = match found {
+            Some((offset, archive)) => (SalvageMethod::Header, Some(offset), archive),
+            None => (SalvageMethod::Scan, None, salvager.scan(&mut problems)?),
+        };
+        salvager.check_pack_streams(&archive, &mut problems);
+        let report = SalvageReport {
+            method,
+            header_offset,
+            problems,
+        };
+        Ok((archive, report))
+    }
+}
+
+struct Salvager<'a, R> {
+    reader: &'a mut R,
+    reader_len: u64,
+    password: &'a [u8],
+    limits: ReadLimits,
+    max_dict_size: u32,
+}
+
+/// A possibly damaged header, see [`Salvager::parse_header`].
+struct ParsedHeader {
+    archive: Archive,
+    /// Whether the header was parsed without problems.
+    intact: bool,
+    /// Whether parsing stopped at the end of the header data.
+    cut_off: bool,
+}
+
+/// A stream found by [`Salvager::scan`].
+struct ScannedStream {
+    start: u64,
+    packed_size: u64,
+    unpack_size: u64,
+}
+
+impl<R: Read + Seek> Salvager<'_, R> {
+    /// Reads into `buf` at `pos` until it's full or the archive ends.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.reader.seek(SeekFrom::Start(pos)).map_err(Error::io)?;
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::io(e)),
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Uses the header the start header points to, whatever state both are in.
+    fn read_start_header(
+        &mut self,
+        problems: &mut Vec<String>,
+    ) -> Result<Option<(u64, Archive)>, Error> {
+        let mut start = [0; SIGNATURE_HEADER_SIZE as usize];
+        if self.read_at(0, &mut start)? < start.len() {
+            problems.push("the archive is shorter than its start header".to_string());
+            return Ok(None);
+        }
+        if &start[..6] != SEVEN_Z_SIGNATURE {
+            problems.push("the signature is damaged".to_string());
+        }
+        let field = |range: std::ops::Range<usize>| {
+            start[range]
+                .iter()
+                .rev()
+                .fold(0u64, |value, &b| value << 8 | b as u64)
+        };
+        let start_header_valid = crc32_cksum(&start[12..]) as u64 == field(8..12);
+        if !start_header_valid {
+            problems.push("the start header doesn't match its CRC".to_string());
+        }
+        let (offset, size, crc) = (field(12..20), field(20..28), field(28..32));
+        let pos = SIGNATURE_HEADER_SIZE.saturating_add(offset);
+        if size == 0 || pos >= self.reader_len || size > self.limits.max_header_size {
+            problems.push(if start_header_valid {
+                format!(
+                    "the header at {pos} is missing, the archive is cut off at {}",
+                    self.reader_len
+                )
+            } else {
+                "the start header doesn't point to a header".to_string()
+            });
+            return Ok(None);
+        }
+        let available = size.min(self.reader_len - pos);
+        let mut buf = vec![0; available as usize];
+        self.read_at(pos, &mut buf)?;
+        if available < size {
+            problems.push(format!(
+                "the header is cut off, {available} of {size} bytes remain"
+            ));
+        } else if crc32_cksum(&buf) as u64 != crc {
+            problems.push("the header doesn't match its CRC".to_string());
+        }
+        let parsed = self.parse_header(&buf, problems);
+        if parsed.archive.files.is_empty() {
+            problems.push("the header describes no entries".to_string());
+            return Ok(None);
+        }
+        Ok(Some((pos, parsed.archive)))
+    }
+
+    /// Searches the whole archive backwards for a header that parses without problems.
+    fn search_header(
+        &mut self,
+        problems: &mut Vec<String>,
+    ) -> Result<Option<(u64, Archive)>, Error> {
+        const WINDOW: usize = 1024 * 1024;
+        let mut window = vec![0; WINDOW + 1];
+        let mut end = self.reader_len;
+        while end > SIGNATURE_HEADER_SIZE {
+            let start = end.saturating_sub(WINDOW as u64).max(SIGNATURE_HEADER_SIZE);
+            // one more byte, the property following a candidate
+            let len = (end - start) as usize + (end < self.reader_len) as usize;
+            let len = self.read_at(start, &mut window[..len])?;
+            for i in (0..(end - start) as usize).rev() {
+                let next = window[..len].get(i + 1).copied();
+                let candidate = match window[i] {
+                    K_ENCODED_HEADER => next == Some(K_PACK_INFO),
+                    K_HEADER => matches!(
+                        next,
+                        Some(K_MAIN_STREAMS_INFO | K_ARCHIVE_PROPERTIES | K_FILES_INFO)
+                    ),
+                    _ => false,
+                };
+                if !candidate {
+                    continue;
+                }
+                let pos = start + i as u64;
+                if let Some(archive) = self.try_header_at(pos)? {
+                    problems.push(format!("found a header at {pos} by searching the archive"));
+                    return Ok(Some((pos, archive)));
+                }
+            }
+            end = start;
+        }
+        problems.push("no header found by searching the archive".to_string());
+        Ok(None)
+    }
+
+    /// Parses a header candidate at `pos`, reading more of it as long as it's cut off.
+    fn try_header_at(&mut self, pos: u64) -> Result<Option<Archive>, Error> {
+        let rest = (self.reader_len - pos).min(self.limits.max_header_size);
+        let mut size = rest.min(64 * 1024);
+        loop {
+            let mut buf = vec![0; size as usize];
+            self.read_at(pos, &mut buf)?;
+            let parsed = self.parse_header(&buf, &mut Vec::new());
+            if parsed.intact && !parsed.archive.files.is_empty() {
+                return Ok(Some(parsed.archive));
+            }
+            if !parsed.cut_off || size == rest {
+                return Ok(None);
+            }
+            size = rest.min(size * 16);
+        }
+    }
+
+    /// Parses a possibly encoded header in `buf` as far as possible, recording its damage.
+    fn parse_header(&mut self, buf: &[u8], problems: &mut Vec<String>) -> ParsedHeader {
+        let mut parsed = ParsedHeader {
+            archive: Archive::default(),
+            intact: true,
+            cut_off: false,
+        };
+        let decoded;
+        let encoded = buf.first() == Some(&K_ENCODED_HEADER);
+        let header = if encoded {
+            match self.decode_header(&buf[1..], problems) {
+                Ok((header, intact)) => {
+                    parsed.intact = intact;
+                    decoded = header;
+                    decoded.as_slice()
+                }
+                Err(e) => {
+                    parsed.intact = false;
+                    parsed.cut_off = is_cut_off(&e);
+                    problems.push(format!("the encoded header can't be decoded: {e}"));
+                    return parsed;
+                }
+            }
+        } else {
+            buf
+        };
+        if header.first() != Some(&K_HEADER) {
+            parsed.intact = false;
+            problems.push("the header is missing".to_string());
+            return parsed;
+        }
+        let mut cursor = Cursor::new(&header[1..]);
+        let limits = HeaderLimits::new(self.limits, header.len());
+        if let Err(e) = Archive::read_header(&mut cursor, &mut parsed.archive, limits) {
+            parsed.intact = false;
+            // the size of an encoded header is known, a short decode isn't fixed by reading more
+            parsed.cut_off = is_cut_off(&e) && !encoded;
+            problems.push(format!(
+                "the header is damaged at {}: {e}",
+                cursor.position() + 1
+            ));
+            if parsed.archive.files.is_empty() {
+                rebuild_entries(&mut parsed.archive, problems);
+            }
+        }
+        parsed
+    }
+
+    /// Decodes as much as possible of an encoded header, returning whether it's intact.
+    fn decode_header(
+        &mut self,
+        mut buf: &[u8],
+        problems: &mut Vec<String>,
+    ) -> Result<(Vec<u8>, bool), Error> {
+        let mut header_archive = Archive::default();
+        let limits = HeaderLimits::new(self.limits, buf.len());
+        let (decoder, size) = Archive::read_encoded_header(
+            &mut buf,
+            self.reader,
+            &mut header_archive,
+            self.password,
+            limits,
+            false,
+        )?;
+        self.limits.check(ReadLimit::HeaderSize, size as u64)?;
+        let mut decoded = Vec::new();
+        let result = decoder.take(size as u64).read_to_end(&mut decoded);
+        if let Err(e) = result {
+            problems.push(format!(
+                "the encoded header decodes to {} of {size} bytes: {}",
+                decoded.len(),
+                Error::bad_password(e, !self.password.is_empty())
+            ));
+            return Ok((decoded, false));
+        }
+        if decoded.len() < size {
+            problems.push(format!(
+                "the encoded header decodes to {} of {size} bytes",
+                decoded.len()
+            ));
+            return Ok((decoded, false));
+        }
+        let folder = &header_archive.folders[0];
+        let crc = crc32_cksum(&decoded);
+        if folder.has_crc && crc as u64 != folder.crc {
+            problems.push(format!(
+                "the decoded header doesn't match its CRC, expected {:08x}, got {crc:08x}",
+                folder.crc
+            ));
+            return Ok((decoded, false));
+        }
+        Ok((decoded, true))
+    }
+
+    /// Scans the archive for LZMA2 streams, making each a block with a single entry.
+    ///
+    /// Packed streams follow each other, so any stream start is accepted where one is
+    /// expected. Elsewhere only a stream whose first chunk decodes is, see
+    /// [`Salvager::verify_first_chunk`], so each position costs at most [`PROBE_SIZE`] bytes of
+    /// decoding with a dictionary of that size.
+    fn scan(&mut self, problems: &mut Vec<String>) -> Result<Archive, Error> {
+        const WINDOW: usize = 1024 * 1024;
+        let mut streams = Vec::new();
+        let mut window = vec![0; WINDOW];
+        let mut out = vec![0; PROBE_SIZE];
+        let (mut window_start, mut window_len) = (0, 0);
+        let mut pos = SIGNATURE_HEADER_SIZE;
+        let mut expected = true;
+        while pos < self.reader_len {
+            // keeps a whole first chunk in the window for verifying it
+            let window_end = window_start + window_len as u64;
+            if pos + MAX_FIRST_CHUNK as u64 > window_end && window_end < self.reader_len
+                || pos >= window_end
+            {
+                window_start = pos;
+                window_len = self.read_at(pos, &mut window)?;
+            }
+            let head = &window[(pos - window_start) as usize..window_len];
+            // uncompressed chunks can't be verified
+            let candidate = lzma2_start(head).filter(|_| expected || head[0] != 0x01);
+            let accepted = match candidate {
+                Some(_) if expected => true,
+                Some(unpack_size) => self.verify_first_chunk(head, unpack_size, &mut out),
+                None => false,
+            };
+            if !accepted {
+                pos += 1;
+                expected = false;
+                continue;
+            }
+            let (stream, complete) = self.decode_stream(pos, &mut out)?;
+            if stream.unpack_size == 0 {
+                pos += 1;
+                expected = false;
+                continue;
+            }
+            if !complete {
+                let cut_off = stream.start + stream.packed_size >= self.reader_len;
+                problems.push(format!(
+                    "the stream at {} is {}, {} bytes are decodable",
+                    stream.start,
+                    if cut_off { "cut off" } else { "damaged" },
+                    stream.unpack_size
+                ));
+            }
+            pos = stream.start + stream.packed_size.max(1);
+            expected = complete;
+            streams.push(stream);
+        }
+        problems.push(format!("found {} LZMA2 streams by scanning", streams.len()));
+        let mut archive = Archive::default();
+        if let Some(first) = streams.first() {
+            archive.pack_pos = first.start - SIGNATURE_HEADER_SIZE;
+        }
+        for (i, stream) in streams.iter().enumerate() {
+            // gaps between streams are stored as part of the stream in front of them
+            let end = streams
+                .get(i + 1)
+                .map_or(stream.start + stream.packed_size, |next| next.start);
+            archive.pack_sizes.push(end - stream.start);
+            archive
+                .folders
+                .push(lzma2_folder(stream.unpack_size, self.max_dict_size));
+            archive.files.push(unnamed_entry(i, 0, stream.unpack_size));
+        }
+        archive.pack_crcs = vec![0; streams.len()];
+        Archive::calculate_stream_map(&mut archive)?;
+        Ok(archive)
+    }
+
+    /// Whether the LZMA chunk at the start of `head` decodes to `unpack_size` bytes, or
+    /// without errors to the first [`PROBE_SIZE`] bytes if it's larger.
+    ///
+    /// The chunk resets the dictionary, so its first [`PROBE_SIZE`] bytes can't refer back
+    /// further than that and a dictionary of that size holds everything they need. The chunk is
+    /// decoded on its own, followed by an end marker.
+    fn verify_first_chunk(&self, head: &[u8], unpack_size: u64, out: &mut [u8]) -> bool {
+        let Some(chunk) = head
+            .get(3..5)
+            .map(|size| 6 + u16::from_be_bytes([size[0], size[1]]) as usize + 1)
+            .and_then(|len| head.get(..len))
+        else {
+            return false;
+        };
+        let probe = unpack_size.min(PROBE_SIZE as u64) as usize;
+        let dict_size = (probe as u32).max(DICT_SIZE_MIN);
+        let mut decoder = LZMA2Reader::new(chunk.chain(&[0][..]), dict_size, None);
+        let mut decoded = 0;
+        while decoded < probe {
+            match read_lzma2(&mut decoder, &mut out[..probe - decoded]) {
+                Ok(0) => return false,
+                Ok(n) => decoded += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+        // a chunk decoded completely has to end there
+        probe < unpack_size as usize || matches!(read_lzma2(&mut decoder, &mut out[..1]), Ok(0))
+    }
+
+    /// Decodes the LZMA2 stream at `start` as far as possible, returning whether it ended.
+    fn decode_stream(
+        &mut self,
+        start: u64,
+        out: &mut [u8],
+    ) -> Result<(ScannedStream, bool), Error> {
+        self.reader
+            .seek(SeekFrom::Start(start))
+            .map_err(Error::io)?;
+        let input = CountingReader {
+            inner: (&mut *self.reader).take(self.reader_len - start),
+            count: 0,
+        };
+        let dict_size = self.max_dict_size.max(DICT_SIZE_MIN);
+        let mut decoder = LZMA2Reader::new(input, dict_size, None);
+        let mut unpack_size = 0;
+        let complete = loop {
+            match read_lzma2(&mut decoder, out) {
+                Ok(0) => break true,
+                Ok(n) => unpack_size += n as u64,
+                Err(_) => break false,
+            }
+        };
+        let stream = ScannedStream {
+            start,
+            packed_size: decoder.get_ref().count,
+            unpack_size,
+        };
+        Ok((stream, complete))
+    }
+
+    /// Records blocks whose packed streams extend past the end of the archive.
+    fn check_pack_streams(&self, archive: &Archive, problems: &mut Vec<String>) {
+        for folder_index in 0..archive.folders.len() {
+            let first = archive.stream_map.folder_first_pack_stream_index[folder_index];
+            let count = archive.folders[folder_index].packed_streams.len();
+            let end = (first..first + count)
+                .map(|i| {
+                    SIGNATURE_HEADER_SIZE
+                        + archive.pack_pos
+                        + archive.stream_map.pack_stream_offsets[i]
+                        + archive.pack_sizes[i]
+                })
+                .max()
+                .unwrap_or(0);
+            if end > self.reader_len {
+                problems.push(format!(
+                    "block {folder_index} is cut off, it ends at {end} of {}",
+                    self.reader_len
+                ));
+            }
+        }
+    }
+}
+
+fn is_cut_off(e: &Error) -> bool {
+    matches!(e, Error::Io { source, .. } if source.kind() == ErrorKind::UnexpectedEof)
+}
+
+/// Rebuilds the entries of a header whose file properties are lost, one per unpack stream of
+/// each complete block.
+fn rebuild_entries(archive: &mut Archive, problems: &mut Vec<String>) {
+    let mut pack_streams = 0;
+    let complete = archive
+        .folders
+        .iter()
+        .take_while(|folder| {
+            pack_streams += folder.packed_streams.len();
+            !folder.coders.is_empty()
+                && !folder.packed_streams.is_empty()
+                && folder.unpack_sizes.len() == folder.total_output_streams
+                && pack_streams <= archive.pack_sizes.len()
+        })
+        .count();
+    if complete < archive.folders.len() {
+        problems.push(format!(
+            "{} of {} blocks are incomplete",
+            archive.folders.len() - complete,
+            archive.folders.len()
+        ));
+        archive.folders.truncate(complete);
+    }
+    let total = archive
+        .folders
+        .iter()
+        .map(|folder| folder.num_unpack_sub_streams)
+        .sum::<usize>();
+    let sub_streams = archive.sub_streams_info.as_ref().filter(|info| {
+        info.unpack_sizes.len() >= total
+            && info.crcs.len() >= total
+            && archive
+                .folders
+                .iter()
+                .all(|folder| folder.num_unpack_sub_streams > 0)
+    });
+    let mut files = Vec::new();
+    let mut stream_index = 0;
+    for (folder_index, folder) in archive.folders.iter_mut().enumerate() {
+        match sub_streams {
+            Some(info) => {
+                for i in 0..folder.num_unpack_sub_streams {
+                    let mut entry = unnamed_entry(folder_index, i, info.unpack_sizes[stream_index]);
+                    entry.has_crc = info.has_crc.contains(stream_index);
+                    entry.crc = info.crcs[stream_index];
+                    files.push(entry);
+                    stream_index += 1;
+                }
+            }
+            None => {
+                folder.num_unpack_sub_streams = 1;
+                let mut entry = unnamed_entry(folder_index, 0, folder.get_unpack_size());
+                entry.has_crc = folder.has_crc;
+                entry.crc = folder.crc;
+                files.push(entry);
+            }
+        }
+    }
+    if files.is_empty() {
+        return;
+    }
+    problems.push(format!(
+        "the names of {} entries are lost, they are named after their blocks",
+        files.len()
+    ));
+    archive.files = files;
+    if let Err(e) = Archive::calculate_stream_map(archive) {
+        problems.push(format!("the blocks are inconsistent: {e}"));
+        archive.files.clear();
+    }
+}
+
+fn unnamed_entry(folder_index: usize, index: usize, size: u64) -> SevenZArchiveEntry {
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = format!("block{folder_index}_{index}");
+    entry.has_stream = true;
+    entry.size = size;
+    entry
+}
+
+/// A block decoding a single LZMA2 stream to `unpack_size` bytes.
+fn lzma2_folder(unpack_size: u64, max_dict_size: u32) -> Folder {
+    let mut coder = Coder::default();
+    coder.id_size = SevenZMethod::ID_LZMA2.len();
+    coder
+        .decompression_method_id_mut()
+        .copy_from_slice(SevenZMethod::ID_LZMA2);
+    coder.num_in_streams = 1;
+    coder.num_out_streams = 1;
+    // the smallest dictionary holding everything the stream refers back to
+    let needed = unpack_size.clamp(
+        DICT_SIZE_MIN as u64,
+        max_dict_size.max(DICT_SIZE_MIN) as u64,
+    );
+    let props = (0..40u8)
+        .find(|&p| ((2 | (p as u64 & 1)) << (p / 2 + 11)) >= needed)
+        .unwrap_or(40);
+    coder.properties = vec![props];
+    Folder {
+        coders: vec![coder],
+        total_input_streams: 1,
+        total_output_streams: 1,
+        packed_streams: vec![0],
+        unpack_sizes: vec![unpack_size],
+        num_unpack_sub_streams: 1,
+        ..Default::default()
+    }
+}
+
+/// Returns the unpacked size of the first chunk if `head` may start an LZMA2 stream.
+fn lzma2_start(head: &[u8]) -> Option<u64> {
+    match *head {
+        // uncompressed chunk resetting the dictionary
+        [0x01, hi, lo, ..] => Some(u16::from_be_bytes([hi, lo]) as u64 + 1),
+        // LZMA chunk resetting everything, with valid properties and range coder start
+        [control, hi, lo, _, _, props, 0, ..] if control >= 0xE0 => {
+            let (lc, lp) = (props % 9, props / 9 % 5);
+            (props <= (4 * 5 + 4) * 9 + 8 && lc + lp <= 4).then(|| {
+                (((control & 0x1F) as u64) << 16 | u16::from_be_bytes([hi, lo]) as u64) + 1
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads from `decoder`, failing instead of panicking where lzma_rust indexes past its input
+/// buffer on some corrupt chunks.
+fn read_lzma2<R: Read>(decoder: &mut LZMA2Reader<R>, buf: &mut [u8]) -> std::io::Result<usize> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| decoder.read(buf))).unwrap_or_else(|_| {
+        Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "corrupt LZMA2 chunk",
+        ))
+    })
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,182 @@
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use super::{SevenZReader, SharedReader};
+use crate::{archive::Archive, block_cache::CachedEntryReader, decoders::DecodeOptions};
+
+/// Seekable reader of a single entry, see [`SevenZReader::open_entry_seekable`].
+pub(crate) enum EntrySeekReader<'a, R: Read + Seek> {
+    Empty,
+    Cached(CachedEntryReader),
+    Stored(StoredEntryReader<'a, R>),
+    Decoding(DecodingEntryReader<'a, R>),
+}
+
+impl<'a, R: Read + Seek> Read for EntrySeekReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Empty => Ok(0),
+            Self::Cached(r) => r.read(buf),
+            Self::Stored(r) => r.read(buf),
+            Self::Decoding(r) => r.read(buf),
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for EntrySeekReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::Empty => seek_position(0, 0, pos),
+            Self::Cached(r) => r.seek(pos),
+            Self::Stored(r) => {
+                r.pos = seek_position(r.pos, r.size, pos)?;
+                Ok(r.pos)
+            }
+            Self::Decoding(r) => {
+                r.pos = seek_position(r.pos, r.size, pos)?;
+                Ok(r.pos)
+            }
+        }
+    }
+}
+
+/// Computes the new position of a seek within an entry of `size` bytes.
+pub(crate) fn seek_position(current: u64, size: u64, pos: SeekFrom) -> std::io::Result<u64> {
+    let new_pos = match pos {
+        SeekFrom::Start(pos) => Some(pos),
+        SeekFrom::End(offset) => size.checked_add_signed(offset),
+        SeekFrom::Current(offset) => current.checked_add_signed(offset),
+    };
+    new_pos.ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+/// Entry data stored without compression, read straight from the source.
+pub(crate) struct StoredEntryReader<'a, R> {
+    source: &'a mut R,
+    /// Absolute position of the entry data in the source.
+    start: u64,
+    size: u64,
+    pos: u64,
+}
+
+impl<'a, R: Read + Seek> StoredEntryReader<'a, R> {
+    pub(crate) fn new(source: &'a mut R, start: u64, size: u64) -> Self {
+        Self {
+            source,
+            start,
+            size,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Read for StoredEntryReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.size {
+            return Ok(0);
+        }
+        self.source.seek(SeekFrom::Start(self.start + self.pos))?;
+        let max = buf.len().min((self.size - self.pos) as usize);
+        let n = self.source.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Entry data in a compressed block.
+///
+/// Seeking forward decodes and discards data, seeking backward decodes the block again
+/// from its start.
+pub(crate) struct DecodingEntryReader<'a, R: Read + Seek> {
+    /// Shared with `decoder`, which is rebuilt from it to seek backward.
+    source: SharedReader<&'a mut R>,
+    archive: &'a Archive,
+    password: &'a [u8],
+    options: DecodeOptions,
+    folder_index: usize,
+    /// Offset of the entry in the decoded block.
+    offset: u64,
+    size: u64,
+    pos: u64,
+    decoder: Option<Box<dyn Read + 'a>>,
+    /// Position of `decoder` relative to the start of the entry.
+    decoded_pos: u64,
+}
+
+impl<'a, R: Read + Seek> DecodingEntryReader<'a, R> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        source: &'a mut R,
+        archive: &'a Archive,
+        password: &'a [u8],
+        options: DecodeOptions,
+        folder_index: usize,
+        offset: u64,
+        size: u64,
+    ) -> Self {
+        Self {
+            source: SharedReader::new(source),
+            archive,
+            password,
+            options,
+            folder_index,
+            offset,
+            size,
+            pos: 0,
+            decoder: None,
+            decoded_pos: 0,
+        }
+    }
+
+    fn restart(&mut self) -> std::io::Result<()> {
+        self.decoder = None;
+        let (mut decoder, _) = SevenZReader::<R>::build_decode_stack(
+            self.source.clone(),
+            self.archive,
+            self.folder_index,
+            self.password,
+            &self.options,
+        )
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        skip(&mut decoder, self.offset)?;
+        self.decoder = Some(decoder);
+        self.decoded_pos = 0;
+        Ok(())
+    }
+}
+
+impl<'a, R: Read + Seek> Read for DecodingEntryReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.decoder.is_none() || self.decoded_pos > self.pos {
+            self.restart()?;
+        }
+        let decoder = self.decoder.as_mut().expect("decoder restarted above");
+        if self.decoded_pos < self.pos {
+            skip(decoder, self.pos - self.decoded_pos)?;
+            self.decoded_pos = self.pos;
+        }
+        let max = buf.len().min((self.size - self.pos) as usize);
+        let n = decoder.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        self.decoded_pos += n as u64;
+        Ok(n)
+    }
+}
+
+fn skip<D: Read + ?Sized>(decoder: &mut D, len: u64) -> std::io::Result<()> {
+    let skipped = std::io::copy(&mut decoder.take(len), &mut std::io::sink())?;
+    if skipped < len {
+        return Err(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "block ended before the entry",
+        ));
+    }
+    Ok(())
+}
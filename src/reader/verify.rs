@@ -0,0 +1,306 @@
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use super::{BoundedReader, SevenZReader, CRC32};
+use crate::{
+    archive::{SevenZMethod, SIGNATURE_HEADER_SIZE},
+    cancel::CancellableReader,
+    error::{ChecksumTarget, Error, UnsupportedFeature},
+    folder::Folder,
+    SevenZArchiveEntry,
+};
+
+/// Result of testing an entry, see [`SevenZReader::test`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    Ok,
+    /// The entry, its block or a packed stream of its block doesn't match its CRC32.
+    ChecksumMismatch {
+        target: ChecksumTarget,
+        expected: u32,
+        actual: u32,
+    },
+    /// The block of the entry uses a method or feature that isn't implemented.
+    Unsupported(UnsupportedFeature),
+    /// The block of the entry is encrypted and the password is missing or most likely wrong.
+    WrongPassword,
+    /// The data of the entry is cut off, e.g. by an interrupted download.
+    Truncated,
+    /// Decoding the block of the entry failed otherwise.
+    Failed(String),
+}
+
+impl EntryStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+
+    /// Status of the entries of a block failing with `e`.
+    pub(super) fn of_error(e: Error, encrypted: bool) -> Self {
+        match e {
+            Error::PasswordRequired | Error::MaybeBadPassword(_) => Self::WrongPassword,
+            Error::ChecksumMismatch { .. } if encrypted => Self::WrongPassword,
+            Error::ChecksumMismatch {
+                target,
+                expected,
+                actual,
+                ..
+            } => Self::ChecksumMismatch {
+                target,
+                expected,
+                actual,
+            },
+            Error::Unsupported { feature, .. } => Self::Unsupported(feature),
+            Error::Io { source, .. } if source.kind() == ErrorKind::UnexpectedEof => {
+                Self::Truncated
+            }
+            Error::Io { .. } if encrypted => Self::WrongPassword,
+            e => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Whether the data of a block is encrypted, so that decoding errors are most likely caused by
+/// a wrong password.
+pub(super) fn is_encrypted(folder: &Folder) -> bool {
+    folder
+        .coders
+        .iter()
+        .any(|coder| coder.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+}
+
+/// Result of [`SevenZReader::test`] and [`SevenZReader::for_each_entries_recovering`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestReport {
+    /// Status of each entry of [`crate::Archive::files`], by index.
+    pub entries: Vec<EntryStatus>,
+}
+
+impl TestReport {
+    /// Whether every entry is intact.
+    pub fn is_ok(&self) -> bool {
+        self.entries.iter().all(EntryStatus::is_ok)
+    }
+
+    /// Indices and statuses of the entries that aren't intact.
+    pub fn failures(&self) -> impl Iterator<Item = (usize, &EntryStatus)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| !status.is_ok())
+    }
+}
+
+impl<R: Read + Seek> SevenZReader<R> {
+    /// Checks every entry without extracting it, like `7z t`.
+    ///
+    /// Each block is decoded and discarded, verifying the CRCs of the packed streams, of the
+    /// block and of its entries. A failing block doesn't stop the test, the failure is reported
+    /// for its entries and the test goes on with the next block. Only cancellation, see
+    /// [`SevenZReader::set_cancellation_token`], and failing to seek the archive return an error.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use sevenz_rust::*;
+    /// let mut sz = SevenZReader::open("backup.7z", Password::empty()).unwrap();
+    /// let report = sz.test().unwrap();
+    /// for (index, status) in report.failures() {
+    ///     println!("{}: {:?}", sz.archive().files[index].name(), status);
+    /// }
+    /// ```
+    pub fn test(&mut self) -> Result<TestReport, Error> {
+        let archive_len = self.source.seek(SeekFrom::End(0)).map_err(Error::io)?;
+        let mut entries = vec![EntryStatus::Ok; self.archive.files.len()];
+        for folder_index in 0..self.archive.folders.len() {
+            self.check_cancelled()?;
+            let start = self.archive.stream_map.folder_first_file_index[folder_index];
+            let end = start + self.archive.folders[folder_index].num_unpack_sub_streams;
+            let statuses = &mut entries[start..end];
+            match self.test_pack_streams(folder_index, archive_len)? {
+                EntryStatus::Ok => self.test_folder(folder_index, statuses)?,
+                status => statuses.fill(status),
+            }
+        }
+        Ok(TestReport { entries })
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancellation {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the packed streams of a block are complete and match their CRCs.
+    fn test_pack_streams(
+        &mut self,
+        folder_index: usize,
+        archive_len: u64,
+    ) -> Result<EntryStatus, Error> {
+        let archive = &self.archive;
+        let first = archive.stream_map.folder_first_pack_stream_index[folder_index];
+        let count = archive.folders[folder_index].packed_streams.len();
+        for pack_index in first..first + count {
+            let offset = SIGNATURE_HEADER_SIZE
+                + archive.pack_pos
+                + archive.stream_map.pack_stream_offsets[pack_index];
+            let size = archive.pack_sizes[pack_index];
+            if offset.saturating_add(size) > archive_len {
+                return Ok(EntryStatus::Truncated);
+            }
+            if !archive.pack_crcs_defined.contains(pack_index) {
+                continue;
+            }
+            self.source
+                .seek(SeekFrom::Start(offset))
+                .map_err(Error::io)?;
+            let mut reader = BoundedReader::new(&mut self.source, size as usize);
+            let mut reader: Box<dyn Read> = match &self.cancellation {
+                Some(token) => Box::new(CancellableReader::new(reader, token.clone())),
+                None => Box::new(&mut reader),
+            };
+            let mut digest = CRC32.digest();
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => digest.update(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => match Error::io(e) {
+                        Error::Cancelled => return Err(Error::Cancelled),
+                        e => return Ok(EntryStatus::of_error(e, false)),
+                    },
+                }
+            }
+            let actual = digest.finalize();
+            let expected = archive.pack_crcs[pack_index] as u32;
+            if actual != expected {
+                return Ok(EntryStatus::ChecksumMismatch {
+                    target: ChecksumTarget::PackStream,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(EntryStatus::Ok)
+    }
+
+    /// Decodes a block, setting the `statuses` of its entries.
+    fn test_folder(
+        &mut self,
+        folder_index: usize,
+        statuses: &mut [EntryStatus],
+    ) -> Result<(), Error> {
+        let archive = &self.archive;
+        let encrypted = is_encrypted(&archive.folders[folder_index]);
+        let folder_reader = match Self::build_decode_stack(
+            &mut self.source,
+            archive,
+            folder_index,
+            &self.password,
+            &self.options,
+        ) {
+            Ok((folder_reader, _)) => folder_reader,
+            Err(e) => {
+                statuses.fill(EntryStatus::of_error(e, encrypted));
+                return Ok(());
+            }
+        };
+        let mut folder_reader: Box<dyn Read> = match &self.cancellation {
+            Some(token) => Box::new(CancellableReader::new(folder_reader, token.clone())),
+            None => folder_reader,
+        };
+        let start = archive.stream_map.folder_first_file_index[folder_index];
+        let files = &archive.files[start..start + statuses.len()];
+        let mut buf = vec![0; 64 * 1024];
+        for (i, file) in files.iter().enumerate() {
+            if !file.has_stream {
+                continue;
+            }
+            let mut reader = BoundedReader::new(&mut folder_reader, file.size as usize);
+            let mut digest = CRC32.digest();
+            let mut read = 0;
+            let result = loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break Ok(()),
+                    Ok(n) => {
+                        digest.update(&buf[..n]);
+                        read += n as u64;
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => break Err(Error::io(e)),
+                }
+            };
+            match result {
+                Err(e) => {
+                    Self::fail_folder(statuses, files, i, e, encrypted)?;
+                    return Ok(());
+                }
+                Ok(()) if read < file.size => {
+                    statuses[i..].fill(EntryStatus::Truncated);
+                    return Ok(());
+                }
+                Ok(()) => {
+                    let actual = digest.finalize();
+                    if file.has_crc && actual != file.crc as u32 {
+                        statuses[i] = if encrypted {
+                            EntryStatus::WrongPassword
+                        } else {
+                            EntryStatus::ChecksumMismatch {
+                                target: ChecksumTarget::Entry,
+                                expected: file.crc as u32,
+                                actual,
+                            }
+                        };
+                    }
+                }
+            }
+        }
+        // the CRC of the block is checked once its end is read
+        loop {
+            match folder_reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    Self::fail_folder(statuses, files, files.len(), Error::io(e), encrypted)?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the statuses of the entries of a block whose decoding failed with `e` while reading
+    /// entry `failed`.
+    fn fail_folder(
+        statuses: &mut [EntryStatus],
+        files: &[SevenZArchiveEntry],
+        failed: usize,
+        e: Error,
+        encrypted: bool,
+    ) -> Result<(), Error> {
+        if let Error::Cancelled = e {
+            return Err(e);
+        }
+        let block_mismatch = matches!(
+            e,
+            Error::ChecksumMismatch {
+                target: ChecksumTarget::Folder,
+                ..
+            }
+        );
+        let status = EntryStatus::of_error(e, encrypted);
+        for (i, entry_status) in statuses.iter_mut().enumerate() {
+            // the rest of the block can't be decoded. A bad block CRC only blames the entries
+            // decoded without an own CRC to check
+            let blamed = if block_mismatch {
+                files[i].has_stream && (i >= failed || !files[i].has_crc)
+            } else {
+                i >= failed
+            };
+            if blamed && entry_status.is_ok() {
+                *entry_status = status.clone();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+//! Selection of the files and directories to archive.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    glob::{self, Glob},
+    Error,
+};
+
+/// Walks a directory tree to select the files and directories to archive, see
+/// [`SevenZWriter::push_source_walker`](crate::SevenZWriter::push_source_walker).
+///
+/// Patterns are globs matched against paths relative to the root with `/` separators, a
+/// pattern without `/` matches the name of a file or directory at any depth. `\` escapes the
+/// next character, e.g. `\*` matches a literal `*`.
+/// Directories are walked in sorted order, so the result doesn't depend on the filesystem.
+/// Special files like FIFOs, sockets and devices are skipped.
+///
+/// # Examples
+/// ```no_run
+/// use sevenz_rust::*;
+/// let walker = SourceWalker::new("project")
+///     .exclude("target")
+///     .exclude("*.log")
+///     .ignore_file(".gitignore")
+///     .hidden(false);
+/// let mut sz = SevenZWriter::create("project.7z").unwrap();
+/// sz.push_source_walker(&walker).unwrap();
+/// sz.finish().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceWalker {
+    root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_files: Vec<String>,
+    max_depth: Option<usize>,
+    hidden: bool,
+    pub(crate) follow_symlinks: bool,
+    same_file_system: bool,
+}
+
+impl SourceWalker {
+    /// Walks everything below `root`, including hidden files, without following symlinks.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_files: Vec::new(),
+            max_depth: None,
+            hidden: true,
+            follow_symlinks: false,
+            same_file_system: false,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Only selects files matching one of the include patterns, like `-i!` of 7-Zip, and the
+    /// directories containing them.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Skips files and whole directories matching `pattern`, like `-x!` of 7-Zip.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Reads ignore rules from files named `name`, e.g. `.gitignore`, in every directory.
+    ///
+    /// The rules use the `.gitignore` syntax: `#` starts a comment, `!` re-includes, a
+    /// trailing `/` only matches directories and a leading or inner `/` anchors the pattern
+    /// to the directory of the ignore file.
+    pub fn ignore_file(mut self, name: impl Into<String>) -> Self {
+        self.ignore_files.push(name.into());
+        self
+    }
+
+    /// Stops at `depth` levels below the root, `1` only selects the children of the root.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Whether files and directories whose name starts with `.` are selected.
+    /// Default's true
+    pub fn hidden(mut self, enabled: bool) -> Self {
+        self.hidden = enabled;
+        self
+    }
+
+    /// Whether symbolic links are followed and archived as what they point to, instead of
+    /// being stored as links. Links back to a parent directory are skipped.
+    /// Default's false
+    pub fn follow_symlinks(mut self, enabled: bool) -> Self {
+        self.follow_symlinks = enabled;
+        self
+    }
+
+    /// Whether directories on other filesystems than the root are skipped, only on Unix.
+    /// Default's false
+    pub fn same_file_system(mut self, enabled: bool) -> Self {
+        self.same_file_system = enabled;
+        self
+    }
+
+    /// Returns the selected paths, the root first and directories before their contents.
+    pub fn walk(&self) -> Result<Vec<PathBuf>, Error> {
+        let compile = |patterns: &[String]| -> Result<Vec<Pattern>, Error> {
+            patterns.iter().map(|p| Pattern::new(p)).collect()
+        };
+        let mut walk = Walk {
+            walker: self,
+            include: compile(&self.include)?,
+            exclude: compile(&self.exclude)?,
+            rules: Vec::new(),
+            ancestors: Vec::new(),
+            root_device: None,
+            paths: vec![self.root.clone()],
+        };
+        let meta = walk.metadata(&self.root)?;
+        if meta.is_dir() {
+            walk.root_device = device(&meta);
+            walk.walk_dir(&self.root, "", 0)?;
+        }
+        Ok(walk.paths)
+    }
+}
+
+struct Walk<'a> {
+    walker: &'a SourceWalker,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    /// Rules of the ignore files of the current directory and its parents.
+    rules: Vec<IgnoreRule>,
+    /// Canonical paths of the current directory and its parents, to detect symlink loops.
+    ancestors: Vec<PathBuf>,
+    root_device: Option<u64>,
+    paths: Vec<PathBuf>,
+}
+
+impl Walk<'_> {
+    /// Adds the selected contents of `dir`, returns whether anything was added.
+    fn walk_dir(&mut self, dir: &Path, relative: &str, depth: usize) -> Result<bool, Error> {
+        if self.walker.max_depth.is_some_and(|max| depth >= max) {
+            return Ok(false);
+        }
+        if self.walker.follow_symlinks {
+            let canonical = fs::canonicalize(dir).map_err(|e| walk_error(e, dir))?;
+            if self.ancestors.contains(&canonical) {
+                return Ok(false);
+            }
+            self.ancestors.push(canonical);
+        }
+        let rules_len = self.rules.len();
+        for name in self.walker.ignore_files.iter() {
+            let path = dir.join(name);
+            if path.is_file() {
+                let content = fs::read_to_string(&path).map_err(|e| walk_error(e, &path))?;
+                self.rules.extend(IgnoreRule::parse(&content, relative)?);
+            }
+        }
+        let mut children = fs::read_dir(dir)
+            .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| walk_error(e, dir))?;
+        children.sort_by_key(|child| child.file_name());
+
+        let mut added = false;
+        for child in children {
+            let path = child.path();
+            let name = child.file_name().to_string_lossy().to_string();
+            let child_relative = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if !self.walker.hidden && name.starts_with('.') {
+                continue;
+            }
+            let meta = self.metadata(&path)?;
+            let is_dir = meta.is_dir();
+            if !(is_dir || meta.is_file() || meta.is_symlink())
+                || self
+                    .exclude
+                    .iter()
+                    .any(|p| p.matches(&child_relative, &name))
+                || self.is_ignored(&child_relative, &name, is_dir)
+            {
+                continue;
+            }
+            if is_dir {
+                if self.walker.same_file_system && device(&meta) != self.root_device {
+                    continue;
+                }
+                let index = self.paths.len();
+                self.paths.push(path.clone());
+                let selected = self.include.is_empty()
+                    || self
+                        .include
+                        .iter()
+                        .any(|p| p.matches(&child_relative, &name));
+                let has_contents = self.walk_dir(&path, &child_relative, depth + 1)?;
+                if selected || has_contents {
+                    added = true;
+                } else {
+                    self.paths.truncate(index);
+                }
+            } else if self.include.is_empty()
+                || self
+                    .include
+                    .iter()
+                    .any(|p| p.matches(&child_relative, &name))
+            {
+                self.paths.push(path);
+                added = true;
+            }
+        }
+        self.rules.truncate(rules_len);
+        if self.walker.follow_symlinks {
+            self.ancestors.pop();
+        }
+        Ok(added)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<fs::Metadata, Error> {
+        if self.walker.follow_symlinks {
+            // a dangling link is stored as a link
+            if let Ok(meta) = fs::metadata(path) {
+                return Ok(meta);
+            }
+        }
+        fs::symlink_metadata(path).map_err(|e| walk_error(e, path))
+    }
+
+    /// The last matching rule decides, like in git.
+    fn is_ignored(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(relative, name, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+fn walk_error(e: std::io::Error, path: &Path) -> Error {
+    Error::io_msg(e, format!("Failed to collect entries from path:{:?}", path))
+}
+
+struct Pattern {
+    glob: Glob,
+    /// Whether the pattern is matched against names instead of relative paths.
+    name_only: bool,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Result<Self, Error> {
+        let pattern = pattern.trim_end_matches('/');
+        Ok(Self {
+            glob: Glob::new(pattern)?,
+            name_only: !glob::has_separator(pattern),
+        })
+    }
+
+    fn matches(&self, relative: &str, name: &str) -> bool {
+        if self.name_only {
+            self.glob.matches(name)
+        } else {
+            self.glob.matches(relative)
+        }
+    }
+}
+
+struct IgnoreRule {
+    pattern: Pattern,
+    /// Relative path of the directory containing the ignore file.
+    base: String,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(content: &str, base: &str) -> Result<Vec<Self>, Error> {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let dir_only = line.ends_with('/');
+            let line = line.trim_end_matches('/');
+            if line.is_empty() {
+                continue;
+            }
+            rules.push(Self {
+                // a leading or inner separator anchors the pattern
+                pattern: Pattern::new(line)?,
+                base: base.to_string(),
+                negated,
+                dir_only,
+            });
+        }
+        Ok(rules)
+    }
+
+    fn matches(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            relative
+        } else {
+            match relative
+                .strip_prefix(self.base.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+        self.pattern.matches(relative, name)
+    }
+}
@@ -2,11 +2,20 @@ mod pack_info;
 mod seq_reader;
 mod unpack_info;
 
-use crate::{archive::*, encoders, lzma::*, reader::CRC32, Error, SevenZArchiveEntry};
+use crate::{
+    archive::*,
+    cancel::CancellationToken,
+    encoders,
+    lzma::*,
+    progress::{ProgressObserver, ProgressTracker},
+    reader::CRC32,
+    Error, SevenZArchiveEntry,
+};
 use bit_set::BitSet;
 use byteorder::*;
 use std::{
     cell::Cell,
+    collections::HashSet,
     fs::File,
     io::{Read, Seek, Write},
     path::Path,
@@ -71,17 +80,128 @@ pub struct SevenZWriter<W: Write> {
     pack_info: PackInfo,
     unpack_info: UnpackInfo,
     encrypt_header: bool,
+    memory_budget: Option<u64>,
+    pub(crate) store_hard_links: bool,
+    pub(crate) stored_times: StoredTimes,
+    pub(crate) path_prefix: String,
+    normalize_names: bool,
+    /// Names of the entries pushed so far, if duplicates are rejected.
+    unique_names: Option<HashSet<String>>,
+    #[cfg(feature = "nfc")]
+    nfc_names: bool,
+    progress: Option<ProgressTracker>,
+    cancellation: Option<CancellationToken>,
+}
+
+/// Which timestamps of files on disk are stored, like the `-mtm`, `-mtc` and `-mta` switches
+/// of 7-Zip. Only the modification time is stored by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredTimes {
+    pub modified: bool,
+    pub created: bool,
+    pub accessed: bool,
+}
+
+impl Default for StoredTimes {
+    fn default() -> Self {
+        Self {
+            modified: true,
+            created: false,
+            accessed: false,
+        }
+    }
+}
+
+impl StoredTimes {
+    /// Removes the timestamps that aren't stored from `entry`.
+    pub(crate) fn apply(&self, entry: &mut SevenZArchiveEntry) {
+        if !self.modified {
+            entry.has_last_modified_date = false;
+            entry.last_modified_date = Default::default();
+        }
+        if !self.created {
+            entry.has_creation_date = false;
+            entry.creation_date = Default::default();
+        }
+        if !self.accessed {
+            entry.has_access_date = false;
+            entry.access_date = Default::default();
+        }
+    }
+}
+
+/// Converts `\\` to `/`, removes roots, drive prefixes, `.` and empty components and rejects
+/// `..` and empty names. Only a leading `X:` component is a drive prefix, `a:b.txt` is kept.
+fn normalize_entry_name(name: &str) -> Result<String> {
+    let invalid = |reason| Error::InvalidEntryName {
+        name: name.to_string(),
+        reason,
+    };
+    let mut components = Vec::new();
+    for (i, component) in name.split(['/', '\\']).enumerate() {
+        let bytes = component.as_bytes();
+        let component =
+            if i == 0 && bytes.len() == 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+                ""
+            } else {
+                component
+            };
+        match component {
+            "" | "." => {}
+            ".." => return Err(invalid("contains a parent directory component")),
+            _ => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(invalid("is empty"));
+    }
+    Ok(components.join("/"))
+}
+
+/// Estimated memory needed by a chain of [`SevenZMethodConfiguration`]s, see
+/// [`SevenZWriter::estimate_memory`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Bytes used by the writer to compress one entry or solid block.
+    pub encode_bytes: u64,
+    /// Bytes a single-threaded reader needs to decompress the result.
+    pub decode_bytes: u64,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 impl SevenZWriter<File> {
     /// Creates a file to write a 7z archive to
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
         let file = std::fs::File::create(path.as_ref())
             .map_err(|e| crate::Error::file_open(e, path.as_ref().to_string_lossy().to_string()))?;
         Self::new(file)
     }
+
+    /// Estimates the memory used to compress with `methods` and to decompress the result.
+    ///
+    /// The LZMA and LZMA2 estimates include the match finder and dictionary, AES only needs
+    /// small block buffers. Fails with [`Error::Unsupported`] for methods the writer can't
+    /// compress with.
+    /// # Examples
+    /// ```
+    /// use sevenz_rust::*;
+    /// let methods = [SevenZMethodConfiguration::new(SevenZMethod::LZMA2)];
+    /// let estimate = SevenZWriter::estimate_memory(&methods).unwrap();
+    /// assert!(estimate.encode_bytes > estimate.decode_bytes);
+    /// ```
+    pub fn estimate_memory(methods: &[SevenZMethodConfiguration]) -> Result<MemoryEstimate> {
+        methods
+            .iter()
+            .try_fold(MemoryEstimate::default(), |total, mc| {
+                let (encode_kb, decode_kb) = encoders::method_memory_usage_kb(mc)?;
+                Ok(MemoryEstimate {
+                    encode_bytes: total.encode_bytes.saturating_add(encode_kb * 1024),
+                    decode_bytes: total.decode_bytes.saturating_add(decode_kb * 1024),
+                })
+            })
+    }
 }
+
 impl<W: Write + Seek> SevenZWriter<W> {
     /// Prepares writer to write a 7z archive to
     pub fn new(mut writer: W) -> Result<Self> {
@@ -98,6 +218,16 @@ impl<W: Write + Seek> SevenZWriter<W> {
             pack_info: Default::default(),
             unpack_info: Default::default(),
             encrypt_header: true,
+            memory_budget: None,
+            store_hard_links: false,
+            stored_times: Default::default(),
+            path_prefix: String::new(),
+            normalize_names: false,
+            unique_names: None,
+            #[cfg(feature = "nfc")]
+            nfc_names: false,
+            progress: None,
+            cancellation: None,
         })
     }
 
@@ -122,6 +252,199 @@ impl<W: Write + Seek> SevenZWriter<W> {
         self.encrypt_header = enabled;
     }
 
+    /// Sets the timestamps stored for files and directories added from disk by
+    /// [`SevenZWriter::push_source_path`] and the `compress*` functions.
+    pub fn set_stored_times(&mut self, stored_times: StoredTimes) -> &mut Self {
+        self.stored_times = stored_times;
+        self
+    }
+
+    /// Sets the archive path [`SevenZWriter::push_source_path`] puts the added tree under,
+    /// e.g. `"project"` adds `src/main.rs` as `project/src/main.rs` and the source directory
+    /// itself as `project`. Empty by default.
+    pub fn set_path_prefix(&mut self, prefix: impl AsRef<str>) -> &mut Self {
+        self.path_prefix = prefix
+            .as_ref()
+            .split(['/', '\\'])
+            .filter(|component| !component.is_empty() && *component != ".")
+            .collect::<Vec<_>>()
+            .join("/");
+        self
+    }
+
+    /// Whether entry names are normalized before they are written: `\` is converted to `/`,
+    /// leading separators, drive prefixes, `.` and empty components are removed, and names
+    /// containing `..` or nothing else fail with [`Error::InvalidEntryName`].
+    /// Without it names are written exactly as given.
+    /// Default's false
+    pub fn set_normalize_names(&mut self, enabled: bool) -> &mut Self {
+        self.normalize_names = enabled;
+        self
+    }
+
+    /// Whether pushing an entry with the name of an earlier entry fails with
+    /// [`Error::InvalidEntryName`]. Names are compared after normalization.
+    /// Default's false
+    pub fn set_reject_duplicate_names(&mut self, enabled: bool) -> &mut Self {
+        self.unique_names = if enabled {
+            Some(self.files.iter().map(|f| f.name.clone()).collect())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Whether entry names are converted to Unicode Normalization Form C, so names of files
+    /// from macOS, which stores decomposed names, match the same names typed elsewhere.
+    /// Default's false
+    #[cfg(feature = "nfc")]
+    pub fn set_nfc_names(&mut self, enabled: bool) -> &mut Self {
+        self.nfc_names = enabled;
+        self
+    }
+
+    /// Applies the name normalization and checks configured on the writer to `entry`.
+    /// `batch` holds the names of the entries pushed together with it so far, the name is only
+    /// recorded as taken by [`SevenZWriter::record_names`] once the entries were added.
+    fn prepare_entry_name(
+        &self,
+        entry: &mut SevenZArchiveEntry,
+        batch: &mut HashSet<String>,
+    ) -> Result<()> {
+        #[cfg(feature = "nfc")]
+        if self.nfc_names && !unicode_normalization::is_nfc(&entry.name) {
+            use unicode_normalization::UnicodeNormalization;
+            entry.name = entry.name.nfc().collect();
+        }
+        if self.normalize_names {
+            let name = normalize_entry_name(&entry.name)?;
+            // keeps the raw name of an unchanged entry read from an archive
+            if name != entry.name {
+                entry.name = name;
+            }
+        }
+        if let Some(names) = self.unique_names.as_ref() {
+            if names.contains(&entry.name) || !batch.insert(entry.name.clone()) {
+                return Err(Error::InvalidEntryName {
+                    name: entry.name.clone(),
+                    reason: "is a duplicate",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the names of the entries added from `first_index` on, for
+    /// [`SevenZWriter::set_reject_duplicate_names`]. Entries that failed to push or were
+    /// cancelled never get here, so their names stay free.
+    fn record_names(&mut self, first_index: usize) {
+        if let Some(names) = self.unique_names.as_mut() {
+            names.extend(self.files[first_index..].iter().map(|f| f.name.clone()));
+        }
+    }
+
+    /// Whether [`SevenZWriter::push_source_path`] stores later hard links to a file as link
+    /// entries instead of copies, see [`SevenZArchiveEntry::is_hard_link`].
+    /// Hard links are only detected on Unix.
+    ///
+    /// **Only this crate restores such entries as hard links.** The 7z format has no hard
+    /// links, so link entries use a file type of their own in the Unix mode, and 7-Zip, p7zip
+    /// and other tools extract them as regular files containing the name of the linked entry.
+    /// Keep the default to create archives that extract the same everywhere.
+    ///
+    /// Default's false, every link is stored as a copy of the file.
+    pub fn set_store_hard_links(&mut self, enabled: bool) -> &mut Self {
+        self.store_hard_links = enabled;
+        self
+    }
+
+    /// Limits the memory used to compress an entry or solid block to `max_bytes`.
+    /// Pushing entries whose content methods are estimated to need more fails with
+    /// [`Error::MemoryBudgetExceeded`] before anything is written, see [`SevenZWriter::estimate_memory`].
+    pub fn set_memory_budget(&mut self, max_bytes: u64) -> &mut Self {
+        self.memory_budget = Some(max_bytes);
+        self
+    }
+
+    /// Reports the progress of the entries pushed from now on to `observer`.
+    ///
+    /// The processed bytes are the bytes read from the sources of entries, and the packed
+    /// bytes are the bytes written to the archive. The total is only known if it's set with
+    /// [`SevenZWriter::set_progress_total`] or added by [`SevenZWriter::push_source_path`]
+    /// and the other methods reading files from disk.
+    pub fn set_progress_observer(
+        &mut self,
+        observer: impl ProgressObserver + Send + 'static,
+    ) -> &mut Self {
+        self.progress = Some(ProgressTracker::new(Box::new(observer)));
+        self
+    }
+
+    /// Sets the unpacked size of all entries that will be pushed, reported as
+    /// [`crate::Progress::total_bytes`].
+    pub fn set_progress_total(&mut self, total_bytes: u64) -> &mut Self {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.set_total(Some(total_bytes));
+        }
+        self
+    }
+
+    /// Stops pushing entries and [`SevenZWriter::finish`] with [`Error::Cancelled`] once `token`
+    /// is cancelled. The token is checked before each chunk of data read from a source.
+    ///
+    /// The entries pushed by a cancelled call are discarded, so the writer can still finish
+    /// an archive of the entries pushed before.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Fails with [`Error::Cancelled`] if the cancellation token was cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        match &self.cancellation {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the output back to the end of the streams written so far if `e` cancelled an
+    /// entry, so the data written for it is overwritten.
+    fn discard_cancelled(&mut self, e: Error) -> Error {
+        if !matches!(e, Error::Cancelled) {
+            return e;
+        }
+        let end = SIGNATURE_HEADER_SIZE + self.pack_info.sizes.iter().sum::<u64>();
+        match self.output.seek(std::io::SeekFrom::Start(end)) {
+            Ok(_) => e,
+            Err(e) => Error::io(e),
+        }
+    }
+
+    /// Adds `bytes` to the progress total, if progress is reported.
+    pub(crate) fn add_progress_total(&mut self, bytes: impl FnOnce() -> u64) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.add_total(bytes());
+        }
+    }
+
+    fn entry_started(
+        &mut self,
+        entry: &SevenZArchiveEntry,
+        entry_index: usize,
+        folder_index: Option<usize>,
+    ) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.entry_started(entry, entry_index, folder_index);
+        }
+    }
+
+    fn entry_finished(&mut self, entry_index: usize) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.set_packed(self.pack_info.sizes.iter().sum());
+            progress.entry_finished(&self.files[entry_index], entry_index);
+        }
+    }
+
     /// Create an archive entry using the file in `path` and entry_name provided.
     /// #deprecated use SevenZArchiveEntry::from_path instead
     #[deprecated]
@@ -165,28 +488,51 @@ impl<W: Write + Seek> SevenZWriter<W> {
     /// ```
     pub fn push_archive_entry<R: Read>(
         &mut self,
-        mut entry: SevenZArchiveEntry,
+        entry: SevenZArchiveEntry,
         reader: Option<R>,
     ) -> Result<&SevenZArchiveEntry> {
+        let entry_index = self
+            .push_entry(entry, reader)
+            .map_err(|e| self.discard_cancelled(e))?;
+        Ok(&self.files[entry_index])
+    }
+
+    /// Adds `entry` and returns its index.
+    fn push_entry<R: Read>(
+        &mut self,
+        mut entry: SevenZArchiveEntry,
+        reader: Option<R>,
+    ) -> Result<usize> {
+        self.check_cancelled()?;
+        self.prepare_entry_name(&mut entry, &mut HashSet::new())?;
+        let entry_index = self.files.len();
+        let folder_index =
+            (!entry.is_directory && reader.is_some()).then_some(self.pack_info.sizes.len());
+        self.entry_started(&entry, entry_index, folder_index);
         if !entry.is_directory {
             if let Some(mut r) = reader {
-                let mut compressed_len = 0;
-                let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len);
+                let packed_base: u64 = self.pack_info.sizes.iter().sum();
+                let compressed_len = Cell::new(0);
+                let mut compressed = CompressWrapWriter::new(&mut self.output, &compressed_len);
                 let content_methods = if entry.content_methods.is_empty() {
                     &self.content_methods
                 } else {
                     &entry.content_methods
                 };
+                check_memory_budget(self.memory_budget, content_methods)?;
                 let mut more_sizes: Vec<Rc<Cell<usize>>> =
                     Vec::with_capacity(content_methods.len() - 1);
 
                 let (crc, size) = {
                     let mut w =
                         Self::create_writer(content_methods, &mut compressed, &mut more_sizes)?;
-                    let mut write_len = 0;
-                    let mut w = CompressWrapWriter::new(&mut w, &mut write_len);
+                    let write_len = Cell::new(0);
+                    let mut w = CompressWrapWriter::new(&mut w, &write_len);
                     let mut buf = [0u8; 4096];
                     loop {
+                        if let Some(token) = &self.cancellation {
+                            token.check()?;
+                        }
                         match r.read(&mut buf) {
                             Ok(n) => {
                                 if n == 0 {
@@ -195,6 +541,10 @@ impl<W: Write + Seek> SevenZWriter<W> {
                                 w.write_all(&buf[..n]).map_err(|e| {
                                     Error::io_msg(e, format!("Encode entry:{}", entry.name()))
                                 })?;
+                                if let Some(progress) = self.progress.as_mut() {
+                                    progress.set_packed(packed_base + compressed_len.get() as u64);
+                                    progress.advance(n as u64);
+                                }
                             }
                             Err(e) => {
                                 return Err(Error::io_msg(
@@ -209,9 +559,10 @@ impl<W: Write + Seek> SevenZWriter<W> {
                     w.write(&[])
                         .map_err(|e| Error::io_msg(e, format!("Encode entry:{}", entry.name())))?;
 
-                    (w.crc_value(), write_len)
+                    (w.crc_value(), write_len.get())
                 };
                 let compressed_crc = compressed.crc_value();
+                let compressed_len = compressed_len.get();
                 entry.has_stream = true;
                 entry.size = size as u64;
                 entry.crc = crc as u64;
@@ -228,7 +579,9 @@ impl<W: Write + Seek> SevenZWriter<W> {
                 self.unpack_info.add(content_methods.clone(), sizes, crc);
 
                 self.files.push(entry);
-                return Ok(self.files.last().unwrap());
+                self.record_names(entry_index);
+                self.entry_finished(entry_index);
+                return Ok(entry_index);
             }
         }
         entry.has_stream = false;
@@ -236,7 +589,9 @@ impl<W: Write + Seek> SevenZWriter<W> {
         entry.compressed_size = 0;
         entry.has_crc = false;
         self.files.push(entry);
-        Ok(self.files.last().unwrap())
+        self.record_names(entry_index);
+        self.entry_finished(entry_index);
+        Ok(entry_index)
     }
 
     /// [Solid compression](https://en.wikipedia.org/wiki/Solid_compression)
@@ -245,20 +600,41 @@ impl<W: Write + Seek> SevenZWriter<W> {
     /// Panics if `entries`'s length not equals to `reader.reader_len()`
     pub fn push_archive_entries<R: Read>(
         &mut self,
-        mut entries: Vec<SevenZArchiveEntry>,
+        entries: Vec<SevenZArchiveEntry>,
         reader: SeqReader<SourceReader<R>>,
     ) -> Result<&mut Self> {
+        self.push_entries(entries, reader)
+            .map_err(|e| self.discard_cancelled(e))?;
+        Ok(self)
+    }
+
+    fn push_entries<R: Read>(
+        &mut self,
+        mut entries: Vec<SevenZArchiveEntry>,
+        reader: SeqReader<SourceReader<R>>,
+    ) -> Result<()> {
+        self.check_cancelled()?;
         let mut r = reader;
         assert_eq!(r.reader_len(), entries.len());
-        let mut compressed_len = 0;
-        let mut compressed = CompressWrapWriter::new(&mut self.output, &mut compressed_len);
+        let mut batch = HashSet::new();
+        for entry in entries.iter_mut() {
+            self.prepare_entry_name(entry, &mut batch)?;
+        }
+        let first_index = self.files.len();
+        let folder_index = Some(self.pack_info.sizes.len());
+        let packed_base: u64 = self.pack_info.sizes.iter().sum();
+        // entries whose start was reported
+        let mut started = 0;
+        let compressed_len = Cell::new(0);
+        let mut compressed = CompressWrapWriter::new(&mut self.output, &compressed_len);
         let content_methods = &self.content_methods;
+        check_memory_budget(self.memory_budget, content_methods)?;
         let mut more_sizes: Vec<Rc<Cell<usize>>> = Vec::with_capacity(content_methods.len() - 1);
 
         let (crc, size) = {
             let mut w = Self::create_writer(content_methods, &mut compressed, &mut more_sizes)?;
-            let mut write_len = 0;
-            let mut w = CompressWrapWriter::new(&mut w, &mut write_len);
+            let write_len = Cell::new(0);
+            let mut w = CompressWrapWriter::new(&mut w, &write_len);
             let mut buf = [0u8; 4096];
             fn entries_names(entries: &[SevenZArchiveEntry]) -> String {
                 let mut names = String::with_capacity(512);
@@ -272,6 +648,9 @@ impl<W: Write + Seek> SevenZWriter<W> {
                 names
             }
             loop {
+                if let Some(token) = &self.cancellation {
+                    token.check()?;
+                }
                 match r.read(&mut buf) {
                     Ok(n) => {
                         if n == 0 {
@@ -280,6 +659,18 @@ impl<W: Write + Seek> SevenZWriter<W> {
                         w.write_all(&buf[..n]).map_err(|e| {
                             Error::io_msg(e, format!("Encode entries:{}", entries_names(&entries)))
                         })?;
+                        if let Some(progress) = self.progress.as_mut() {
+                            while started <= r.current() {
+                                progress.entry_started(
+                                    &entries[started],
+                                    first_index + started,
+                                    folder_index,
+                                );
+                                started += 1;
+                            }
+                            progress.set_packed(packed_base + compressed_len.get() as u64);
+                            progress.advance(n as u64);
+                        }
                     }
                     Err(e) => {
                         return Err(Error::io_msg(
@@ -304,9 +695,10 @@ impl<W: Write + Seek> SevenZWriter<W> {
                 Error::io_msg(e, format!("Encode entry:{}", entries_names(&entries)))
             })?;
 
-            (w.crc_value(), write_len)
+            (w.crc_value(), write_len.get())
         };
         let compressed_crc = compressed.crc_value();
+        let compressed_len = compressed_len.get();
         let mut sub_stream_crcs = Vec::with_capacity(entries.len());
         let mut sub_stream_sizes = Vec::with_capacity(entries.len());
         for i in 0..entries.len() {
@@ -318,6 +710,15 @@ impl<W: Write + Seek> SevenZWriter<W> {
             sub_stream_sizes.push(entry.size);
             entry.has_crc = true;
         }
+        if let Some(first) = entries.first_mut() {
+            first.compressed_size = compressed_len as u64;
+        }
+        if let Some(progress) = self.progress.as_mut() {
+            // entries without data
+            for i in started..entries.len() {
+                progress.entry_started(&entries[i], first_index + i, folder_index);
+            }
+        }
 
         self.pack_info
             .add_stream(compressed_len as u64, compressed_crc);
@@ -336,7 +737,11 @@ impl<W: Write + Seek> SevenZWriter<W> {
         );
 
         self.files.extend(entries);
-        Ok(self)
+        self.record_names(first_index);
+        for entry_index in first_index..self.files.len() {
+            self.entry_finished(entry_index);
+        }
+        Ok(())
     }
 
     fn create_writer<'a, O: Write + 'a>(
@@ -361,7 +766,12 @@ impl<W: Write + Seek> SevenZWriter<W> {
     }
 
     /// Finishes the compression.
+    ///
+    /// Fails with an I/O error carrying [`Error::Cancelled`] if the cancellation token was
+    /// cancelled, see [`SevenZWriter::set_cancellation_token`].
     pub fn finish(mut self) -> std::io::Result<W> {
+        self.check_cancelled()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         let mut header: Vec<u8> = Vec::with_capacity(64 * 1024);
         self.write_encoded_header(&mut header)?;
         let header_pos = self.output.stream_position()?;
@@ -428,8 +838,8 @@ impl<W: Write + Seek> SevenZWriter<W> {
 
         let mut encoded_data = Vec::with_capacity(size as usize / 2);
 
-        let mut compress_size = 0;
-        let mut compressed = CompressWrapWriter::new(&mut encoded_data, &mut compress_size);
+        let compress_size = Cell::new(0);
+        let mut compressed = CompressWrapWriter::new(&mut encoded_data, &compress_size);
         {
             let mut encoder = Self::create_writer(&methods, &mut compressed, &mut more_sizes)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
@@ -438,7 +848,7 @@ impl<W: Write + Seek> SevenZWriter<W> {
         }
 
         let compress_crc = compressed.crc_value();
-        let compress_size = *compressed.bytes_written;
+        let compress_size = compressed.bytes_written.get();
         if compress_size as u64 + 20 >= size {
             // compression made it worse. Write raw data
             header.write_all(&raw_header)?;
@@ -569,7 +979,7 @@ impl<W: Write + Seek> SevenZWriter<W> {
         let out = &mut temp;
         out.write_u8(0)?;
         for file in self.files.iter() {
-            for c in file.name().encode_utf16() {
+            for c in file.raw_name().iter() {
                 let buf = c.to_le_bytes();
                 out.write_all(&buf)?;
             }
@@ -602,6 +1012,20 @@ impl<W: Write + Seek> SevenZWriter<W> {
     );
 }
 
+fn check_memory_budget(budget: Option<u64>, methods: &[SevenZMethodConfiguration]) -> Result<()> {
+    let Some(max_bytes) = budget else {
+        return Ok(());
+    };
+    let estimate = SevenZWriter::estimate_memory(methods)?.encode_bytes;
+    if estimate > max_bytes {
+        return Err(Error::MemoryBudgetExceeded {
+            budget: max_bytes,
+            estimate,
+        });
+    }
+    Ok(())
+}
+
 pub(crate) fn write_u64<W: Write>(header: &mut W, mut value: u64) -> std::io::Result<()> {
     let mut first = 0;
     let mut mask = 0x80;
@@ -647,10 +1071,10 @@ struct CompressWrapWriter<'a, W> {
     writer: W,
     crc: crc::Digest<'static, u32>,
     cache: Vec<u8>,
-    bytes_written: &'a mut usize,
+    bytes_written: &'a Cell<usize>,
 }
 impl<'a, W: Write> CompressWrapWriter<'a, W> {
-    pub fn new(writer: W, bytes_written: &'a mut usize) -> Self {
+    pub fn new(writer: W, bytes_written: &'a Cell<usize>) -> Self {
         Self {
             writer,
             crc: crate::reader::CRC32.digest(),
@@ -670,7 +1094,7 @@ impl<'a, W: Write> Write for CompressWrapWriter<'a, W> {
         self.cache.resize(buf.len(), Default::default());
         let len = self.writer.write(buf)?;
         self.crc.update(&buf[..len]);
-        *self.bytes_written += len;
+        self.bytes_written.set(self.bytes_written.get() + len);
         Ok(len)
     }
 
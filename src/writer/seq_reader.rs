@@ -52,6 +52,11 @@ impl<R> SeqReader<R> {
     pub fn reader_len(&self) -> usize {
         self.readers.len()
     }
+
+    /// Index of the reader the last data was read from.
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
 }
 
 impl<R: Read> Read for SeqReader<R> {
@@ -118,20 +123,74 @@ impl<R> SourceReader<R> {
     }
 }
 
+/// Content of a file to archive: the file itself, or the target of a link.
+pub(crate) enum SourceFile {
+    File(File),
+    Link(io::Cursor<Vec<u8>>),
+}
+
+impl SourceFile {
+    /// Opens `path`, reading the target of a symbolic link unless `follow_symlinks` is set
+    /// and the link points to a file.
+    pub(crate) fn open(path: &Path, follow_symlinks: bool) -> io::Result<Self> {
+        let followed = follow_symlinks && path.is_file();
+        if !followed && path.symlink_metadata()?.is_symlink() {
+            Ok(Self::Link(io::Cursor::new(symlink_target(path)?)))
+        } else {
+            File::open(path).map(Self::File)
+        }
+    }
+}
+
+impl Read for SourceFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SourceFile::File(f) => f.read(buf),
+            SourceFile::Link(target) => target.read(buf),
+        }
+    }
+}
+
+/// Target of the symbolic link at `path`, with `/` separators.
+fn symlink_target(path: &Path) -> io::Result<Vec<u8>> {
+    let target = std::fs::read_link(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(target.as_os_str().as_bytes().to_vec())
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(target.to_string_lossy().replace('\\', "/").into_bytes())
+    }
+}
+
 pub(crate) struct LazyFileReader {
     path: PathBuf,
-    reader: Option<File>,
+    follow_symlinks: bool,
+    reader: Option<SourceFile>,
     end: bool,
 }
 
 impl LazyFileReader {
-    pub fn new(path: PathBuf) -> Self {
+    pub fn new(path: PathBuf, follow_symlinks: bool) -> Self {
         Self {
             path,
+            follow_symlinks,
             reader: None,
             end: false,
         }
     }
+
+    /// Reads `content` instead of a file, e.g. the target of a hard link.
+    pub fn with_content(content: Vec<u8>) -> Self {
+        Self {
+            path: PathBuf::new(),
+            follow_symlinks: false,
+            reader: Some(SourceFile::Link(io::Cursor::new(content))),
+            end: false,
+        }
+    }
 }
 
 impl Read for LazyFileReader {
@@ -140,7 +199,7 @@ impl Read for LazyFileReader {
             return Ok(0);
         }
         if self.reader.is_none() {
-            self.reader = Some(File::open(&self.path)?);
+            self.reader = Some(SourceFile::open(&self.path, self.follow_symlinks)?);
         }
         let n = self.reader.as_mut().unwrap().read(buf)?;
         if n == 0 {
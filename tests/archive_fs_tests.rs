@@ -0,0 +1,111 @@
+use sevenz_rust::*;
+
+#[test]
+fn archive_fs_open_stored_file() -> anyhow::Result<()> {
+    let reader = SevenZReader::open("tests/resources/copy.7z", Password::empty())?;
+    let mut fs = ArchiveFs::new(reader);
+    let name = fs.archive().files[0].name().to_string();
+
+    assert!(fs.exists(&name));
+    assert!(fs.metadata(&name)?.is_file());
+    let mut content = String::new();
+    std::io::Read::read_to_string(&mut fs.open(&name)?, &mut content)?;
+    assert_eq!(content, "simple copy encoding");
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn archive_fs_implied_directories() -> anyhow::Result<()> {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    let temp_dir = tempfile::tempdir()?;
+    let dest = temp_dir.path().join("fs.7z");
+    let mut sz = SevenZWriter::create(&dest)?;
+    for (name, content) in [
+        ("docs/readme.txt", "read me"),
+        ("docs/guide/intro.md", "introduction"),
+        ("src/main.rs", "fn main() {}"),
+    ] {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        sz.push_archive_entry(entry, Some(Cursor::new(content)))?;
+    }
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "empty".to_string();
+    entry.is_directory = true;
+    sz.push_archive_entry::<&[u8]>(entry, None)?;
+    sz.finish()?;
+
+    let mut fs = ArchiveFs::new(SevenZReader::open(&dest, Password::empty())?);
+
+    let names = |entries: Vec<ArchiveFsEntry>| -> Vec<String> {
+        entries.iter().map(|e| e.path().to_string()).collect()
+    };
+    assert_eq!(names(fs.read_dir("")?), ["docs", "empty", "src"]);
+    assert_eq!(
+        names(fs.read_dir("/docs/")?),
+        ["docs/guide", "docs/readme.txt"]
+    );
+    assert!(fs.read_dir("empty")?.is_empty());
+    assert!(fs.read_dir("src/main.rs").is_err());
+
+    let docs = fs.metadata("docs")?;
+    assert!(docs.is_dir());
+    assert_eq!(docs.file_index(), None);
+    assert!(fs.metadata("empty")?.file_index().is_some());
+    let intro = fs.metadata("docs\\guide\\intro.md")?;
+    assert_eq!(intro.name(), "intro.md");
+    assert_eq!(intro.size(), 12);
+    assert!(!fs.exists("docs/missing.txt"));
+    assert!(fs.metadata("docs/missing.txt").is_err());
+
+    assert_eq!(
+        names(fs.glob("**/*.[mr][ds]")?),
+        ["docs/guide/intro.md", "src/main.rs"]
+    );
+    assert_eq!(names(fs.glob("docs/*")?), ["docs/guide", "docs/readme.txt"]);
+    assert_eq!(names(fs.glob("?rc")?), ["src"]);
+
+    assert!(fs.open("docs").is_err());
+    let mut file = fs.open("docs/guide/intro.md")?;
+    file.seek(SeekFrom::Start(5))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    assert_eq!(content, "duction");
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn archive_fs_glob_deep_paths() -> anyhow::Result<()> {
+    use std::io::Cursor;
+
+    let temp_dir = tempfile::tempdir()?;
+    let dest = temp_dir.path().join("deep.7z");
+    let deep = "a/".repeat(60);
+    let long_name = "a".repeat(60);
+    let mut sz = SevenZWriter::create(&dest)?;
+    for name in [format!("{deep}x"), format!("{deep}y"), long_name.clone()] {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name;
+        sz.push_archive_entry(entry, Some(Cursor::new("content")))?;
+    }
+    sz.finish()?;
+
+    let fs = ArchiveFs::new(SevenZReader::open(&dest, Password::empty())?);
+    let names = |entries: Vec<ArchiveFsEntry>| -> Vec<String> {
+        entries.iter().map(|e| e.path().to_string()).collect()
+    };
+    // backtracking over every way to split the path between the wildcards would never finish
+    let pattern = "**/".repeat(10) + "x";
+    assert_eq!(names(fs.glob(&pattern)?), [format!("{deep}x")]);
+    let pattern = "*a".repeat(10) + "*b";
+    assert!(fs.glob(&pattern)?.is_empty());
+    let pattern = "*a".repeat(10) + "*";
+    assert_eq!(names(fs.glob(&pattern)?), [long_name]);
+
+    Ok(())
+}
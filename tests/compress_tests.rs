@@ -173,3 +173,337 @@ fn compress_one_file_with_random_content_encrypted() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "compress")]
+#[test]
+fn compress_with_memory_budget() -> anyhow::Result<()> {
+    let small = [SevenZMethodConfiguration::new(SevenZMethod::LZMA2)
+        .with_options(MethodOptions::Num(1 << 20))];
+    let large = [SevenZMethodConfiguration::new(SevenZMethod::LZMA2)
+        .with_options(MethodOptions::Num(64 << 20))];
+    let small_estimate = SevenZWriter::estimate_memory(&small)?;
+    let large_estimate = SevenZWriter::estimate_memory(&large)?;
+    assert!(small_estimate.encode_bytes < large_estimate.encode_bytes);
+    assert!(large_estimate.decode_bytes >= 64 << 20);
+    assert!(
+        SevenZWriter::estimate_memory(&[SevenZMethodConfiguration::new(SevenZMethod::ZSTD)])
+            .is_err()
+    );
+
+    let mut writer = SevenZWriter::new(std::io::Cursor::new(Vec::new()))?;
+    writer.set_memory_budget(large_estimate.encode_bytes - 1);
+    writer.set_content_methods(large.to_vec());
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "a.txt".to_string();
+    let result = writer.push_archive_entry(entry.clone(), Some(&b"content"[..]));
+    assert!(matches!(
+        result,
+        Err(Error::MemoryBudgetExceeded { budget, estimate })
+            if budget == large_estimate.encode_bytes - 1 && estimate == large_estimate.encode_bytes
+    ));
+
+    writer.set_content_methods(small.to_vec());
+    writer.push_archive_entry(entry, Some(&b"content"[..]))?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn compress_normalizes_entry_names() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let dest = temp_dir.path().join("names.7z");
+    let entry = |name: &str| {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry
+    };
+    let mut sz = SevenZWriter::create(&dest)?;
+    sz.set_normalize_names(true)
+        .set_reject_duplicate_names(true);
+    for (name, expected) in [
+        ("/abs/a.txt", "abs/a.txt"),
+        ("C:\\win\\b.txt", "win/b.txt"),
+        ("a:b.txt", "a:b.txt"),
+        ("./dir//./c.txt", "dir/c.txt"),
+        ("dir/", "dir"),
+    ] {
+        let pushed = sz.push_archive_entry(entry(name), Some(&b"content"[..]))?;
+        assert_eq!(pushed.name(), expected);
+    }
+    for (name, reason) in [
+        ("../evil.txt", "contains a parent directory component"),
+        ("a/../../evil.txt", "contains a parent directory component"),
+        ("/./", "is empty"),
+        ("", "is empty"),
+        ("D:", "is empty"),
+        ("abs\\a.txt", "is a duplicate"),
+    ] {
+        let result = sz.push_archive_entry(entry(name), Some(&b"content"[..]));
+        assert!(
+            matches!(result, Err(Error::InvalidEntryName { reason: r, .. }) if r == reason),
+            "{name}: {result:?}"
+        );
+    }
+    // the name of an entry that failed to push stays free
+    struct Failing;
+    impl std::io::Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "failing"))
+        }
+    }
+    assert!(sz
+        .push_archive_entry(entry("e.txt"), Some(Failing))
+        .is_err());
+    let batch = vec![entry("f.txt"), entry("f.txt")];
+    let readers = vec![SourceReader::new(&b"1"[..]), SourceReader::new(&b"2"[..])];
+    let result = sz.push_archive_entries(batch, SeqReader::new(readers));
+    assert!(matches!(result, Err(Error::InvalidEntryName { .. })));
+    sz.push_archive_entry(entry("e.txt"), Some(&b"content"[..]))?;
+    sz.push_archive_entry(entry("f.txt"), Some(&b"content"[..]))?;
+    sz.finish()?;
+
+    // names are written as given by default
+    let mut sz = SevenZWriter::new(std::io::Cursor::new(Vec::new()))?;
+    let pushed = sz.push_archive_entry(entry("./a\\b"), Some(&b"content"[..]))?;
+    assert_eq!(pushed.name(), "./a\\b");
+
+    #[cfg(feature = "nfc")]
+    {
+        sz.set_nfc_names(true);
+        // "e" followed by a combining acute accent
+        let pushed = sz.push_archive_entry(entry("cafe\u{301}.txt"), Some(&b"content"[..]))?;
+        assert_eq!(pushed.name(), "caf\u{e9}.txt");
+    }
+
+    let archive = Archive::open(&dest)?;
+    let names: Vec<&str> = archive.files.iter().map(|f| f.name()).collect();
+    assert_eq!(
+        names,
+        [
+            "abs/a.txt",
+            "win/b.txt",
+            "a:b.txt",
+            "dir/c.txt",
+            "dir",
+            "e.txt",
+            "f.txt"
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[derive(Clone, Default)]
+struct RecordingObserver {
+    events: std::sync::Arc<std::sync::Mutex<Vec<(String, String, Progress)>>>,
+}
+
+#[cfg(feature = "compress")]
+impl RecordingObserver {
+    fn take(&self) -> Vec<(String, String, Progress)> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "compress")]
+impl ProgressObserver for RecordingObserver {
+    fn on_entry_start(&mut self, entry: &SevenZArchiveEntry, progress: &Progress) {
+        let event = ("start".to_string(), entry.name().to_string(), *progress);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_progress(&mut self, progress: &Progress) {
+        let event = ("progress".to_string(), String::new(), *progress);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_entry_finish(&mut self, entry: &SevenZArchiveEntry, progress: &Progress) {
+        let event = (
+            "finish".to_string(),
+            format!("{}:{}", entry.name(), entry.compressed_size),
+            *progress,
+        );
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn compress_and_extract_report_progress() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let src = temp_dir.path().join("src");
+    std::fs::create_dir_all(src.join("dir"))?;
+    std::fs::write(src.join("a.txt"), vec![b'a'; 10_000])?;
+    std::fs::write(src.join("dir/b.txt"), vec![b'b'; 20_000])?;
+    let observer = RecordingObserver::default();
+
+    // one entry per block
+    let dest = temp_dir.path().join("progress.7z");
+    compress_to_path_with_progress(&src, &dest, observer.clone())?;
+    let events = observer.take();
+    let last = events.last().unwrap().2;
+    assert_eq!(last.total_bytes, Some(30_000));
+    assert_eq!(last.processed_bytes, 30_000);
+    let archive = Archive::open(&dest)?;
+    assert_eq!(last.packed_bytes, archive.pack_sizes.iter().sum::<u64>());
+    let finished: Vec<&str> = events
+        .iter()
+        .filter(|e| e.0 == "finish")
+        .map(|e| e.1.as_str())
+        .collect();
+    assert_eq!(finished.len(), 3);
+    assert!(finished.contains(&"dir:0"));
+    assert!(finished
+        .iter()
+        .any(|f| f.starts_with("a.txt:") && !f.ends_with(":0")));
+
+    // solid
+    let mut sz = SevenZWriter::new(std::io::Cursor::new(Vec::new()))?;
+    sz.set_progress_observer(observer.clone());
+    sz.push_source_path(&src, |_| true)?;
+    let data = sz.finish()?.into_inner();
+    let events = observer.take();
+    let mut starts: Vec<(&str, Option<usize>)> = events
+        .iter()
+        .filter(|e| e.0 == "start")
+        .map(|e| (e.1.as_str(), e.2.folder_index))
+        .collect();
+    starts.sort();
+    assert_eq!(
+        starts,
+        [("a.txt", Some(0)), ("dir", None), ("dir/b.txt", Some(0))]
+    );
+    let last = events.last().unwrap();
+    assert_eq!(last.0, "finish");
+    assert_eq!(last.2.processed_bytes, 30_000);
+    assert_eq!(last.2.total_bytes, Some(30_000));
+    // the compressed size of a solid block is set on its first entry
+    let solid_sizes: Vec<bool> = events
+        .iter()
+        .filter(|e| e.0 == "finish" && e.2.entry_index > Some(0))
+        .map(|e| e.1.ends_with(":0"))
+        .collect();
+    assert_eq!(solid_sizes, [false, true]);
+
+    // extraction
+    let len = data.len() as u64;
+    let mut reader = SevenZReader::new(std::io::Cursor::new(data), len, Password::empty())?;
+    reader.set_progress_observer(observer.clone());
+    reader.for_each_entries(|_, reader| {
+        std::io::copy(reader, &mut std::io::sink())?;
+        Ok(true)
+    })?;
+    let events = observer.take();
+    let last = events.last().unwrap();
+    assert_eq!(last.0, "finish");
+    assert_eq!(last.2.total_bytes, Some(30_000));
+    assert_eq!(last.2.processed_bytes, 30_000);
+    assert!(last.2.packed_bytes > 0 && last.2.packed_bytes <= len);
+    let entries: Vec<(&str, &str, Option<usize>)> = events
+        .iter()
+        .filter(|e| e.0 != "progress")
+        .map(|e| (e.0.as_str(), e.1.as_str(), e.2.entry_index))
+        .collect();
+    assert_eq!(entries.len(), 6);
+    for pair in entries.chunks(2) {
+        assert_eq!((pair[0].0, pair[1].0), ("start", "finish"));
+        assert_eq!(pair[0].2, pair[1].2);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn compress_and_extract_cancellation() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    /// Cancels `token` once `after` bytes were read.
+    struct CancellingReader<R> {
+        inner: R,
+        token: CancellationToken,
+        after: usize,
+    }
+
+    impl<R: Read> Read for CancellingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.after = self.after.saturating_sub(n);
+            if self.after == 0 {
+                self.token.cancel();
+            }
+            Ok(n)
+        }
+    }
+
+    let temp_dir = tempdir()?;
+    let dest = temp_dir.path().join("cancel.7z");
+    let entry = |name: &str| {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry
+    };
+    let big = vec![7u8; 1 << 20];
+    let mut sz = SevenZWriter::create(&dest)?;
+    let token = CancellationToken::new();
+    sz.set_cancellation_token(token.clone());
+    sz.push_archive_entry(entry("a.txt"), Some(&b"first"[..]))?;
+    let reader = CancellingReader {
+        inner: big.as_slice(),
+        token: token.clone(),
+        after: 64 * 1024,
+    };
+    let result = sz.push_archive_entry(entry("big.bin"), Some(reader));
+    assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+    assert!(token.is_cancelled());
+    // the cancelled entry is discarded and the writer can go on with a new token
+    sz.set_cancellation_token(CancellationToken::new());
+    sz.push_archive_entry(entry("c.txt"), Some(&b"last"[..]))?;
+    sz.finish()?;
+
+    let mut contents = Vec::new();
+    decompress_file_with_extract_fn(&dest, temp_dir.path().join("out"), |entry, reader, _| {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        contents.push((entry.name().to_string(), content));
+        Ok(true)
+    })?;
+    assert_eq!(
+        contents,
+        [
+            ("a.txt".to_string(), "first".to_string()),
+            ("c.txt".to_string(), "last".to_string())
+        ]
+    );
+
+    // a cancelled archive is removed
+    let src = temp_dir.path().join("src");
+    std::fs::create_dir_all(&src)?;
+    std::fs::write(src.join("big.bin"), &big)?;
+    let cancelled = CancellationToken::new();
+    cancelled.cancel();
+    let archive = temp_dir.path().join("removed.7z");
+    let result = compress_to_path_with_cancellation(&src, &archive, cancelled);
+    assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+    assert!(!archive.exists());
+
+    // a partially extracted file is removed
+    compress_to_path(&src, &archive)?;
+    struct CancelOnProgress(CancellationToken);
+    impl ProgressObserver for CancelOnProgress {
+        fn on_progress(&mut self, _progress: &Progress) {
+            self.0.cancel();
+        }
+    }
+    let token = CancellationToken::new();
+    let out = temp_dir.path().join("partial");
+    let options = ExtractOptions {
+        cancellation: Some(token.clone()),
+        ..Default::default()
+    };
+    let result = decompress_file_with_progress(&archive, &out, options, CancelOnProgress(token));
+    assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+    assert!(out.exists());
+    assert!(!out.join("big.bin").exists());
+    Ok(())
+}
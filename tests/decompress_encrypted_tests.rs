@@ -1,3 +1,4 @@
+#![cfg(feature = "aes256")]
 use sevenz_rust::*;
 use std::{fs::read_to_string, io::Read, path::PathBuf};
 use tempfile::tempdir;
@@ -49,3 +50,17 @@ fn test_decompress_to_memory() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_archive_integrity_with_wrong_password() -> anyhow::Result<()> {
+    let mut reader = SevenZReader::open("tests/resources/encrypted.7z", "wrong".into())?;
+    let report = reader.test()?;
+    assert!(!report.is_ok());
+    for (_, status) in report.failures() {
+        assert_eq!(status, &EntryStatus::WrongPassword);
+    }
+
+    let mut reader = SevenZReader::open("tests/resources/encrypted.7z", "sevenz-rust".into())?;
+    assert!(reader.test()?.is_ok());
+    Ok(())
+}
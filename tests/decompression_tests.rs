@@ -1,11 +1,12 @@
 use std::{
     fs::{read, read_to_string, File},
+    io::{Cursor, Read, Seek, SeekFrom},
     path::PathBuf,
 };
 
 use tempfile::tempdir;
 
-use sevenz_rust::{decompress_file, Archive, BlockDecoder};
+use sevenz_rust::{decompress_file, Archive, BlockDecoder, Password, ReadAhead, SevenZReader};
 
 #[test]
 fn decompress_single_empty_file_unencoded_header() -> anyhow::Result<()> {
@@ -199,3 +200,1459 @@ fn test_entry_compressed_size() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_pipelined_decoding_matches_sequential() -> anyhow::Result<()> {
+    let mut file = File::open("tests/resources/7za433_7zip_lzma2_bcj2.7z")?;
+    let file_len = file.metadata()?.len();
+    let archive = Archive::read(&mut file, file_len, &[])?;
+    for i in 0..archive.folders.len() {
+        let mut expected = Vec::new();
+        BlockDecoder::new(i, &archive, &[], &mut file).for_each_entries(&mut |entry, reader| {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            expected.push((entry.name().to_string(), content));
+            Ok(true)
+        })?;
+
+        let mut actual = Vec::new();
+        BlockDecoder::new(i, &archive, &[], &mut file).for_each_entries_pipelined(
+            ReadAhead::new(2, 4096),
+            &mut |entry, reader| {
+                let mut content = Vec::new();
+                reader.read_to_end(&mut content)?;
+                actual.push((entry.name().to_string(), content));
+                Ok(true)
+            },
+        )?;
+        assert_eq!(expected, actual);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pipelined_decoding_reports_crc_failure() -> anyhow::Result<()> {
+    let mut data = read("tests/resources/copy.7z")?;
+    data[40] ^= 0xff;
+    let len = data.len() as u64;
+    let mut reader = SevenZReader::new(Cursor::new(data), len, Password::empty())?;
+    let result = reader.for_each_entries_pipelined(ReadAhead::default(), |_, reader| {
+        std::io::copy(reader, &mut std::io::sink())?;
+        Ok(true)
+    });
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+fn lzma2_compress(data: &[u8]) -> Vec<u8> {
+    use sevenz_rust::lzma::{CountingWriter, LZMA2Options, LZMA2Writer};
+    use std::io::Write;
+
+    let mut out = Vec::new();
+    let mut writer = LZMA2Writer::new(CountingWriter::new(&mut out), &LZMA2Options::default());
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap();
+    drop(writer);
+    out
+}
+
+/// Blocks compressed independently, and their concatenation, which has a dictionary reset per
+/// block.
+#[cfg(feature = "compress")]
+fn lzma2_reset_stream() -> (Vec<Vec<u8>>, Vec<u8>) {
+    let blocks: Vec<Vec<u8>> = (0..5u8)
+        .map(|i| {
+            (0..200_000u32)
+                .map(|n| (n % 251) as u8 ^ i)
+                .collect::<Vec<u8>>()
+        })
+        .collect();
+    let mut stream = Vec::new();
+    for block in blocks.iter() {
+        let compressed = lzma2_compress(block);
+        stream.extend_from_slice(&compressed[..compressed.len() - 1]);
+    }
+    stream.push(0x00);
+    (blocks, stream)
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_lzma2_parallel_decoding_of_reset_segments() -> anyhow::Result<()> {
+    use sevenz_rust::{lzma::LZMA2Options, LZMA2ParallelReader};
+
+    let (blocks, stream) = lzma2_reset_stream();
+
+    let mut reader =
+        LZMA2ParallelReader::new(stream.as_slice(), LZMA2Options::DICT_SIZE_DEFAULT, 3)?;
+    assert!(reader.is_parallel());
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, blocks.concat());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_lzma2_parallel_falls_back_without_reset_points() -> anyhow::Result<()> {
+    use sevenz_rust::{lzma::LZMA2Options, LZMA2ParallelReader};
+
+    let data = b"a stream with a single dictionary reset".repeat(1000);
+    let stream = lzma2_compress(&data);
+    let mut reader =
+        LZMA2ParallelReader::new(stream.as_slice(), LZMA2Options::DICT_SIZE_DEFAULT, 4)?;
+    assert!(!reader.is_parallel());
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, data);
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_lzma2_parallel_decoding_within_memory_limit() -> anyhow::Result<()> {
+    use sevenz_rust::{lzma::LZMA2Options, LZMA2ParallelReader};
+
+    let (blocks, stream) = lzma2_reset_stream();
+    // no segment fits next to the dictionaries, all are decoded on the calling thread
+    let mut reader = LZMA2ParallelReader::with_memory_limit(
+        stream.as_slice(),
+        LZMA2Options::DICT_SIZE_DEFAULT,
+        3,
+        0,
+    )?;
+    assert!(reader.is_parallel());
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, blocks.concat());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_lzma2_parallel_decoding_without_end_marker() -> anyhow::Result<()> {
+    use sevenz_rust::{lzma::LZMA2Options, LZMA2ParallelReader};
+
+    let (blocks, mut stream) = lzma2_reset_stream();
+    stream.pop();
+    let mut reader =
+        LZMA2ParallelReader::new(stream.as_slice(), LZMA2Options::DICT_SIZE_DEFAULT, 3)?;
+    assert!(reader.is_parallel());
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, blocks.concat());
+
+    drop(reader);
+
+    // a chunk cut off in the middle still fails, after the segments before it
+    stream.truncate(stream.len() - 10);
+    let mut reader =
+        LZMA2ParallelReader::new(stream.as_slice(), LZMA2Options::DICT_SIZE_DEFAULT, 3)?;
+    let mut decoded = vec![0; blocks[0].len()];
+    reader.read_exact(&mut decoded)?;
+    assert_eq!(decoded, blocks[0]);
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_lzma2_threads_decoding() -> anyhow::Result<()> {
+    let mut source_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_file.push("tests/resources/7za433_7zip_lzma2_bcj2.7z");
+    let mut expected = Vec::new();
+    SevenZReader::open(&source_file, Password::empty())?.for_each_entries(|entry, reader| {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        expected.push((entry.name().to_string(), content));
+        Ok(true)
+    })?;
+
+    let mut actual = Vec::new();
+    let mut reader = SevenZReader::open(&source_file, Password::empty())?;
+    reader.set_lzma2_threads(4);
+    reader.for_each_entries(|entry, reader| {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        actual.push((entry.name().to_string(), content));
+        Ok(true)
+    })?;
+    assert_eq!(expected, actual);
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_open_entry_random_access() -> anyhow::Result<()> {
+    use sevenz_rust::SevenZWriter;
+
+    let temp_dir = tempdir()?;
+    let folder = temp_dir.path().join("folder");
+    std::fs::create_dir(&folder)?;
+    for i in 0..20 {
+        std::fs::write(
+            folder.join(format!("file{i}.txt")),
+            format!("content of file {i}").repeat(i + 1),
+        )?;
+    }
+    let dest = temp_dir.path().join("folder.7z");
+    let mut sz = SevenZWriter::create(&dest)?;
+    sz.push_source_path(&folder, |_| true)?;
+    sz.finish()?;
+
+    for cache_size in [0, 1024 * 1024] {
+        let mut reader = SevenZReader::open(&dest, Password::empty())?;
+        reader.set_block_cache_size(cache_size);
+        let names: Vec<String> = reader
+            .archive()
+            .files
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect();
+        for index in (0..names.len()).rev().chain(0..names.len()) {
+            let mut content = String::new();
+            reader.open_entry(index)?.read_to_string(&mut content)?;
+            assert_eq!(content, read_to_string(folder.join(&names[index]))?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_entry_seekable_stored() -> anyhow::Result<()> {
+    let mut reader = SevenZReader::open("tests/resources/copy.7z", Password::empty())?;
+    let mut entry = reader.open_entry_seekable(0)?;
+
+    let mut content = String::new();
+    entry.seek(SeekFrom::Start(7))?;
+    entry.read_to_string(&mut content)?;
+    assert_eq!(content, "copy encoding");
+
+    content.clear();
+    assert_eq!(entry.seek(SeekFrom::End(-8))?, 12);
+    entry.read_to_string(&mut content)?;
+    assert_eq!(content, "encoding");
+
+    content.clear();
+    entry.seek(SeekFrom::Current(-20))?;
+    entry.read_to_string(&mut content)?;
+    assert_eq!(content, "simple copy encoding");
+
+    assert!(entry.seek(SeekFrom::Current(-21)).is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_open_entry_seekable_compressed() -> anyhow::Result<()> {
+    use sevenz_rust::SevenZWriter;
+
+    let temp_dir = tempdir()?;
+    let folder = temp_dir.path().join("folder");
+    std::fs::create_dir(&folder)?;
+    for i in 0..4 {
+        let content: Vec<u8> = (0..100_000u32).map(|n| (n % 251) as u8 ^ i).collect();
+        std::fs::write(folder.join(format!("file{i}.bin")), content)?;
+    }
+    let dest = temp_dir.path().join("folder.7z");
+    let mut sz = SevenZWriter::create(&dest)?;
+    sz.push_source_path_non_solid(&folder, |_| true)?;
+    sz.finish()?;
+
+    for cache_size in [0, 1024 * 1024] {
+        let mut reader = SevenZReader::open(&dest, Password::empty())?;
+        reader.set_block_cache_size(cache_size);
+        for index in 0..reader.archive().files.len() {
+            let expected = read(folder.join(reader.archive().files[index].name()))?;
+            let mut entry = reader.open_entry_seekable(index)?;
+            for start in [90_000u64, 10, 50_000, 0] {
+                let mut buf = vec![0; 1000];
+                assert_eq!(entry.seek(SeekFrom::Start(start))?, start);
+                entry.read_exact(&mut buf)?;
+                assert_eq!(buf, expected[start as usize..start as usize + 1000]);
+            }
+            let mut tail = Vec::new();
+            entry.seek(SeekFrom::End(-100))?;
+            entry.read_to_end(&mut tail)?;
+            assert_eq!(tail, expected[expected.len() - 100..]);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_entry_of_short_block() -> anyhow::Result<()> {
+    // a copied block of two 100 byte entries, whose packed stream has only 150 bytes
+    let packed = [[b'a'; 100].as_slice(), &[b'b'; 50]].concat();
+    #[rustfmt::skip]
+    let header = [
+        0x01, 0x04,
+        0x06, 0x00, 0x01, 0x09, 0x80, 150, 0x00,
+        0x07, 0x0b, 0x01, 0x00, 0x01, 0x01, 0x00, 0x0c, 0x80, 200, 0x00,
+        0x08, 0x0d, 0x02, 0x09, 100, 0x00,
+        0x00,
+        0x05, 0x02, 0x11, 9, 0x00, b'a', 0, 0, 0, b'b', 0, 0, 0, 0x00,
+        0x00,
+    ];
+    let crc32 = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut start_header = Vec::new();
+    start_header.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+    start_header.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    start_header.extend_from_slice(&crc32.checksum(&header).to_le_bytes());
+    let mut data = vec![b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, 0, 4];
+    data.extend_from_slice(&crc32.checksum(&start_header).to_le_bytes());
+    data.extend_from_slice(&start_header);
+    data.extend_from_slice(&packed);
+    data.extend_from_slice(&header);
+
+    let len = data.len() as u64;
+    let mut reader = SevenZReader::new(Cursor::new(data), len, Password::empty())?;
+    assert_eq!(reader.archive().files[1].size(), 100);
+    reader.set_block_cache_size(1024 * 1024);
+    assert!(reader.open_entry(1).is_err());
+    assert!(reader.open_entry_seekable(1).is_err());
+    assert!(reader.open_entry(0).is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+fn archive_with_entry(dir: &std::path::Path, name: &str) -> anyhow::Result<PathBuf> {
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    let dest = dir.join("unsafe.7z");
+    let mut sz = SevenZWriter::create(&dest)?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = name.to_string();
+    sz.push_archive_entry(entry, Some(Cursor::new("evil")))?;
+    sz.finish()?;
+    Ok(dest)
+}
+
+#[cfg(feature = "compress")]
+fn archive_with_files(dest: &std::path::Path, files: &[(&str, &str)]) -> anyhow::Result<()> {
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    let mut sz = SevenZWriter::create(dest)?;
+    for (name, content) in files {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry.has_last_modified_date = true;
+        entry.last_modified_date = std::time::SystemTime::now().try_into()?;
+        sz.push_archive_entry(entry, Some(Cursor::new(content.to_string())))?;
+    }
+    sz.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_overwrite_policies() -> anyhow::Result<()> {
+    use sevenz_rust::{
+        decompress_file_with_extract_fn, decompress_file_with_options, default_entry_extract_fn,
+        ExtractOptions, OverwritePolicy,
+    };
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("a.7z");
+    archive_with_files(&archive, &[("a.txt", "new")])?;
+    let extract = |overwrite| -> anyhow::Result<PathBuf> {
+        let dest = temp_dir.path().join(format!("{overwrite:?}"));
+        std::fs::create_dir_all(&dest)?;
+        std::fs::write(dest.join("a.txt"), "old")?;
+        let options = ExtractOptions {
+            overwrite,
+            ..Default::default()
+        };
+        decompress_file_with_options(&archive, &dest, options)?;
+        Ok(dest)
+    };
+
+    let dest = extract(OverwritePolicy::Skip)?;
+    assert_eq!(read_to_string(dest.join("a.txt"))?, "old");
+
+    let dest = extract(OverwritePolicy::Rename)?;
+    assert_eq!(read_to_string(dest.join("a.txt"))?, "old");
+    assert_eq!(read_to_string(dest.join("a_1.txt"))?, "new");
+
+    // the existing file was written just now, after the entry
+    let dest = extract(OverwritePolicy::IfNewer)?;
+    assert_eq!(read_to_string(dest.join("a.txt"))?, "old");
+
+    let dest = temp_dir.path().join("readonly");
+    std::fs::create_dir_all(&dest)?;
+    let existing = dest.join("a.txt");
+    std::fs::write(&existing, "old")?;
+    let mut permissions = std::fs::metadata(&existing)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&existing, permissions)?;
+    decompress_file_with_options(&archive, &dest, ExtractOptions::default())?;
+    assert_eq!(read_to_string(&existing)?, "new");
+
+    // an extract function skipping the entry leaves the file read-only
+    let mut permissions = std::fs::metadata(&existing)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&existing, permissions)?;
+    decompress_file_with_extract_fn(&archive, &dest, |_, _, _| Ok(true))?;
+    assert!(std::fs::metadata(&existing)?.permissions().readonly());
+    decompress_file_with_extract_fn(&archive, &dest, default_entry_extract_fn)?;
+    assert!(!std::fs::metadata(&existing)?.permissions().readonly());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_flat_path_mode() -> anyhow::Result<()> {
+    use sevenz_rust::{decompress_file_with_options, ExtractOptions, PathMode};
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("a.7z");
+    archive_with_files(&archive, &[("dir/a.txt", "a"), ("dir/sub/b.txt", "b")])?;
+    let dest = temp_dir.path().join("dest");
+    let options = ExtractOptions {
+        path_mode: PathMode::Flat,
+        ..Default::default()
+    };
+    decompress_file_with_options(&archive, &dest, options)?;
+    assert_eq!(read_to_string(dest.join("a.txt"))?, "a");
+    assert_eq!(read_to_string(dest.join("b.txt"))?, "b");
+    assert!(!dest.join("dir").exists());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_restores_metadata() -> anyhow::Result<()> {
+    use sevenz_rust::{
+        decompress_file_with_options, ExtractOptions, MetadataErrors, SevenZArchiveEntry,
+        SevenZWriter,
+    };
+    use std::time::{Duration, SystemTime};
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("a.7z");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    let entry = |name: &str, is_directory: bool| -> anyhow::Result<SevenZArchiveEntry> {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry.is_directory = is_directory;
+        entry.has_last_modified_date = true;
+        entry.last_modified_date = modified.try_into()?;
+        Ok(entry)
+    };
+    let mut sz = SevenZWriter::create(&archive)?;
+    sz.push_archive_entry::<&[u8]>(entry("dir", true)?, None)?;
+    let mut read_only = entry("dir/read_only.txt", false)?;
+    read_only.has_windows_attributes = true;
+    read_only.windows_attributes = 0x1;
+    sz.push_archive_entry(read_only, Some(&b"content"[..]))?;
+    sz.push_archive_entry(entry("dir/empty.txt", false)?, Some(&b""[..]))?;
+    sz.finish()?;
+
+    let dest = temp_dir.path().join("dest");
+    let metadata_errors = MetadataErrors::new();
+    let options = ExtractOptions {
+        metadata_errors: Some(metadata_errors.clone()),
+        ..Default::default()
+    };
+    decompress_file_with_options(&archive, &dest, options)?;
+    assert!(metadata_errors.is_empty(), "{:?}", metadata_errors.take());
+    for path in ["dir", "dir/read_only.txt", "dir/empty.txt"] {
+        let metadata = std::fs::metadata(dest.join(path))?;
+        assert_eq!(metadata.modified()?, modified, "{path}");
+    }
+    let metadata = std::fs::metadata(dest.join("dir/read_only.txt"))?;
+    assert!(metadata.permissions().readonly());
+    assert!(!std::fs::metadata(dest.join("dir/empty.txt"))?
+        .permissions()
+        .readonly());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_rejects_path_traversal() -> anyhow::Result<()> {
+    for name in [
+        "../evil.txt",
+        "a/../../evil.txt",
+        "/evil.txt",
+        "C:evil.txt",
+        "..\\evil.txt",
+    ] {
+        let temp_dir = tempdir()?;
+        let archive = archive_with_entry(temp_dir.path(), name)?;
+        let dest = temp_dir.path().join("dest");
+        let result = decompress_file(archive, &dest);
+        assert!(
+            matches!(result, Err(sevenz_rust::Error::UnsafeEntryPath { .. })),
+            "{name}: {result:?}"
+        );
+        assert!(!temp_dir.path().join("evil.txt").exists());
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn test_decompress_does_not_follow_symlinks() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let outside = temp_dir.path().join("outside");
+    std::fs::create_dir(&outside)?;
+    std::fs::write(outside.join("target.txt"), "untouched")?;
+    let dest = temp_dir.path().join("dest");
+    std::fs::create_dir(&dest)?;
+    std::os::unix::fs::symlink(&outside, dest.join("link"))?;
+    std::os::unix::fs::symlink(outside.join("target.txt"), dest.join("file.txt"))?;
+
+    let archive = archive_with_entry(temp_dir.path(), "link/target.txt")?;
+    let result = decompress_file(archive, &dest);
+    assert!(matches!(
+        result,
+        Err(sevenz_rust::Error::UnsafeEntryPath { .. })
+    ));
+
+    let archive = archive_with_entry(temp_dir.path(), "file.txt")?;
+    decompress_file(archive, &dest)?;
+    assert!(!dest.join("file.txt").symlink_metadata()?.is_symlink());
+    assert_eq!(read_to_string(dest.join("file.txt"))?, "evil");
+    assert_eq!(read_to_string(outside.join("target.txt"))?, "untouched");
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn test_decompress_replaces_links_in_destination() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let outside = temp_dir.path().join("outside.txt");
+    std::fs::write(&outside, "untouched")?;
+    let dest = temp_dir.path().join("dest");
+    std::fs::create_dir_all(dest.join("sub"))?;
+    std::fs::hard_link(&outside, dest.join("sub/file.txt"))?;
+    std::os::unix::fs::symlink(temp_dir.path(), dest.join("sub/link"))?;
+
+    let archive = archive_with_entry(temp_dir.path(), "sub/file.txt")?;
+    decompress_file(archive, &dest)?;
+    assert_eq!(read_to_string(dest.join("sub/file.txt"))?, "evil");
+    assert_eq!(read_to_string(&outside)?, "untouched");
+
+    let archive = archive_with_entry(temp_dir.path(), "sub/link/outside.txt")?;
+    let result = decompress_file(archive, &dest);
+    assert!(matches!(
+        result,
+        Err(sevenz_rust::Error::UnsafeEntryPath { .. })
+    ));
+    assert_eq!(read_to_string(&outside)?, "untouched");
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn test_unix_modes_and_symlinks_round_trip() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir()?;
+    let source = temp_dir.path().join("source");
+    std::fs::create_dir(&source)?;
+    std::fs::write(source.join("file.txt"), "content")?;
+    std::fs::set_permissions(
+        source.join("file.txt"),
+        std::fs::Permissions::from_mode(0o640),
+    )?;
+    std::fs::write(source.join("run.sh"), "#!/bin/sh")?;
+    std::fs::set_permissions(
+        source.join("run.sh"),
+        std::fs::Permissions::from_mode(0o751),
+    )?;
+    std::os::unix::fs::symlink("file.txt", source.join("link"))?;
+    let archive = temp_dir.path().join("modes.7z");
+    sevenz_rust::compress_to_path(&source, &archive)?;
+
+    let reader = SevenZReader::open(&archive, Password::empty())?;
+    let link = reader
+        .archive()
+        .files
+        .iter()
+        .find(|entry| entry.name() == "link")
+        .expect("link entry");
+    assert!(link.is_symlink());
+    assert_eq!(link.unix_mode().map(|mode| mode & 0o170000), Some(0o120000));
+
+    let dest = temp_dir.path().join("dest");
+    decompress_file(&archive, &dest)?;
+    assert_eq!(
+        std::fs::read_link(dest.join("link"))?,
+        PathBuf::from("file.txt")
+    );
+    assert_eq!(read_to_string(dest.join("link"))?, "content");
+    let mode = |name: &str| -> anyhow::Result<u32> {
+        Ok(std::fs::metadata(dest.join(name))?.permissions().mode() & 0o7777)
+    };
+    assert_eq!(mode("file.txt")?, 0o640);
+    assert_eq!(mode("run.sh")?, 0o751);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn test_decompress_symlink_policies() -> anyhow::Result<()> {
+    use sevenz_rust::{
+        decompress_file_with_options, ExtractOptions, SevenZArchiveEntry, SevenZWriter,
+        SymlinkPolicy,
+    };
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("links.7z");
+    let mut sz = SevenZWriter::create(&archive)?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "escape".to_string();
+    entry.set_unix_mode(0o120777);
+    sz.push_archive_entry(entry, Some(Cursor::new("../outside")))?;
+    sz.finish()?;
+
+    let extract = |symlinks| {
+        let dest = temp_dir.path().join(format!("{symlinks:?}"));
+        let options = ExtractOptions {
+            symlinks,
+            ..Default::default()
+        };
+        decompress_file_with_options(&archive, &dest, options).map(|_| dest.join("escape"))
+    };
+    assert!(matches!(
+        extract(SymlinkPolicy::Contained),
+        Err(sevenz_rust::Error::UnsafeEntryPath { .. })
+    ));
+    assert!(extract(SymlinkPolicy::Skip)?.symlink_metadata().is_err());
+    let link = extract(SymlinkPolicy::Any)?;
+    assert_eq!(std::fs::read_link(link)?, PathBuf::from("../outside"));
+    let file = extract(SymlinkPolicy::AsFile)?;
+    assert_eq!(read_to_string(file)?, "../outside");
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn test_decompress_rejects_chained_symlink_escape() -> anyhow::Result<()> {
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("chained.7z");
+    let mut sz = SevenZWriter::create(&archive)?;
+    // each target stays inside lexically, but `a` resolves through `d/s` to the parent of
+    // the destination
+    for (name, target) in [("d/s", ".."), ("a", "d/s/..")] {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.to_string();
+        entry.set_unix_mode(0o120777);
+        sz.push_archive_entry(entry, Some(Cursor::new(target)))?;
+    }
+    sz.finish()?;
+
+    let dest = temp_dir.path().join("dest");
+    let result = decompress_file(&archive, &dest);
+    assert!(
+        matches!(&result, Err(sevenz_rust::Error::UnsafeEntryPath { name, .. }) if name == "a"),
+        "{result:?}"
+    );
+    assert!(dest.join("a").symlink_metadata().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_decompress_with_limits() -> anyhow::Result<()> {
+    use sevenz_rust::{decompress_file_with_limits, Error, ExtractLimit, ExtractLimits};
+
+    let source = "tests/resources/two_files_with_content_lzma.7z";
+    let temp_dir = tempdir()?;
+    let exceeded = |limits: ExtractLimits| match decompress_file_with_limits(
+        source,
+        temp_dir.path().join("exceeded"),
+        limits,
+    ) {
+        Err(Error::ExtractLimitExceeded { limit, .. }) => Some(limit),
+        _ => None,
+    };
+    assert_eq!(
+        exceeded(ExtractLimits {
+            max_entries: Some(1),
+            ..Default::default()
+        }),
+        Some(ExtractLimit::Entries)
+    );
+    assert_eq!(
+        exceeded(ExtractLimits {
+            max_entry_size: Some(1),
+            ..Default::default()
+        }),
+        Some(ExtractLimit::EntrySize)
+    );
+    assert_eq!(
+        exceeded(ExtractLimits {
+            max_total_size: Some(1),
+            ..Default::default()
+        }),
+        Some(ExtractLimit::TotalSize)
+    );
+    // nothing is extracted once a limit is exceeded
+    assert!(!temp_dir.path().join("exceeded").join("file1.txt").exists());
+
+    let dest = temp_dir.path().join("within");
+    decompress_file_with_limits(
+        source,
+        &dest,
+        ExtractLimits {
+            max_entries: Some(2),
+            max_entry_size: Some(1024),
+            max_total_size: Some(1024),
+            max_ratio: Some(100),
+        },
+    )?;
+    assert_eq!(
+        read_to_string(dest.join("file1.txt"))?,
+        "file one content\n"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_extract_limits_compression_ratio() -> anyhow::Result<()> {
+    use sevenz_rust::{Error, ExtractLimit, ExtractLimits, SevenZArchiveEntry, SevenZWriter};
+
+    let temp_dir = tempdir()?;
+    let dest = temp_dir.path().join("zeros.7z");
+    let mut sz = SevenZWriter::create(&dest)?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "zeros".to_string();
+    sz.push_archive_entry(entry, Some(Cursor::new(vec![0u8; 4 * 1024 * 1024])))?;
+    sz.finish()?;
+
+    let mut reader = SevenZReader::open(&dest, Password::empty())?;
+    reader.set_extract_limits(ExtractLimits {
+        max_ratio: Some(100),
+        ..Default::default()
+    });
+    assert!(matches!(
+        reader.open_entry(0).map(|_| ()),
+        Err(Error::ExtractLimitExceeded {
+            limit: ExtractLimit::Ratio,
+            ..
+        })
+    ));
+    let result = reader.for_each_entries(|_, _| unreachable!("limits are checked first"));
+    assert!(matches!(
+        result,
+        Err(Error::ExtractLimitExceeded {
+            limit: ExtractLimit::Ratio,
+            ..
+        })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_read_with_limits() -> anyhow::Result<()> {
+    use sevenz_rust::{Error, ReadLimit, ReadLimits};
+
+    let data = read("tests/resources/two_files_with_content_lzma.7z")?;
+    let read_with = |limits: ReadLimits| {
+        Archive::read_with_limits(&mut Cursor::new(&data), data.len() as u64, &[], limits)
+    };
+    assert_eq!(read_with(ReadLimits::default())?.files.len(), 2);
+    for (limits, expected) in [
+        (
+            ReadLimits {
+                max_files: 1,
+                ..Default::default()
+            },
+            ReadLimit::Files,
+        ),
+        (
+            ReadLimits {
+                max_header_size: 16,
+                ..Default::default()
+            },
+            ReadLimit::HeaderSize,
+        ),
+        (
+            ReadLimits {
+                max_name_bytes: 8,
+                ..Default::default()
+            },
+            ReadLimit::NameBytes,
+        ),
+    ] {
+        match read_with(limits) {
+            Err(Error::ReadLimitExceeded { limit, .. }) => assert_eq!(limit, expected),
+            other => panic!("expected {expected:?} to be exceeded, got {other:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_read_rejects_huge_declared_counts() {
+    // kHeader, kFilesInfo and 2^24 files: within the default limit, but far more than
+    // a 7-byte header can describe
+    let header = [0x01, 0x05, 0xF0, 0x00, 0x00, 0x00, 0x01];
+    let data = archive_with_header(&[], &header);
+
+    let len = data.len() as u64;
+    let result = Archive::read(&mut Cursor::new(data), len, &[]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_read_rejects_huge_encoded_header() {
+    use sevenz_rust::{Error, HeaderError};
+
+    // kEncodedHeader with 16 packed bytes decoded by LZMA2 to `unpack_size` bytes, using a
+    // dictionary of size `2 << dict_prop / 2 + 11`
+    let encoded_header = |dict_prop: u8, unpack_size: &[u8]| {
+        let mut header = vec![0x17, 0x06, 0x00, 0x01, 0x09, 0x10, 0x00];
+        header.extend_from_slice(&[0x07, 0x0B, 0x01, 0x00, 0x01, 0x21, 0x21, 0x01, dict_prop]);
+        header.push(0x0C);
+        header.extend_from_slice(unpack_size);
+        header.extend_from_slice(&[0x00, 0x00]);
+        archive_with_header(&[0; 16], &header)
+    };
+    let read = |data: Vec<u8>| {
+        let len = data.len() as u64;
+        Archive::read(&mut Cursor::new(data), len, &[])
+    };
+
+    // 1 GiB from 16 bytes, rejected before allocating anything
+    let data = encoded_header(16, &[0xF0, 0x00, 0x00, 0x00, 0x40]);
+    match read(data) {
+        Err(Error::InvalidHeader {
+            kind: HeaderError::ValueOutOfRange { value, .. },
+            ..
+        }) => assert_eq!(value, 1 << 30),
+        other => panic!("expected the header size to be rejected, got {other:?}"),
+    }
+    // a 1 GiB dictionary for a 4 KiB header
+    let data = encoded_header(36, &[0x90, 0x00]);
+    assert!(
+        matches!(read(data), Err(Error::MaxMemLimited { .. })),
+        "expected the dictionary to exceed the header memory limit"
+    );
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// An archive of `packed` streams followed by the next header `header`.
+fn archive_with_header(packed: &[u8], header: &[u8]) -> Vec<u8> {
+    let mut start_header = Vec::new();
+    start_header.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+    start_header.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    start_header.extend_from_slice(&crc32(header).to_le_bytes());
+    let mut data = vec![b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C, 0, 4];
+    data.extend_from_slice(&crc32(&start_header).to_le_bytes());
+    data.extend_from_slice(&start_header);
+    data.extend_from_slice(packed);
+    data.extend_from_slice(header);
+    data
+}
+
+#[test]
+fn test_memory_limit() -> anyhow::Result<()> {
+    use sevenz_rust::Error;
+
+    for source in [
+        "tests/resources/two_files_with_content_lzma.7z",
+        "tests/resources/7za433_7zip_lzma2_bcj2.7z",
+    ] {
+        let mut reader = SevenZReader::open(source, Password::empty())?;
+        let needed = (0..reader.archive().folders.len())
+            .map(|i| reader.archive().estimate_decode_memory(i))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .max()
+            .unwrap_or_default();
+        assert!(needed > 0);
+
+        reader.set_memory_limit(needed as usize - 1);
+        let result = reader.for_each_entries(|_, _| Ok(true));
+        assert!(
+            matches!(result, Err(Error::MaxMemLimited { .. })),
+            "{source}"
+        );
+
+        reader.set_memory_limit(needed as usize);
+        reader.for_each_entries(|_, reader| {
+            std::io::copy(reader, &mut std::io::sink())?;
+            Ok(true)
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_entry_filter() -> anyhow::Result<()> {
+    use sevenz_rust::{
+        decompress_file_with_options, EntryFilter, ExtractLimits, ExtractOptions,
+        SevenZArchiveEntry, SevenZWriter, SourceReader,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    /// Counts the bytes read from the archive.
+    struct CountingReader<R> {
+        inner: R,
+        read: Rc<Cell<u64>>,
+    }
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.set(self.read.get() + n as u64);
+            Ok(n)
+        }
+    }
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("filter.7z");
+    // xorshift, so the content doesn't compress
+    let mut state = 0x2545_f491u32;
+    let noise: Vec<u8> = (0..256 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    let mut sz = SevenZWriter::create(&archive)?;
+    let solid = ["a.txt", "docs/b.md", "docs/c.txt", "d.txt"];
+    let entries = solid
+        .iter()
+        .map(|name| {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            entry
+        })
+        .collect();
+    let readers = solid
+        .iter()
+        .map(|name| SourceReader::from(Cursor::new(name.as_bytes().to_vec())))
+        .collect::<Vec<_>>();
+    sz.push_archive_entries(entries, readers.into())?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "e.log".to_string();
+    sz.push_archive_entry(entry, Some(Cursor::new(noise)))?;
+    sz.finish()?;
+
+    let read = Rc::new(Cell::new(0));
+    let source = CountingReader {
+        inner: File::open(&archive)?,
+        read: read.clone(),
+    };
+    let len = std::fs::metadata(&archive)?.len();
+    let mut reader = SevenZReader::new(source, len, Password::empty())?;
+    reader
+        .set_entry_filter(EntryFilter::new().include("*.txt").exclude("d.txt"))
+        .set_extract_limits(ExtractLimits {
+            max_entry_size: Some(1024),
+            ..Default::default()
+        });
+    let mut names = Vec::new();
+    reader.for_each_entries(|entry, content| {
+        let mut text = String::new();
+        content.read_to_string(&mut text)?;
+        assert_eq!(text, entry.name());
+        names.push(text);
+        Ok(true)
+    })?;
+    assert_eq!(names, ["a.txt", "docs/c.txt"]);
+    // the block of e.log was skipped
+    assert!(read.get() < 64 * 1024, "read {} bytes", read.get());
+
+    let dest = temp_dir.path().join("out");
+    let options = ExtractOptions {
+        filter: EntryFilter::new().include("docs/**"),
+        ..Default::default()
+    };
+    decompress_file_with_options(&archive, &dest, options)?;
+    assert_eq!(
+        std::fs::read_to_string(dest.join("docs/b.md"))?,
+        "docs/b.md"
+    );
+    assert!(dest.join("docs/c.txt").exists());
+    assert!(!dest.join("a.txt").exists());
+    assert!(!dest.join("e.log").exists());
+
+    let options = ExtractOptions {
+        filter: EntryFilter::new().include("[invalid"),
+        ..Default::default()
+    };
+    assert!(
+        decompress_file_with_options(&archive, temp_dir.path().join("invalid"), options).is_err()
+    );
+
+    #[cfg(feature = "regex")]
+    {
+        let dest = temp_dir.path().join("regex");
+        let options = ExtractOptions {
+            filter: EntryFilter::new().include_regex(r"^[a-d]\.txt$"),
+            ..Default::default()
+        };
+        decompress_file_with_options(&archive, &dest, options)?;
+        assert!(dest.join("a.txt").exists());
+        assert!(dest.join("d.txt").exists());
+        assert!(!dest.join("docs").exists());
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_entry_filter_escaped_metacharacters() -> anyhow::Result<()> {
+    use sevenz_rust::EntryFilter;
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("escaped.7z");
+    archive_with_files(
+        &archive,
+        &[
+            ("docs/a*b.txt", "1"),
+            ("docs/axb.txt", "2"),
+            ("docs/[1].txt", "3"),
+            ("docs/1.txt", "4"),
+        ],
+    )?;
+    let selected = |filter: EntryFilter| -> anyhow::Result<Vec<String>> {
+        let mut reader = SevenZReader::open(&archive, Password::empty())?;
+        reader.set_entry_filter(filter);
+        let mut names = Vec::new();
+        reader.for_each_entries(|entry, _| {
+            names.push(entry.name().to_string());
+            Ok(true)
+        })?;
+        Ok(names)
+    };
+
+    assert_eq!(
+        selected(EntryFilter::new().include(r"docs/a\*b.txt"))?,
+        ["docs/a*b.txt"]
+    );
+    assert_eq!(
+        selected(EntryFilter::new().include("docs/a*b.txt"))?,
+        ["docs/a*b.txt", "docs/axb.txt"]
+    );
+    // without an unescaped `/` the name is matched
+    assert_eq!(
+        selected(EntryFilter::new().include(r"\[1\].txt"))?,
+        ["docs/[1].txt"]
+    );
+    assert_eq!(
+        selected(EntryFilter::new().include("[1].txt"))?,
+        ["docs/1.txt"]
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_entry_names_with_unpaired_surrogates() -> anyhow::Result<()> {
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("names.7z");
+    // "a\u{d800}b.txt", a valid Windows filename
+    let raw_name: Vec<u16> = vec![0x61, 0xd800, 0x62, 0x2e, 0x74, 0x78, 0x74];
+    let mut sz = SevenZWriter::create(&archive)?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.set_raw_name(raw_name.clone());
+    sz.push_archive_entry(entry, Some(Cursor::new("content")))?;
+    sz.finish()?;
+
+    let reader = SevenZReader::open(&archive, Password::empty())?;
+    let entry = &reader.archive().files[0];
+    assert_eq!(entry.name(), "a\u{fffd}b.txt");
+    assert!(entry.has_lossy_name());
+    assert_eq!(entry.raw_name().as_ref(), raw_name.as_slice());
+    #[cfg(not(windows))]
+    assert_eq!(entry.os_name(), "a\u{fffd}b.txt");
+
+    // a renamed entry is written with its new name
+    let mut renamed = entry.clone();
+    renamed.name = "renamed.txt".to_string();
+    assert!(!renamed.has_lossy_name());
+    assert_eq!(renamed.raw_name().len(), "renamed.txt".len());
+
+    let dest = temp_dir.path().join("out");
+    decompress_file(&archive, &dest)?;
+    #[cfg(not(windows))]
+    assert_eq!(read_to_string(dest.join("a\u{fffd}b.txt"))?, "content");
+    Ok(())
+}
+
+#[test]
+fn test_structured_errors() -> anyhow::Result<()> {
+    use sevenz_rust::{ChecksumTarget, Error};
+
+    let data = read("tests/resources/copy.7z")?;
+    let len = data.len() as u64;
+
+    let mut bad_signature = data.clone();
+    bad_signature[0] = b'X';
+    let result = Archive::read(&mut Cursor::new(bad_signature), len, &[]);
+    assert!(matches!(result, Err(Error::BadSignature(..))));
+
+    let mut bad_start_header = data.clone();
+    bad_start_header[12] ^= 0xff;
+    let result = Archive::read(&mut Cursor::new(bad_start_header), len, &[]);
+    assert!(matches!(
+        result,
+        Err(Error::ChecksumMismatch {
+            target: ChecksumTarget::StartHeader,
+            ..
+        })
+    ));
+
+    let mut corrupted = data.clone();
+    corrupted[40] ^= 0xff;
+    let mut reader = SevenZReader::new(Cursor::new(corrupted), len, Password::empty())?;
+    let err = reader
+        .for_each_entries(|_, reader| {
+            std::io::copy(reader, &mut std::io::sink())?;
+            Ok(true)
+        })
+        .unwrap_err();
+    assert!(matches!(err, Error::ChecksumMismatch { expected, actual, .. } if expected != actual));
+    assert!(err.entry().is_some());
+    assert_eq!(err.folder(), Some(0));
+    assert!(err.to_string().contains("in entry"), "{err}");
+
+    let mut reader = SevenZReader::new(Cursor::new(data), len, Password::empty())?;
+    let err = reader
+        .for_each_entries(|_, _| {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full").into())
+        })
+        .unwrap_err();
+    assert!(matches!(err, Error::Io { .. }));
+    assert_eq!(
+        std::error::Error::source(&err).map(|e| e.to_string()),
+        Some("disk full".to_string())
+    );
+    assert_eq!(err.folder(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_integrity() -> anyhow::Result<()> {
+    use sevenz_rust::{ChecksumTarget, EntryStatus};
+
+    let data = read("tests/resources/copy.7z")?;
+    let len = data.len() as u64;
+
+    let mut reader = SevenZReader::new(Cursor::new(data.clone()), len, Password::empty())?;
+    let report = reader.test()?;
+    assert_eq!(report.entries.len(), reader.archive().files.len());
+    assert!(report.is_ok(), "{report:?}");
+
+    let mut corrupted = data;
+    corrupted[40] ^= 0xff;
+    let mut reader = SevenZReader::new(Cursor::new(corrupted), len, Password::empty())?;
+    let report = reader.test()?;
+    let failures: Vec<_> = report.failures().collect();
+    assert!(!failures.is_empty());
+    for (_, status) in failures {
+        assert!(
+            matches!(status, EntryStatus::ChecksumMismatch { target: ChecksumTarget::Entry | ChecksumTarget::PackStream, expected, actual } if expected != actual),
+            "{status:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_archive_integrity_with_interrupted_reads() -> anyhow::Result<()> {
+    // fails every other read with `Interrupted`, which readers are expected to retry
+    struct Interrupting<R>(R, bool);
+    impl<R: std::io::Read> std::io::Read for Interrupting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            self.0.read(buf)
+        }
+    }
+    impl<R: std::io::Seek> std::io::Seek for Interrupting<R> {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("a.7z");
+    archive_with_files(&archive, &[("a.txt", "a"), ("b.txt", "b")])?;
+    let data = read(&archive)?;
+    let len = data.len() as u64;
+    let source = Interrupting(Cursor::new(data), false);
+    let mut reader = SevenZReader::new(source, len, Password::empty())?;
+    let report = reader.test()?;
+    assert!(report.is_ok(), "{report:?}");
+
+    Ok(())
+}
+
+#[test]
+fn test_for_each_entries_recovering() -> anyhow::Result<()> {
+    use sevenz_rust::{ChecksumTarget, EntryStatus};
+
+    let mut data = read("tests/resources/copy.7z")?;
+    let len = data.len() as u64;
+    data[40] ^= 0xff;
+    let mut reader = SevenZReader::new(Cursor::new(data), len, Password::empty())?;
+    let mut names = Vec::new();
+    let report = reader.for_each_entries_recovering(|entry, content| {
+        std::io::copy(content, &mut std::io::sink())?;
+        names.push(entry.name().to_string());
+        Ok(true)
+    })?;
+    let failures: Vec<_> = report.failures().collect();
+    assert_eq!(failures.len(), 1, "{report:?}");
+    let (index, status) = failures[0];
+    assert!(matches!(
+        status,
+        EntryStatus::ChecksumMismatch {
+            target: ChecksumTarget::Entry,
+            ..
+        }
+    ));
+    // the entries after the broken one are still extracted
+    let files = &reader.archive().files;
+    let intact: Vec<_> = (0..files.len())
+        .filter(|&i| i != index)
+        .map(|i| files[i].name().to_string())
+        .collect();
+    assert_eq!(names, intact);
+
+    // errors of the closure still stop
+    let data = read("tests/resources/copy.7z")?;
+    let mut reader = SevenZReader::new(Cursor::new(data), len, Password::empty())?;
+    let result = reader.for_each_entries_recovering(|_, _| {
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full").into())
+    });
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_decompress_recovering() -> anyhow::Result<()> {
+    use sevenz_rust::{decompress_file_recovering, ExtractOptions};
+
+    let temp_dir = tempdir()?;
+    let archive = temp_dir.path().join("damaged.7z");
+    let content = |name: &str| format!("{name} ").repeat(4096);
+    let names = ["a.txt", "b.txt", "c.txt"];
+    let files: Vec<_> = names.iter().map(|name| (*name, content(name))).collect();
+    let files: Vec<_> = files.iter().map(|(n, c)| (*n, c.as_str())).collect();
+    archive_with_files(&archive, &files)?;
+
+    // breaks the block of b.txt
+    let mut data = read(&archive)?;
+    let header = Archive::read(&mut Cursor::new(&data), data.len() as u64, &[])?;
+    assert_eq!(header.folders.len(), 3);
+    let offset = 32 + header.pack_pos + header.pack_sizes[0] + header.pack_sizes[1] / 2;
+    data[offset as usize] ^= 0x55;
+    std::fs::write(&archive, &data)?;
+
+    for keep_broken in [false, true] {
+        let dest = temp_dir.path().join(format!("out_{keep_broken}"));
+        let options = ExtractOptions {
+            keep_broken,
+            ..Default::default()
+        };
+        let report = decompress_file_recovering(&archive, &dest, options)?;
+        let failures: Vec<_> = report.failures().map(|(i, _)| i).collect();
+        assert_eq!(failures, [1], "{report:?}");
+        assert_eq!(read_to_string(dest.join("a.txt"))?, content("a.txt"));
+        assert_eq!(read_to_string(dest.join("c.txt"))?, content("c.txt"));
+        assert_eq!(dest.join("b.txt").exists(), keep_broken);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_salvage_damaged_archives() -> anyhow::Result<()> {
+    use sevenz_rust::{SalvageMethod, SevenZArchiveEntry, SevenZWriter};
+
+    // non-solid, so each entry is a block of its own
+    let names: Vec<String> = (0..40).map(|i| format!("dir/file_{i}.txt")).collect();
+    let content = |name: &str| name.repeat(100);
+    let mut sz = SevenZWriter::new(Cursor::new(Vec::new()))?;
+    for name in &names {
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name.clone();
+        sz.push_archive_entry(entry, Some(Cursor::new(content(name))))?;
+    }
+    let data = sz.finish()?.into_inner();
+    let header_pos = u64::from_le_bytes(data[12..20].try_into()?) as usize + 32;
+
+    let salvage = |data: &[u8]| -> anyhow::Result<_> {
+        let len = data.len() as u64;
+        let (archive, report) = Archive::salvage(&mut Cursor::new(data), len, &[])?;
+        let mut reader =
+            SevenZReader::from_archive(archive, Cursor::new(data.to_vec()), Password::empty());
+        let mut extracted = Vec::new();
+        let failures = reader.for_each_entries_recovering(|entry, content| {
+            let mut text = String::new();
+            content.read_to_string(&mut text)?;
+            extracted.push((entry.name().to_string(), text));
+            Ok(true)
+        })?;
+        assert!(failures.is_ok(), "{failures:?}");
+        Ok((report, extracted))
+    };
+    let intact: Vec<_> = names.iter().map(|n| (n.clone(), content(n))).collect();
+
+    let (report, extracted) = salvage(&data)?;
+    assert!(report.is_intact());
+    assert_eq!(extracted, intact);
+
+    // the start header points nowhere, the header is found by searching
+    let mut damaged = data.clone();
+    damaged[12..20].fill(0xff);
+    let (report, extracted) = salvage(&damaged)?;
+    assert_eq!(report.method, SalvageMethod::Header);
+    assert_eq!(report.header_offset, Some(header_pos as u64));
+    assert_eq!(extracted, intact);
+
+    // the encoded header doesn't match its CRC, the second one in it after the packed stream's
+    assert_eq!(damaged[header_pos], 0x17);
+    let mut damaged = data.clone();
+    let crc_pos = (header_pos..damaged.len() - 1)
+        .filter(|&i| damaged[i..i + 2] == [0x0a, 0x01])
+        .nth(1)
+        .unwrap();
+    damaged[crc_pos + 2] ^= 0xff;
+    let (report, extracted) = salvage(&damaged)?;
+    assert_eq!(report.method, SalvageMethod::Header);
+    assert!(
+        report.problems.iter().any(|p| p.contains("decoded header")),
+        "{report:?}"
+    );
+    assert_eq!(extracted, intact);
+
+    // an interrupted upload, the header and the last block are lost
+    let cut = header_pos - 20;
+    let (report, extracted) = salvage(&data[..cut])?;
+    assert_eq!(report.method, SalvageMethod::Scan);
+    assert_eq!(extracted.len(), names.len());
+    for ((name, text), (_, expected)) in extracted.iter().zip(&intact).take(names.len() - 1) {
+        assert_eq!(text, expected, "{name}");
+    }
+    let (_, last) = extracted.last().unwrap();
+    assert!(intact.last().unwrap().1.starts_with(last.as_str()));
+
+    // the header is lost and the chunk size of the second stream is damaged, the streams after
+    // it are found where none is expected
+    let header = Archive::read(&mut Cursor::new(&data), data.len() as u64, &[])?;
+    let mut damaged = data[..cut].to_vec();
+    damaged[32 + header.pack_sizes[0] as usize + 2] ^= 0xff;
+    let (report, extracted) = salvage(&damaged)?;
+    assert_eq!(report.method, SalvageMethod::Scan);
+    let found: Vec<_> = extracted.iter().map(|(_, text)| text).collect();
+    for (i, (name, expected)) in intact.iter().enumerate().take(names.len() - 1) {
+        assert_eq!(found.contains(&expected), i != 1, "{name}");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn test_salvage_with_limits() -> anyhow::Result<()> {
+    use sevenz_rust::{SalvageLimits, SalvageMethod, SevenZArchiveEntry, SevenZWriter};
+
+    // the second half repeats the first one, 64 KiB back
+    let mut state = 0x2545_f491_u32;
+    let mut content: Vec<u8> = (0..1 << 16)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    content.extend_from_within(..);
+    let mut sz = SevenZWriter::new(Cursor::new(Vec::new()))?;
+    let mut entry = SevenZArchiveEntry::new();
+    entry.name = "data.bin".into();
+    sz.push_archive_entry(entry, Some(Cursor::new(content.clone())))?;
+    let data = sz.finish()?.into_inner();
+    // the header is lost
+    let cut = u64::from_le_bytes(data[12..20].try_into()?) as usize + 32;
+    let data = &data[..cut];
+
+    let (_, report) = Archive::salvage(&mut Cursor::new(data), cut as u64, &[])?;
+    assert_eq!(report.method, SalvageMethod::Scan);
+    assert!(
+        report.problems.iter().all(|p| !p.contains("damaged")),
+        "{report:?}"
+    );
+
+    let limits = SalvageLimits {
+        max_dict_size: 4096,
+        ..Default::default()
+    };
+    let (archive, report) =
+        Archive::salvage_with_limits(&mut Cursor::new(data), cut as u64, &[], limits)?;
+    assert_eq!(report.method, SalvageMethod::Scan);
+    assert!(
+        report.problems.iter().any(|p| p.contains("damaged")),
+        "{report:?}"
+    );
+    let size = archive.files[0].size() as usize;
+    assert!(size < content.len());
+    Ok(())
+}
@@ -91,3 +91,270 @@ fn compress_multi_files_mix_solid_and_non_solid() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn compress_hard_links() -> anyhow::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp_dir = tempdir()?;
+    let folder = temp_dir.path().join("folder");
+    std::fs::create_dir_all(folder.join("sub"))?;
+    std::fs::write(folder.join("a.txt"), "shared content")?;
+    std::fs::write(folder.join("b.txt"), "other content")?;
+    std::fs::hard_link(folder.join("a.txt"), folder.join("sub/link.txt"))?;
+
+    for store_hard_links in [false, true] {
+        let dest = temp_dir.path().join(format!("links_{store_hard_links}.7z"));
+        let mut sz = SevenZWriter::create(&dest)?;
+        sz.set_store_hard_links(store_hard_links);
+        sz.push_source_path(&folder, |_| true)?;
+        sz.finish()?;
+
+        let reader = SevenZReader::open(&dest, Password::empty())?;
+        let names: Vec<&str> = reader.archive().files.iter().map(|f| f.name()).collect();
+        let position = |name| names.iter().position(|n| *n == name).expect(name);
+        // directories stay before their contents
+        assert!(position("sub") < position("sub/link.txt"));
+        let later = position("a.txt").max(position("sub/link.txt"));
+        let link = &reader.archive().files[later];
+        assert_eq!(link.is_hard_link(), store_hard_links);
+        assert_eq!(link.size() == 14, !store_hard_links);
+
+        let out = temp_dir.path().join(format!("out_{store_hard_links}"));
+        decompress_file(&dest, &out)?;
+        let a = std::fs::metadata(out.join("a.txt"))?;
+        let link = std::fs::metadata(out.join("sub/link.txt"))?;
+        assert_eq!(a.ino() == link.ino(), store_hard_links);
+        assert_eq!(
+            std::fs::read_to_string(out.join("sub/link.txt"))?,
+            "shared content"
+        );
+
+        if store_hard_links {
+            let out = temp_dir.path().join("out_copy");
+            let options = ExtractOptions {
+                hard_links: HardLinkPolicy::Copy,
+                ..Default::default()
+            };
+            decompress_file_with_options(&dest, &out, options)?;
+            let a = std::fs::metadata(out.join("a.txt"))?;
+            let link = std::fs::metadata(out.join("sub/link.txt"))?;
+            assert_ne!(a.ino(), link.ino());
+            assert_eq!(
+                std::fs::read_to_string(out.join("sub/link.txt"))?,
+                "shared content"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn push_source_path_with_directories_and_prefix() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let folder = temp_dir.path().join("folder");
+    std::fs::create_dir_all(folder.join("empty"))?;
+    std::fs::create_dir_all(folder.join("sub"))?;
+    std::fs::write(folder.join("sub/a.txt"), "a")?;
+    let dest = temp_dir.path().join("tree.7z");
+
+    let mut sz = SevenZWriter::create(&dest)?;
+    sz.set_path_prefix("root/");
+    sz.set_stored_times(StoredTimes {
+        created: true,
+        ..Default::default()
+    });
+    sz.push_source_path(&folder, |_| true)?;
+    sz.finish()?;
+
+    let reader = SevenZReader::open(&dest, Password::empty())?;
+    let mut names: Vec<&str> = reader.archive().files.iter().map(|f| f.name()).collect();
+    names.sort();
+    assert_eq!(names, ["root", "root/empty", "root/sub", "root/sub/a.txt"]);
+    for entry in reader.archive().files.iter() {
+        assert_eq!(
+            entry.is_directory(),
+            entry.name() != "root/sub/a.txt",
+            "{}",
+            entry.name()
+        );
+        assert!(entry.has_last_modified_date);
+        assert!(!entry.has_access_date);
+    }
+
+    let out = temp_dir.path().join("out");
+    decompress_file(&dest, &out)?;
+    assert!(out.join("root/empty").is_dir());
+    assert_eq!(std::fs::read_to_string(out.join("root/sub/a.txt"))?, "a");
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn source_walker_selection() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("project");
+    for dir in ["target", "sub/deep", "ignored", "docs"] {
+        std::fs::create_dir_all(root.join(dir))?;
+    }
+    for file in [
+        "a.rs",
+        "b.log",
+        ".hidden",
+        "target/x.rs",
+        "sub/c.rs",
+        "sub/deep/d.rs",
+        "ignored/e.rs",
+        "f.tmp",
+        "keep.tmp",
+        "docs/readme.md",
+    ] {
+        std::fs::write(root.join(file), file)?;
+    }
+    std::fs::write(
+        root.join(".gitignore"),
+        "# build output\nignored/\n*.tmp\n!keep.tmp\n",
+    )?;
+    let relative = |walker: &SourceWalker| -> anyhow::Result<Vec<String>> {
+        Ok(walker
+            .walk()?
+            .iter()
+            .map(|p| {
+                p.strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect())
+    };
+
+    let walker = SourceWalker::new(&root)
+        .exclude("target")
+        .exclude("*.log")
+        .ignore_file(".gitignore")
+        .hidden(false)
+        .max_depth(2);
+    assert_eq!(
+        relative(&walker)?,
+        [
+            "",
+            "a.rs",
+            "docs",
+            "docs/readme.md",
+            "keep.tmp",
+            "sub",
+            "sub/c.rs",
+            "sub/deep"
+        ]
+    );
+
+    let walker = SourceWalker::new(&root).include("*.rs").exclude("target/");
+    assert_eq!(
+        relative(&walker)?,
+        [
+            "",
+            "a.rs",
+            "ignored",
+            "ignored/e.rs",
+            "sub",
+            "sub/c.rs",
+            "sub/deep",
+            "sub/deep/d.rs"
+        ]
+    );
+
+    let dest = temp_dir.path().join("walked.7z");
+    compress_to_path_with_walker(&walker, &dest)?;
+    let out = temp_dir.path().join("out");
+    decompress_file(&dest, &out)?;
+    assert_eq!(
+        std::fs::read_to_string(out.join("sub/deep/d.rs"))?,
+        "sub/deep/d.rs"
+    );
+    assert!(!out.join("docs").exists());
+
+    Ok(())
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn source_walker_escaped_metacharacters() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("root");
+    std::fs::create_dir_all(root.join("sub"))?;
+    for name in ["report[1].txt", "report1.txt", "sub/report[1].txt"] {
+        std::fs::write(root.join(name), name)?;
+    }
+    let relative = |walker: &SourceWalker| -> anyhow::Result<Vec<String>> {
+        Ok(walker
+            .walk()?
+            .iter()
+            .map(|p| {
+                p.strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect())
+    };
+
+    let walker = SourceWalker::new(&root).include(r"report\[1\].txt");
+    assert_eq!(
+        relative(&walker)?,
+        ["", "report[1].txt", "sub", "sub/report[1].txt"]
+    );
+    let walker = SourceWalker::new(&root).include(r"sub/report\[1\].txt");
+    assert_eq!(relative(&walker)?, ["", "sub", "sub/report[1].txt"]);
+    let walker = SourceWalker::new(&root).include("report[1].txt");
+    assert_eq!(relative(&walker)?, ["", "report1.txt"]);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "compress", unix))]
+#[test]
+fn source_walker_follows_symlinks() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("root");
+    std::fs::create_dir_all(root.join("dir"))?;
+    std::fs::write(root.join("dir/file.txt"), "content")?;
+    std::os::unix::fs::symlink("dir/file.txt", root.join("link.txt"))?;
+    // a loop back to the root is skipped
+    std::os::unix::fs::symlink("..", root.join("dir/parent"))?;
+
+    for follow_symlinks in [false, true] {
+        let walker = SourceWalker::new(&root).follow_symlinks(follow_symlinks);
+        let dest = temp_dir.path().join(format!("follow_{follow_symlinks}.7z"));
+        let mut sz = SevenZWriter::create(&dest)?;
+        sz.push_source_walker(&walker)?;
+        sz.finish()?;
+
+        let reader = SevenZReader::open(&dest, Password::empty())?;
+        let link = reader
+            .archive()
+            .files
+            .iter()
+            .find(|f| f.name() == "link.txt")
+            .expect("link entry");
+        assert_eq!(link.is_symlink(), !follow_symlinks);
+        assert_eq!(link.size(), if follow_symlinks { 7 } else { 12 });
+        let parent = reader
+            .archive()
+            .files
+            .iter()
+            .find(|f| f.name() == "dir/parent")
+            .expect("parent entry");
+        assert!(parent.is_symlink() || parent.is_directory());
+        assert!(!reader
+            .archive()
+            .files
+            .iter()
+            .any(|f| f.name().starts_with("dir/parent/dir/parent")));
+    }
+
+    Ok(())
+}